@@ -0,0 +1,291 @@
+use std::fs::File;
+use std::io::{Read, Write};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Mutex, OnceLock};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Host side of a serial line, it never blocks the simulation.
+pub trait CharDev {
+    fn write_byte(&mut self, b: u8);
+    fn read_byte(&mut self) -> Option<u8>;
+
+    /// False if `read_byte` never gives anything, e.g. an output only device.
+    fn has_input(&self) -> bool {
+        true
+    }
+}
+
+/// Open a char device from its config spec:
+/// - `stdio`: write to stdout, read from stdin.
+/// - `stdout`: write to stdout only.
+/// - `file:path`: write to a host file.
+/// - `pty`: a new pseudo terminal, its `/dev/pts/N` path is printed.
+/// - `unix:path`: a unix socket server at `path`, one client at a time.
+/// - `null`: discard everything.
+pub fn open(spec: &str) -> Box<dyn CharDev> {
+    match spec.split_once(':') {
+        #[cfg(unix)]
+        Some(("unix", path)) => match UnixDev::new(path) {
+            Ok(d) => {
+                eprintln!("chardev listen on unix socket {path}.");
+                Box::new(d)
+            },
+            Err(e) => {
+                eprintln!("chardev unix socket {path} open failed. {e}");
+                Box::new(NullDev)
+            },
+        },
+        Some(("file", path)) => match File::create(path) {
+            Ok(f) => Box::new(FileDev { file: f }),
+            Err(e) => {
+                eprintln!("chardev file {path} open failed. {e}");
+                Box::new(NullDev)
+            },
+        },
+        _ => match spec {
+            "stdio" => Box::new(StdioDev { input: true }),
+            "stdout" => Box::new(StdioDev { input: false }),
+            "null" => Box::new(NullDev),
+            #[cfg(target_os = "linux")]
+            "pty" => match PtyDev::new() {
+                Ok(d) => {
+                    eprintln!("chardev on pty {}.", d.slave());
+                    Box::new(d)
+                },
+                Err(e) => {
+                    eprintln!("chardev pty open failed. {e}");
+                    Box::new(NullDev)
+                },
+            },
+            _ => {
+                eprintln!("chardev {spec} not supported.");
+                Box::new(NullDev)
+            },
+        },
+    }
+}
+
+pub struct NullDev;
+
+impl CharDev for NullDev {
+    fn write_byte(&mut self, _b: u8) {}
+
+    fn read_byte(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn has_input(&self) -> bool {
+        false
+    }
+}
+
+pub struct StdioDev {
+    input: bool,
+}
+
+//stdin is read by one thread for the whole process, everything else takes
+//the bytes from it.
+fn stdin_rx() -> &'static Mutex<Receiver<u8>> {
+    static RX: OnceLock<Mutex<Receiver<u8>>> = OnceLock::new();
+    RX.get_or_init(|| {
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut buf = [0u8; 1];
+            while let Ok(1) = std::io::stdin().read(&mut buf) {
+                if tx.send(buf[0]).is_err() {
+                    break;
+                }
+            }
+        });
+        Mutex::new(rx)
+    })
+}
+
+/// Read stdin like `Stdin::read`: wait for a byte, then take the ones
+/// already there up to a newline. 0 at the end of stdin.
+pub fn read_stdin(buf: &mut [u8]) -> std::io::Result<usize> {
+    let rx = stdin_rx().lock().map_err(|_| std::io::Error::other("stdin reader poisoned"))?;
+    let mut n = 0;
+    while n < buf.len() {
+        let b = if n == 0 { rx.recv().ok() } else { rx.try_recv().ok() };
+        match b {
+            Some(b) => {
+                buf[n] = b;
+                n += 1;
+                if b == b'\n' {
+                    break;
+                }
+            },
+            None => break,
+        }
+    }
+    Ok(n)
+}
+
+/// Read a line of stdin like `Stdin::read_line`.
+pub fn read_stdin_line(line: &mut String) -> std::io::Result<usize> {
+    let mut bytes = Vec::new();
+    let mut b = [0u8; 1];
+    while read_stdin(&mut b)? == 1 {
+        bytes.push(b[0]);
+        if b[0] == b'\n' {
+            break;
+        }
+    }
+    line.push_str(&String::from_utf8_lossy(&bytes));
+    Ok(bytes.len())
+}
+
+impl CharDev for StdioDev {
+    fn write_byte(&mut self, b: u8) {
+        let mut out = std::io::stdout();
+        let _ = out.write_all(&[b]);
+        let _ = out.flush();
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        if !self.input {
+            return None;
+        }
+        stdin_rx().lock().ok()?.try_recv().ok()
+    }
+
+    fn has_input(&self) -> bool {
+        self.input
+    }
+}
+
+pub struct FileDev {
+    file: File,
+}
+
+impl CharDev for FileDev {
+    fn write_byte(&mut self, b: u8) {
+        let _ = self.file.write_all(&[b]);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        None
+    }
+
+    fn has_input(&self) -> bool {
+        false
+    }
+}
+
+/// Master side of a pseudo terminal, bytes are dropped while no terminal
+/// is attached to the slave.
+#[cfg(target_os = "linux")]
+pub struct PtyDev {
+    master: File,
+    slave: String,
+}
+
+#[cfg(target_os = "linux")]
+impl PtyDev {
+    pub fn new() -> std::io::Result<Self> {
+        use std::os::fd::FromRawFd;
+
+        unsafe {
+            let fd = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK);
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let master = File::from_raw_fd(fd);
+            if libc::grantpt(fd) != 0 || libc::unlockpt(fd) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            //raw mode, the line discipline must not echo the guest output back.
+            let mut tio: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(fd, &mut tio) == 0 {
+                libc::cfmakeraw(&mut tio);
+                libc::tcsetattr(fd, libc::TCSANOW, &tio);
+            }
+            let mut name = [0 as libc::c_char; 128];
+            if libc::ptsname_r(fd, name.as_mut_ptr(), name.len()) != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            let slave = std::ffi::CStr::from_ptr(name.as_ptr()).to_string_lossy().into_owned();
+            Ok(PtyDev { master, slave })
+        }
+    }
+
+    pub fn slave(&self) -> &str {
+        &self.slave
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl CharDev for PtyDev {
+    fn write_byte(&mut self, b: u8) {
+        let _ = self.master.write(&[b]);
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        let mut buf = [0u8; 1];
+        match self.master.read(&mut buf) {
+            Ok(1) => Some(buf[0]),
+            _ => None,
+        }
+    }
+}
+
+/// Unix socket server, a new client replaces the previous one.
+#[cfg(unix)]
+pub struct UnixDev {
+    listener: UnixListener,
+    client: Option<UnixStream>,
+}
+
+#[cfg(unix)]
+impl UnixDev {
+    pub fn new(path: &str) -> std::io::Result<Self> {
+        let _ = std::fs::remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(UnixDev { listener, client: None })
+    }
+
+    fn accept(&mut self) {
+        if let Ok((stream, _)) = self.listener.accept() {
+            if stream.set_nonblocking(true).is_ok() {
+                self.client = Some(stream);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl CharDev for UnixDev {
+    fn write_byte(&mut self, b: u8) {
+        self.accept();
+        if let Some(c) = self.client.as_mut() {
+            if let Err(e) = c.write_all(&[b]) {
+                if e.kind() != std::io::ErrorKind::WouldBlock {
+                    self.client = None;
+                }
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.accept();
+        let c = self.client.as_mut()?;
+        let mut buf = [0u8; 1];
+        match c.read(&mut buf) {
+            Ok(1) => Some(buf[0]),
+            //client closed
+            Ok(_) => {
+                self.client = None;
+                None
+            },
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::WouldBlock {
+                    self.client = None;
+                }
+                None
+            },
+        }
+    }
+}
@@ -1,70 +1,226 @@
+use std::collections::HashMap;
 use std::{fs::{self, File}, io::BufReader};
 use crate::{mem::Mem, rv32_actor::Rv32Actor};
-use crate::perips::Perips;
+use crate::perips::{Peripheral, Perips};
+use crate::perips::clint::Clint;
+use crate::perips::plic::Plic;
+use crate::perips::uart16550::Uart16550;
+use crate::perips::gpio::Gpio;
+use crate::perips::timer::Timer;
+use crate::perips::watchdog::Watchdog;
+use crate::perips::syscon::Syscon;
+use crate::perips::htif::Htif;
+use crate::perips::dma::Dma;
+use crate::perips::spi::Spi;
+use crate::perips::i2c::I2c;
+use crate::perips::virtio::VirtioMmio;
+use crate::perips::framebuffer::Framebuffer;
+use crate::perips::rng::Rng;
+use crate::perips::crc::Crc;
+use crate::perips::rtc::Rtc;
+use crate::perips::clic::Clic;
 use crate::rv32_actor::cpu::Rv32Cpu;
 
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize)]
-struct CCpu {
-    name: String,
-    class: String,
-    isa: String,
-    freq: f32,
-    rst_pc: u32,
+pub struct CCpu {
+    pub name: String,
+    pub class: String,
+    pub isa: String,
+    pub freq: f32,
+    pub rst_pc: u32,
+    //the index of the cpu by default.
+    #[serde(default)]
+    pub hartid: Option<u32>,
 }
 
 #[derive(Serialize, Deserialize)]
-struct CMem {
-    name: String,
-    start: u32,
-    size: u32,
+pub struct CMem {
+    pub name: String,
+    pub start: u32,
+    pub size: u32,
 }
 
+/// Config entry of a peripheral, keys other than the common ones are
+/// kept in `args` for the class factory.
 #[derive(Serialize, Deserialize)]
-struct CPerips {
-    name: String,
-    class: u32,
-    start: u32,
-    size: u32,
-    intr: u32,
+pub struct CPerips {
+    pub name: String,
+    pub class: String,
+    pub start: u32,
+    pub size: u32,
+    #[serde(default)]
+    pub intr: u32,
+    #[serde(default)]
+    pub irq: u32,
+    //MHz, 0 is the clock of the first cpu.
+    #[serde(default)]
+    pub freq: f32,
+    #[serde(flatten)]
+    pub args: serde_json::Map<String, serde_json::Value>,
+}
+
+impl CPerips {
+    pub fn arg_u64(&self, key: &str, default: u64) -> u64 {
+        self.args.get(key).and_then(|v| v.as_u64()).unwrap_or(default)
+    }
+
+    pub fn arg_f64(&self, key: &str, default: f64) -> f64 {
+        self.args.get(key).and_then(|v| v.as_f64()).unwrap_or(default)
+    }
+
+    pub fn arg_bool(&self, key: &str, default: bool) -> bool {
+        self.args.get(key).and_then(|v| v.as_bool()).unwrap_or(default)
+    }
+
+    pub fn arg_str(&self, key: &str) -> Option<&str> {
+        self.args.get(key).and_then(|v| v.as_str())
+    }
 }
 
 #[derive(Serialize, Deserialize)]
-struct CSoc {
-    name: String,
-    cpus: Vec<CCpu>,
-    mems: Vec<CMem>,
-    perips: Vec<CPerips>,
+pub struct CSoc {
+    pub name: String,
+    pub cpus: Vec<CCpu>,
+    pub mems: Vec<CMem>,
+    pub perips: Vec<CPerips>,
+    /// Serve the semihosting calls of the firmware.
+    #[serde(default)]
+    pub semihosting: bool,
+    /// Skip the ticks nothing happens in while all the harts wait in a `wfi`.
+    #[serde(default = "default_fast_forward")]
+    pub fast_forward: bool,
 }
 
-pub fn build_soc(cfg_file: String) -> Rv32Actor {
-    let soc_cfg = read_cfg(cfg_file);
-    println!("create {} soc.", soc_cfg.name);
-    let mut soc: Rv32Actor = Rv32Actor::new(soc_cfg.name);
-
-    for cfg in soc_cfg.cpus {
-        println!("add {} to soc.", cfg.name);
-        let cpu = Rv32Cpu::new(cfg.name, cfg.rst_pc, cfg.freq);
-        soc.add_cpu(cpu);
+fn default_fast_forward() -> bool {
+    true
+}
+
+impl Default for CSoc {
+    fn default() -> Self {
+        CSoc{name: "default".to_owned(),
+                cpus: vec![CCpu{name: "cpu0".to_owned(), class: "rv32".to_owned(), isa: "im".to_owned(), freq: 50.0, rst_pc: 0, hartid: None}],
+                mems: vec![CMem{name: "ram".to_owned(), start: 0, size: 8192}],
+                perips: vec![CPerips{name: "htif".to_owned(), class: "htif".to_owned(), start: 0, size: 0,
+                                intr: 0, irq: 0, freq: 0.0, args: serde_json::Map::new()}],
+                semihosting: false,
+                fast_forward: true,
+            }
     }
+}
 
-    for cfg in soc_cfg.mems {
-        let mem = Mem::new(cfg.name, cfg.start, cfg.size);
-        println!("add mem {:?} to soc.", mem);
-        soc.add_mem(mem);
+impl CSoc {
+    /// One cpu and 64MiB of ram from 0 for the linux user programs.
+    pub fn user_mode() -> Self {
+        CSoc{name: "user".to_owned(),
+                cpus: vec![CCpu{name: "cpu0".to_owned(), class: "rv32".to_owned(), isa: "im".to_owned(), freq: 100.0, rst_pc: 0, hartid: None}],
+                mems: vec![CMem{name: "ram".to_owned(), start: 0, size: 64 << 20}],
+                perips: Vec::new(),
+                semihosting: false,
+                fast_forward: true,
+            }
     }
+}
+
+pub type PeripFactory = Box<dyn Fn(&CPerips) -> Box<dyn Peripheral>>;
+
+pub struct SocBuilder {
+    classes: HashMap<String, PeripFactory>,
+}
 
-    for cfg in soc_cfg.perips {
-        let p = Perips::new(cfg.name, cfg.start, cfg.size, cfg.intr);
-        println!("add perips {:?} to soc.", p);
-        soc.add_perips(p);
+impl Default for SocBuilder {
+    fn default() -> Self {
+        Self::new()
     }
+}
+
+impl SocBuilder {
+    pub fn new() -> Self {
+        let mut builder = SocBuilder { classes: HashMap::new() };
+        builder.register_perip("regs", |cfg| {
+            Box::new(Perips::new(cfg.name.clone(), cfg.start, cfg.size, cfg.intr, cfg.irq))
+        });
+        builder.register_perip("clint", |cfg| Box::new(Clint::new(cfg)));
+        builder.register_perip("plic", |cfg| Box::new(Plic::new(cfg)));
+        builder.register_perip("uart16550", |cfg| Box::new(Uart16550::new(cfg)));
+        builder.register_perip("gpio", |cfg| Box::new(Gpio::new(cfg)));
+        builder.register_perip("timer", |cfg| Box::new(Timer::new(cfg)));
+        builder.register_perip("watchdog", |cfg| Box::new(Watchdog::new(cfg)));
+        builder.register_perip("syscon", |cfg| Box::new(Syscon::new(cfg)));
+        builder.register_perip("htif", |cfg| Box::new(Htif::new(cfg)));
+        builder.register_perip("dma", |cfg| Box::new(Dma::new(cfg)));
+        builder.register_perip("spi", |cfg| Box::new(Spi::new(cfg)));
+        builder.register_perip("i2c", |cfg| Box::new(I2c::new(cfg)));
+        builder.register_perip("virtio_mmio", |cfg| Box::new(VirtioMmio::new(cfg)));
+        builder.register_perip("framebuffer", |cfg| Box::new(Framebuffer::new(cfg)));
+        builder.register_perip("rng", |cfg| Box::new(Rng::new(cfg)));
+        builder.register_perip("crc", |cfg| Box::new(Crc::new(cfg)));
+        builder.register_perip("rtc", |cfg| Box::new(Rtc::new(cfg)));
+        builder.register_perip("clic", |cfg| Box::new(Clic::new(cfg)));
+        builder
+    }
+
+    /// Register a peripheral class, a later registration of the same class replaces it.
+    pub fn register_perip<F>(&mut self, class: &str, factory: F)
+    where
+        F: Fn(&CPerips) -> Box<dyn Peripheral> + 'static,
+    {
+        self.classes.insert(class.to_owned(), Box::new(factory));
+    }
+
+    pub fn build(&self, cfg_file: String) -> Rv32Actor {
+        self.build_cfg(read_cfg(cfg_file))
+    }
+
+    /// The build log goes to stderr, stdout is the firmware's.
+    pub fn build_cfg(&self, soc_cfg: CSoc) -> Rv32Actor {
+        eprintln!("create {} soc.", soc_cfg.name);
+        let mut soc: Rv32Actor = Rv32Actor::new(soc_cfg.name);
+        soc.set_semihosting(soc_cfg.semihosting);
+        soc.set_fast_forward(soc_cfg.fast_forward);
+
+        let cpu_freq = soc_cfg.cpus.first().map_or(0.0, |c| c.freq);
+        for cfg in soc_cfg.cpus {
+            eprintln!("add {} to soc.", cfg.name);
+            let id = cfg.hartid.unwrap_or(soc.cpu_count() as u32);
+            if soc.hart_index(id).is_some() {
+                eprintln!("mhartid {id} of {} is used twice.", cfg.name);
+            }
+            let cpu = Rv32Cpu::new(cfg.name, cfg.rst_pc, cfg.freq);
+            soc.add_cpu(cpu);
+            soc.set_hartid(soc.cpu_count() - 1, id);
+        }
+
+        for cfg in soc_cfg.mems {
+            let mem = Mem::new(cfg.name, cfg.start, cfg.size);
+            eprintln!("add mem {:?} to soc.", mem);
+            soc.add_mem(mem);
+        }
+
+        for mut cfg in soc_cfg.perips {
+            if cfg.freq == 0.0 {
+                cfg.freq = cpu_freq;
+            }
+            match self.classes.get(&cfg.class) {
+                Some(factory) => {
+                    eprintln!("add perips {}({}) {:08X}+{} to soc.", cfg.name, cfg.class, cfg.start, cfg.size);
+                    soc.add_clocked_perips(factory(&cfg), cfg.freq);
+                },
+                None => eprintln!("perips class {} of {} not found.", cfg.class, cfg.name),
+            }
+        }
 
-    soc
+        soc
+    }
 }
 
-fn read_cfg(cfg_file: String) -> CSoc {
+pub fn build_soc(cfg_file: String) -> Rv32Actor {
+    SocBuilder::new().build(cfg_file)
+}
+
+/// Parse a config file, the default soc is returned if it can not be read.
+pub fn read_cfg(cfg_file: String) -> CSoc {
 
     match fs::File::open(cfg_file) {
         Ok(f) => {
@@ -79,11 +235,7 @@ fn read_cfg(cfg_file: String) -> CSoc {
         Err(e) => println!("config file open failed. {e}"),
     };
 
-    return CSoc{name: "default".to_owned(), 
-                cpus: vec![CCpu{name: "cpu0".to_owned(), class: "rv32".to_owned(), isa: "im".to_owned(), freq: 50.0, rst_pc: 0}], 
-                mems: vec![CMem{name: "ram".to_owned(), start: 0, size: 8192}], 
-                perips: Vec::new()
-            };
+    CSoc::default()
 
     // let json_str = "{\"name\": \"cpu0\", \"freq\": 50.0}";
     // let json = serde_json::from_str(json_str);
@@ -97,6 +249,5 @@ fn read_cfg(cfg_file: String) -> CSoc {
     //     Ok(json_str) => println!("{}", json_str),
     //     Err(_) => println!("json seri failed."),
     // }
-    
-}
 
+}
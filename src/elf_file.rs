@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::fs;
+
+const PT_LOAD: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const EM_RISCV: u16 = 0xf3;
+
+/// The loadable content of a riscv elf32 file.
+pub struct ElfFile {
+    pub entry: u32,
+    /// (address, bytes) of the load segments, the bss is zero filled.
+    pub segments: Vec<(u32, Vec<u8>)>,
+    pub symbols: HashMap<String, u32>,
+}
+
+pub fn is_elf(bytes: &[u8]) -> bool {
+    bytes.starts_with(b"\x7fELF")
+}
+
+pub fn read_file(path: &str) -> Result<ElfFile, Box<dyn std::error::Error>> {
+    parse(&fs::read(path)?)
+}
+
+fn u16_at(b: &[u8], pos: usize) -> Result<u16, String> {
+    b.get(pos..pos + 2).map(|s| u16::from_le_bytes([s[0], s[1]])).ok_or("elf truncated.".to_owned())
+}
+
+fn u32_at(b: &[u8], pos: usize) -> Result<u32, String> {
+    b.get(pos..pos + 4).map(|s| u32::from_le_bytes([s[0], s[1], s[2], s[3]])).ok_or("elf truncated.".to_owned())
+}
+
+fn slice(b: &[u8], pos: u32, size: u32) -> Result<&[u8], String> {
+    b.get(pos as usize..(pos as usize + size as usize)).ok_or("elf truncated.".to_owned())
+}
+
+pub fn parse(b: &[u8]) -> Result<ElfFile, Box<dyn std::error::Error>> {
+    //elf32, little endian
+    if !is_elf(b) || b.len() < 52 || b[4] != 1 || b[5] != 1 {
+        return Err("not a little endian elf32 file.".into());
+    }
+    if u16_at(b, 18)? != EM_RISCV {
+        return Err("not a riscv elf file.".into());
+    }
+    let entry = u32_at(b, 24)?;
+    let phoff = u32_at(b, 28)? as usize;
+    let shoff = u32_at(b, 32)? as usize;
+    let phentsize = u16_at(b, 42)? as usize;
+    let phnum = u16_at(b, 44)? as usize;
+    let shentsize = u16_at(b, 46)? as usize;
+    let shnum = u16_at(b, 48)? as usize;
+
+    let mut segments = Vec::new();
+    for i in 0..phnum {
+        let ph = phoff + i * phentsize;
+        if u32_at(b, ph)? != PT_LOAD {
+            continue;
+        }
+        let offset = u32_at(b, ph + 4)?;
+        let paddr = u32_at(b, ph + 12)?;
+        let filesz = u32_at(b, ph + 16)?;
+        let memsz = u32_at(b, ph + 20)?;
+        let mut data = slice(b, offset, filesz)?.to_vec();
+        data.resize(memsz.max(filesz) as usize, 0);
+        segments.push((paddr, data));
+    }
+
+    let mut symbols = HashMap::new();
+    for i in 0..shnum {
+        let sh = shoff + i * shentsize;
+        if u32_at(b, sh + 4)? != SHT_SYMTAB {
+            continue;
+        }
+        let symtab = slice(b, u32_at(b, sh + 16)?, u32_at(b, sh + 20)?)?;
+        let strsh = shoff + u32_at(b, sh + 24)? as usize * shentsize;
+        let strtab = slice(b, u32_at(b, strsh + 16)?, u32_at(b, strsh + 20)?)?;
+        for sym in symtab.chunks_exact(16) {
+            let name = u32_at(sym, 0)? as usize;
+            let value = u32_at(sym, 4)?;
+            if let Some(s) = strtab.get(name..) {
+                let end = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+                if end > 0 {
+                    symbols.insert(String::from_utf8_lossy(&s[..end]).into_owned(), value);
+                }
+            }
+        }
+    }
+
+    Ok(ElfFile { entry, segments, symbols })
+}
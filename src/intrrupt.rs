@@ -1,4 +1,3 @@
-
 #[derive(Clone, Copy)]
 pub enum IntrType {
     None,
@@ -6,4 +5,154 @@ pub enum IntrType {
     IntrByExt(u32),
     ExceInstruction(u32),
     ExceMem(u32),
+    Ecall,
+    Breakpoint(u32),
+}
+
+impl IntrType {
+    /// mcause and mtval of a synchronous exception.
+    pub fn cause(&self) -> Option<(u32, u32)> {
+        match *self {
+            IntrType::ExceMem(pc) => Some((1, pc)),
+            IntrType::ExceInstruction(instr) => Some((2, instr)),
+            IntrType::Breakpoint(pc) => Some((3, pc)),
+            IntrType::Ecall => Some((11, 0)),
+            _ => None,
+        }
+    }
+}
+
+/// The interrupt a clic presents to its hart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClicIntr {
+    pub id: u32,
+    pub level: u8,
+    /// Selective hardware vectoring.
+    pub shv: bool,
+}
+
+/// Interrupt lines driven by the peripherals, indexed by line number, and
+/// the mip bits the interrupt controllers drive into each hart.
+#[derive(Default)]
+pub struct IntrLines {
+    lines: Vec<bool>,
+    harts: Vec<u32>,
+    //the mhartid of each hart.
+    ids: Vec<u32>,
+    clic: Vec<Option<ClicIntr>>,
+    //the clic interrupts the harts took, for the clic to clear the edge triggered ones.
+    clic_acks: Vec<Vec<u32>>,
+    //any of them changed since the last take_changed.
+    changed: bool,
+}
+
+impl IntrLines {
+    pub fn new() -> Self {
+        IntrLines { lines: Vec::new(), harts: Vec::new(), ids: Vec::new(), clic: Vec::new(), clic_acks: Vec::new(), changed: false }
+    }
+
+    pub fn set_harts(&mut self, n: usize) {
+        self.harts.resize(n, 0);
+        while self.ids.len() < n {
+            self.ids.push(self.ids.len() as u32);
+        }
+        self.ids.truncate(n);
+        self.clic.resize(n, None);
+        self.clic_acks.resize(n, Vec::new());
+    }
+
+    pub fn harts(&self) -> usize {
+        self.harts.len()
+    }
+
+    pub fn set_hart_id(&mut self, hart: usize, id: u32) {
+        if let Some(i) = self.ids.get_mut(hart) {
+            *i = id;
+        }
+    }
+
+    /// mhartid of the hart `hart`, the index of the cpu in the soc.
+    pub fn hart_id(&self, hart: usize) -> u32 {
+        self.ids.get(hart).copied().unwrap_or(hart as u32)
+    }
+
+    /// The hart with mhartid `id`.
+    pub fn hart_index(&self, id: u32) -> Option<usize> {
+        self.ids.iter().position(|&i| i == id)
+    }
+
+    pub fn set_hart_pending(&mut self, hart: usize, mask: u32, level: bool) {
+        if let Some(p) = self.harts.get_mut(hart) {
+            let old = *p;
+            if level {
+                *p |= mask;
+            } else {
+                *p &= !mask;
+            }
+            self.changed |= *p != old;
+        }
+    }
+
+    pub fn hart_pending(&self, hart: usize) -> u32 {
+        self.harts.get(hart).copied().unwrap_or(0)
+    }
+
+    pub fn set_hart_clic(&mut self, hart: usize, intr: Option<ClicIntr>) {
+        if let Some(c) = self.clic.get_mut(hart) {
+            self.changed |= *c != intr;
+            *c = intr;
+        }
+    }
+
+    pub fn hart_clic(&self, hart: usize) -> Option<ClicIntr> {
+        self.clic.get(hart).copied().flatten()
+    }
+
+    pub fn ack_clic(&mut self, hart: usize, id: u32) {
+        if let Some(a) = self.clic_acks.get_mut(hart) {
+            a.push(id);
+            self.changed = true;
+        }
+    }
+
+    pub fn take_clic_acks(&mut self, hart: usize) -> Vec<u32> {
+        self.clic_acks.get_mut(hart).map(std::mem::take).unwrap_or_default()
+    }
+
+    pub fn raise(&mut self, line: u32) {
+        let i = line as usize;
+        if i >= self.lines.len() {
+            self.lines.resize(i + 1, false);
+        }
+        self.changed |= !self.lines[i];
+        self.lines[i] = true;
+    }
+
+    pub fn lower(&mut self, line: u32) {
+        if let Some(l) = self.lines.get_mut(line as usize) {
+            self.changed |= *l;
+            *l = false;
+        }
+    }
+
+    pub fn is_raised(&self, line: u32) -> bool {
+        self.lines.get(line as usize).copied().unwrap_or(false)
+    }
+
+    pub fn any_raised(&self) -> bool {
+        self.lines.iter().any(|&l| l)
+    }
+
+    /// Whether a line, a mip bit or a clic interrupt changed since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn clear(&mut self) {
+        self.changed = true;
+        self.lines.iter_mut().for_each(|l| *l = false);
+        self.harts.iter_mut().for_each(|p| *p = 0);
+        self.clic.iter_mut().for_each(|c| *c = None);
+        self.clic_acks.iter_mut().for_each(|a| a.clear());
+    }
 }
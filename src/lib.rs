@@ -0,0 +1,9 @@
+pub mod bin_file;
+pub mod elf_file;
+pub mod mem;
+pub mod perips;
+pub mod config;
+pub mod rv32_actor;
+pub mod utils;
+pub mod intrrupt;
+pub mod chardev;
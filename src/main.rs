@@ -1,10 +1,5 @@
-mod bin_file;
-mod mem;
-mod perips;
-mod config;
-mod rv32_actor;
-mod utils;
-mod intrrupt;
+use zemulator::config;
+use zemulator::rv32_actor::{SocEvent, StopReason};
 
 fn test_isa() {
     let filenames = [
@@ -63,45 +58,47 @@ fn test_isa() {
     let mut not_complete_filename = Vec::new();
     for filename in filenames {
         println!("start read {filename}");
-        match bin_file::read_file(filename) {
-            Ok(bytes) => {
-                let mut soc = config::build_soc("".to_owned());
-                soc.fill_mem(0, bytes, 0);
+        let mut soc = config::build_soc("".to_owned());
+        //the elf next to the bin gives htif its tohost.
+        let elf = filename.trim_end_matches(".bin");
+        let loaded = if std::path::Path::new(elf).exists() {
+            soc.load_elf_file(elf).map(|_| ())
+        } else {
+            soc.load_bin_file(filename, 0)
+        };
+        if let Err(e) = loaded {
+            println!("文件读取错误, {}", e);
+            break;
+        }
 
-                let mut exit_loop = 0;
-                for _ in 0..500 {
-                    soc.tick();
+        //tests that do not write tohost end with s10 = 1 and s11 = 1 on pass.
+        let mut exit_loop = 0;
+        let passed = match soc.run_until(500, |soc| {
+            if soc.get_rs(26) == 1 {
+                exit_loop += 1;
+            }
+            exit_loop > 10
+        }) {
+            StopReason::Halt { code } => Some(code == 0),
+            StopReason::Condition => {
+                println!("loop break at {}", soc.get_tick());
+                Some(soc.get_rs(27) == 1)
+            },
+            _ => None,
+        };
 
-                    let s10 = soc.get_rs(26);
-                    if s10 == 1 {
-                        exit_loop += 1;
-                        if exit_loop > 10 {
-                            println!("loop break at {}", soc.get_tick());
-                            break;
-                        }
-                    }
-                }
-    
-                let s10 = soc.get_rs(26);
-                let s11 = soc.get_rs(27);
-                if s10 == 1 { 
-                    if s11 == 1 {
-                        println!("{filename} test Ok!!!");
-                    } else {
-                        println!("{filename} test Failed!!!");
-                        failed += 1;
-                        failed_filename.push(filename.to_string());
-                    }
-                } else {
-                    println!("{filename} test not completed!!!");
-                    not_complete += 1;
-                    not_complete_filename.push(filename.to_string());
-                }
+        match passed {
+            Some(true) => println!("{filename} test Ok!!!"),
+            Some(false) => {
+                println!("{filename} test Failed!!!");
+                failed += 1;
+                failed_filename.push(filename.to_string());
+            },
+            None => {
+                println!("{filename} test not completed!!!");
+                not_complete += 1;
+                not_complete_filename.push(filename.to_string());
             },
-            Err(e) => {
-                println!("文件读取错误, {}", e);
-                break;
-            }
         }
     }
     println!("failed {failed}: ");
@@ -115,87 +112,181 @@ fn test_isa() {
     println!("successful {}.", filenames.len() - failed - not_complete);
 }
 
-fn test_one_file(filename: &String, mut steps: i32) {
+//the process keeps 8 bits of the exit status, a nonzero halt code whose low
+//byte is 0 exits 1 so it is not read as a pass.
+fn exit_status(code: u32) -> i32 {
+    if code != 0 && code & 0xff == 0 { 1 } else { (code & 0xff) as i32 }
+}
+
+//returns the exit code the firmware halted the soc with, 0 otherwise.
+fn test_one_file(filename: &String, mut steps: i32, quiet: bool, guest_args: &[String]) -> i32 {
     println!("start read {filename}");
-    match bin_file::read_file(filename) {
-        Ok(bytes) => {
-            let mut soc = config::build_soc("rv32im.cfg".to_owned());
-            soc.fill_mem(0, bytes, 0);
+    let mut soc = config::build_soc("rv32im.cfg".to_owned());
+    soc.set_trace(!quiet);
+    let mut cmdline = vec![filename.clone()];
+    cmdline.extend_from_slice(guest_args);
+    soc.set_cmdline(&cmdline.join(" "));
+    soc.add_hook(|e| {
+        if let SocEvent::Pin { perip, pin, level } = e {
+            println!("{perip} pin {pin} -> {}.", *level as u32);
+        }
+    });
+    if let Err(e) = soc.load_file(filename, 0) {
+        println!("文件读取错误, {}", e);
+        return 1;
+    }
 
-            loop {
-                if steps >= 0 {
-                    while steps > 0 {
-                        soc.tick();
-                        steps -= 1;
-                    }
+    let mut exit_code = 0;
+    loop {
+        if steps >= 0 {
+            if steps > 0 {
+                match soc.run(steps as u32) {
+                    StopReason::Breakpoint { cpu, pc } => {
+                        let hartid = soc.cpu(cpu).map_or(0, |c| c.hartid());
+                        println!("cpu{cpu} (mhartid {hartid}) stop at breakpoint {pc:x}.");
+                    },
+                    StopReason::Halt { code } => {
+                        exit_code = exit_status(code);
+                        break;
+                    },
+                    _ => {},
+                }
+                steps = 0;
+            }
 
-                    let mut key = String::new();
-                    match std::io::stdin().read_line(&mut key) {
-                        Ok(_) => {
-                            // println!("{n} bytes read.");
-                            // println!("key = {}.", key.trim());
-                            let cmds = crate::utils::split_string(key);
-                            if cmds.len() > 0 {
-                                if cmds[0] == "q" {
-                                    break;
-                                } else if cmds[0] == "n" {
-                                    if cmds.len() > 1 {
-                                        steps = crate::utils::parse_i32_err_to_min(&cmds[1]);
-                                    } else {
-                                        steps = 1;
-                                    }
-                                } else if cmds[0] == "r" {
-                                    steps = 1;
-                                } else if cmds[0] == "i" {
-                                    println!("insert breakpoint.");
-                                    steps = 0;
-                                } else if cmds[0] == "p" {
-                                    if cmds.len() > 2 {
-                                        soc.print_d(&cmds[1], &cmds[2]);
-                                    } else {
-                                        println!("e.g. p cpu0 reg/csr.");
-                                        println!("     p mem address(hex).");
-                                        println!("     p gpio_a offset(hex).");
-                                    }
-                                    steps = 0;
-                                } else if cmds[0] == "s" {
-                                    if cmds.len() > 3 {
-                                        soc.set_v_d(&cmds[1], &cmds[2], &cmds[3]);
-                                    } else {
-                                        println!("e.g. s cpu0 index(hex, reg<32, else csr) vvv(hex).");
-                                        println!("     s mem address(hex) vvv(hex).");
-                                        println!("     s gpio_a(perips) address(hex) vvv(hex).");
-                                    }
-                                    steps = 0;
-                                } else {
-                                    println!("command can not found.");
-                                    steps = 0;
+            let mut key = String::new();
+            match zemulator::chardev::read_stdin_line(&mut key) {
+                Ok(_) => {
+                    let cmds = zemulator::utils::split_string(key);
+                    if !cmds.is_empty() {
+                        if cmds[0] == "q" {
+                            break;
+                        } else if cmds[0] == "n" {
+                            if cmds.len() > 1 {
+                                steps = zemulator::utils::parse_i32_err_to_min(&cmds[1]);
+                            } else {
+                                steps = 1;
+                            }
+                        } else if cmds[0] == "r" {
+                            steps = i32::MAX;
+                        } else if cmds[0] == "i" {
+                            if cmds.len() > 2 {
+                                let pc = zemulator::utils::parse_hex_u32_err_to_0(&cmds[1]);
+                                let cpu = cmds[2].trim_start_matches("cpu").parse::<usize>().ok().filter(|&c| c < soc.cpu_count());
+                                match cpu {
+                                    Some(cpu) => {
+                                        soc.add_cpu_breakpoint(cpu, pc);
+                                        println!("insert breakpoint {pc:x} for cpu{cpu}.");
+                                    },
+                                    None => println!("no cpu {}.", cmds[2]),
+                                }
+                            } else if cmds.len() > 1 {
+                                let pc = zemulator::utils::parse_hex_u32_err_to_0(&cmds[1]);
+                                soc.add_breakpoint(pc);
+                                println!("insert breakpoint {pc:x}.");
+                            } else {
+                                println!("e.g. i address(hex), stop any cpu.");
+                                println!("     i address(hex) cpu1, stop cpu1 only.");
+                            }
+                            steps = 0;
+                        } else if cmds[0] == "d" {
+                            if cmds.len() > 1 {
+                                soc.remove_breakpoint(zemulator::utils::parse_hex_u32_err_to_0(&cmds[1]));
+                            } else {
+                                println!("e.g. d address(hex).");
+                            }
+                            steps = 0;
+                        } else if cmds[0] == "g" {
+                            if cmds.len() > 3 {
+                                let pin = zemulator::utils::parse_i32_err_to_min(&cmds[2]) as u32;
+                                if !soc.set_pin(&cmds[1], pin, cmds[3] != "0") {
+                                    println!("{} has no pin {}.", cmds[1], cmds[2]);
+                                }
+                            } else if cmds.len() > 2 {
+                                let pin = zemulator::utils::parse_i32_err_to_min(&cmds[2]) as u32;
+                                match soc.get_pin(&cmds[1], pin) {
+                                    Some(level) => println!("{} pin {pin}: {}.", cmds[1], level as u32),
+                                    None => println!("{} has no pin {}.", cmds[1], cmds[2]),
                                 }
                             } else {
-                                println!("command can not found.");
-                                steps = 0;
+                                println!("e.g. g gpio_a pin(dec) 0/1, drive an input pin.");
+                                println!("     g gpio_a pin(dec), read a pin.");
+                            }
+                            steps = 0;
+                        } else if cmds[0] == "p" {
+                            if cmds.len() > 2 {
+                                soc.print_d(&cmds[1], &cmds[2]);
+                            } else {
+                                println!("e.g. p cpu0 reg/csr/pc.");
+                                println!("     p mem address(hex).");
+                                println!("     p gpio_a offset(hex).");
+                            }
+                            steps = 0;
+                        } else if cmds[0] == "s" {
+                            if cmds.len() > 3 {
+                                soc.set_v_d(&cmds[1], &cmds[2], &cmds[3]);
+                            } else {
+                                println!("e.g. s cpu0 index(hex, reg<32, else csr) vvv(hex).");
+                                println!("     s mem address(hex) vvv(hex).");
+                                println!("     s gpio_a(perips) address(hex) vvv(hex).");
                             }
-                        },
-                        Err(e) => {
-                            println!("input error {e}.")
-                        },
+                            steps = 0;
+                        } else {
+                            println!("command can not found.");
+                            steps = 0;
+                        }
+                    } else {
+                        println!("command can not found.");
+                        steps = 0;
                     }
-                } else {
-                    soc.tick();
-                }
+                },
+                Err(e) => {
+                    println!("input error {e}.")
+                },
             }
+        } else if let StopReason::Halt { code } = soc.run(u32::MAX) {
+            exit_code = exit_status(code);
+            break;
+        }
+    }
 
+    println!("{filename} test completed!!! tick cnt: {}, simulated {:.6} s.", soc.get_tick(), soc.sim_time());
+    exit_code
+}
 
-            println!("{filename} test completed!!! tick cnt: {}.", soc.get_tick());
-        },
-        Err(e) => {
-            println!("文件读取错误, {}", e);
+//run a linux user program, returns its exit code.
+fn run_user(args: &[String], quiet: bool) -> i32 {
+    let mut soc = config::SocBuilder::new().build_cfg(config::CSoc::user_mode());
+    soc.set_trace(!quiet);
+    let envs: Vec<String> = std::env::vars().map(|(k, v)| format!("{k}={v}")).collect();
+    if let Err(e) = soc.load_user_program(&args[0], args, &envs) {
+        println!("文件读取错误, {}", e);
+        return 1;
+    }
+    loop {
+        if let StopReason::Halt { code } = soc.run(u32::MAX) {
+            return code as i32;
         }
     }
 }
 
 fn main() {
     let args:Vec<String> = std::env::args().collect();
+    //the args after the program of -u are its own.
+    if let Some(u) = args.iter().position(|a| a == "-u") {
+        if args.len() > u + 1 {
+            std::process::exit(run_user(&args[u + 1..], args[..u].iter().any(|a| a == "-q")));
+        }
+        println!("e.g. zemulator -q -u program args.");
+        return;
+    }
+    let quiet = args.iter().any(|a| a == "-q");
+    //the args after -- go to the firmware.
+    let (args, guest_args) = match args.iter().position(|a| a == "--") {
+        Some(i) => (args[..i].to_vec(), args[i + 1..].to_vec()),
+        None => (args, Vec::new()),
+    };
+    let args:Vec<String> = args.into_iter().filter(|a| a != "-q").collect();
 
     if args.len() > 1 {
         if args[1] == "isa" {
@@ -203,15 +294,15 @@ fn main() {
         } else {
             if args.len() > 2 {
                 if args[2] =="-d" {
-                    test_one_file(&args[1], 0);
+                    std::process::exit(test_one_file(&args[1], 0, quiet, &guest_args));
                 } else {
                     match args[2].parse::<i32>() {
-                        Ok(steps) => test_one_file(&args[1], steps),
+                        Ok(steps) => std::process::exit(test_one_file(&args[1], steps, quiet, &guest_args)),
                         Err(e) => println!("arg format error. {e}"),
                     };
                 }
             } else {
-                test_one_file(&args[1], -1);
+                std::process::exit(test_one_file(&args[1], -1, quiet, &guest_args));
             }
         }
     } else {
@@ -220,6 +311,10 @@ fn main() {
         println!("2. run and stop at start: zemulator filename -d.");
         println!("3. run and stop at xxx steps: zemulator filename xxx.");
         println!("4. run with no stop: zemulator filename.");
+        println!("5. run a linux user program: zemulator -q -u program args.");
+        println!("add -q to not print the executed instructions.");
+        println!("add -- args to pass a command line to the firmware (semihosting).");
+        println!("the exit code is the one the firmware halts the soc with (e.g. syscon).");
         println!("--------------------------------");
     }
 }
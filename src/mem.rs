@@ -55,7 +55,7 @@ impl MemIO for Mem {
             }
             res.push_str(&format!("{:02X} ", self.data[pos + i]));
         }
-        return res;
+        res
     }
 }
 
@@ -78,6 +78,14 @@ impl Mem {
         }
     }
 
+    pub fn end(&self) -> u32 {
+        self.start + self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.data.fill(0);
+    }
+
     pub fn match_name(&self, name: &String) -> bool {
         self.name.eq(name)
     }
@@ -106,4 +114,43 @@ impl Mem {
         let pos = (addr - self.start) as usize;
         ((self.data[pos+1] as u16) << 8) | (self.data[pos] as u16)
     }
-}
\ No newline at end of file
+}
+
+/// Read `buf.len()` bytes from `addr` on across the mems, false if one of
+/// them is outside the mems.
+pub fn read_bytes(mems: &[Mem], addr: u32, buf: &mut [u8]) -> bool {
+    for (i, elem) in buf.iter_mut().enumerate() {
+        let a = addr.wrapping_add(i as u32);
+        match mems.iter().find(|m| m.in_range(a)) {
+            Some(m) => *elem = m.read_u8(a),
+            None => return false,
+        }
+    }
+    true
+}
+
+/// The bytes of `len` from `addr` on that are in the mems, up to the first gap.
+pub fn mapped_len(mems: &[Mem], addr: u32, len: u32) -> u32 {
+    let mut n = 0;
+    while n < len {
+        let a = addr.wrapping_add(n);
+        match mems.iter().find(|m| m.in_range(a)) {
+            Some(m) => n += (m.end().wrapping_sub(a)).min(len - n),
+            None => break,
+        }
+    }
+    n
+}
+
+/// Write `data` to `addr` on across the mems, false if a byte is outside
+/// the mems. The bytes before it are written.
+pub fn write_bytes(mems: &mut [Mem], addr: u32, data: &[u8]) -> bool {
+    for (i, &elem) in data.iter().enumerate() {
+        let a = addr.wrapping_add(i as u32);
+        match mems.iter_mut().find(|m| m.in_range(a)) {
+            Some(m) => m.write_u8(elem, a),
+            None => return false,
+        }
+    }
+    true
+}
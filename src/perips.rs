@@ -1,6 +1,259 @@
-use crate::mem::MemIO;
+pub mod clint;
+pub mod plic;
+pub mod uart16550;
+pub mod gpio;
+pub mod timer;
+pub mod watchdog;
+pub mod syscon;
+pub mod htif;
+pub mod dma;
+pub mod spi;
+pub mod spi_nor;
+pub mod i2c;
+pub mod i2c_eeprom;
+pub mod i2c_sensor;
+pub mod virtio;
+pub mod virtio_blk;
+pub mod virtio_console;
+pub mod framebuffer;
+pub mod rng;
+pub mod crc;
+pub mod rtc;
+pub mod clic;
 
+use std::collections::HashMap;
 
+use crate::intrrupt::{ClicIntr, IntrLines};
+use crate::mem::{self, Mem, MemIO};
+use crate::rv32_actor::SocEvent;
+
+/// A memory mapped device on the soc bus.
+///
+/// Implement this for a custom device and register it with
+/// `SocBuilder::register_perip` under a class name, the config then
+/// instantiates it by that class.
+pub trait Peripheral {
+    fn name(&self) -> &str;
+    fn in_range(&self, addr: u32) -> bool;
+    fn read_u32(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32;
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx);
+
+    /// Byte and half word accesses go to the aligned word by default.
+    fn read_u8(&mut self, addr: u32, ctx: &mut PeripCtx) -> u8 {
+        (self.read_u32(addr & !3, ctx) >> ((addr & 3) * 8)) as u8
+    }
+
+    fn read_u16(&mut self, addr: u32, ctx: &mut PeripCtx) -> u16 {
+        (self.read_u32(addr & !3, ctx) >> ((addr & 2) * 8)) as u16
+    }
+
+    fn write_u8(&mut self, data: u8, addr: u32, ctx: &mut PeripCtx) {
+        let shift = (addr & 3) * 8;
+        self.write_masked((data as u32) << shift, 0xff << shift, addr & !3, ctx);
+    }
+
+    fn write_u16(&mut self, data: u16, addr: u32, ctx: &mut PeripCtx) {
+        let shift = (addr & 2) * 8;
+        self.write_masked((data as u32) << shift, 0xffff << shift, addr & !3, ctx);
+    }
+
+    /// Write the bytes of `data` in `mask` to the word at `addr`, the others
+    /// keep what `merge_read` gives.
+    fn write_masked(&mut self, data: u32, mask: u32, addr: u32, ctx: &mut PeripCtx) {
+        let old = self.merge_read(addr, ctx);
+        self.write_u32((old & !mask) | (data & mask), addr, ctx);
+    }
+
+    /// The word a byte or half word write goes into, `read_u32` by default.
+    /// Override it for the registers a read changes (a fifo, a claim) or
+    /// whose bits written with 1 clear, 0 leaves them alone.
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        self.read_u32(addr, ctx)
+    }
+
+    /// Called on each edge of the perip clock, after the cpus executed. The
+    /// ctx reaches the other perips over the bus.
+    fn tick(&mut self, _ctx: &mut PeripCtx) {}
+
+    /// Ticks of the perip clock that can be skipped at once while all the
+    /// harts wait in a `wfi`, the perip changes no interrupt, pin or event before the tick
+    /// that follows them. 0 by default, `u64::MAX` if only the bus drives it.
+    fn idle_ticks(&self) -> u64 {
+        0
+    }
+
+    /// Advance by `ticks` ticks, no more than `idle_ticks`, instead of
+    /// ticking them one by one.
+    fn skip(&mut self, _ticks: u64, _ctx: &mut PeripCtx) {}
+
+    /// Called when the soc is reset.
+    fn reset(&mut self, _ctx: &mut PeripCtx) {}
+
+    /// Drive an input pin from the host, false if there is no such pin.
+    fn set_pin(&mut self, _pin: u32, _level: bool, _ctx: &mut PeripCtx) -> bool {
+        false
+    }
+
+    /// Level of a pin as seen from the host.
+    fn get_pin(&self, _pin: u32) -> Option<bool> {
+        None
+    }
+
+    /// Symbols of a loaded elf, e.g. the htif `tohost`.
+    fn set_symbols(&mut self, _symbols: &HashMap<String, u32>) {}
+
+    fn dump(&self) -> String {
+        self.name().to_owned()
+    }
+}
+
+/// Handle given to the peripheral callbacks to drive interrupt lines and
+/// to access the soc memories (dma).
+pub struct PeripCtx<'a> {
+    mems: &'a mut [Mem],
+    intr: &'a mut IntrLines,
+    events: &'a mut Vec<SocEvent>,
+    //the other perips, before and after the one the ctx is given to.
+    bus: [&'a mut [Box<dyn Peripheral>]; 2],
+}
+
+impl<'a> PeripCtx<'a> {
+    pub fn new(mems: &'a mut [Mem], intr: &'a mut IntrLines, events: &'a mut Vec<SocEvent>) -> Self {
+        PeripCtx { mems, intr, events, bus: [&mut [], &mut []] }
+    }
+
+    /// A ctx that also reaches the `before` and `after` perips over the bus.
+    pub fn with_bus(mems: &'a mut [Mem], intr: &'a mut IntrLines, events: &'a mut Vec<SocEvent>,
+                    before: &'a mut [Box<dyn Peripheral>], after: &'a mut [Box<dyn Peripheral>]) -> Self {
+        PeripCtx { mems, intr, events, bus: [before, after] }
+    }
+
+    /// Report an event to the soc hooks.
+    pub fn emit(&mut self, event: SocEvent) {
+        self.events.push(event);
+    }
+
+    pub fn raise_intr(&mut self, line: u32) {
+        self.intr.raise(line);
+    }
+
+    pub fn lower_intr(&mut self, line: u32) {
+        self.intr.lower(line);
+    }
+
+    pub fn harts(&self) -> usize {
+        self.intr.harts()
+    }
+
+    /// mhartid of the hart `hart`, the harts are the cpus in the soc order.
+    pub fn hart_id(&self, hart: usize) -> u32 {
+        self.intr.hart_id(hart)
+    }
+
+    /// The hart with mhartid `id`, the controllers address the harts by it.
+    pub fn hart_index(&self, id: u32) -> Option<usize> {
+        self.intr.hart_index(id)
+    }
+
+    /// Drive the `mask` bits of the mip of `hart`.
+    pub fn set_hart_pending(&mut self, hart: usize, mask: u32, level: bool) {
+        self.intr.set_hart_pending(hart, mask, level);
+    }
+
+    /// The mip bits the controllers drive into `hart`.
+    pub fn hart_pending(&self, hart: usize) -> u32 {
+        self.intr.hart_pending(hart)
+    }
+
+    /// Present the interrupt a clic selected for `hart`.
+    pub fn set_hart_clic(&mut self, hart: usize, intr: Option<ClicIntr>) {
+        self.intr.set_hart_clic(hart, intr);
+    }
+
+    /// The clic interrupts `hart` took since the last call.
+    pub fn take_clic_acks(&mut self, hart: usize) -> Vec<u32> {
+        self.intr.take_clic_acks(hart)
+    }
+
+    pub fn is_intr_raised(&self, line: u32) -> bool {
+        self.intr.is_raised(line)
+    }
+
+    pub fn set_intr(&mut self, line: u32, level: bool) {
+        if level {
+            self.intr.raise(line);
+        } else {
+            self.intr.lower(line);
+        }
+    }
+
+    /// Read `buf.len()` bytes from `addr`, false if any byte is outside the mems.
+    pub fn dma_read(&self, addr: u32, buf: &mut [u8]) -> bool {
+        mem::read_bytes(self.mems, addr, buf)
+    }
+
+    /// The bytes of `len` from `addr` on that are in the mems, check a guest
+    /// given length with it before allocating the buffer.
+    pub fn dma_len(&self, addr: u32, len: u32) -> u32 {
+        mem::mapped_len(self.mems, addr, len)
+    }
+
+    /// Write `data` to `addr`, false if any byte is outside the mems.
+    pub fn dma_write(&mut self, addr: u32, data: &[u8]) -> bool {
+        mem::write_bytes(self.mems, addr, data)
+    }
+
+    /// Load `size` (1, 2 or 4) bytes like a cpu does, the perips reached
+    /// over the bus take precedence over the mems. None if nothing is there.
+    pub fn bus_read(&mut self, addr: u32, size: u32) -> Option<u32> {
+        let PeripCtx { mems, intr, events, bus } = self;
+        for p in bus.iter_mut().flat_map(|b| b.iter_mut()) {
+            if p.in_range(addr) {
+                let mut ctx = PeripCtx::new(mems, intr, events);
+                return Some(match size {
+                    1 => p.read_u8(addr, &mut ctx) as u32,
+                    2 => p.read_u16(addr, &mut ctx) as u32,
+                    _ => p.read_u32(addr, &mut ctx),
+                });
+            }
+        }
+        let m = mems.iter().find(|m| m.in_range(addr) && m.in_range(addr + size - 1))?;
+        Some(match size {
+            1 => m.read_u8(addr) as u32,
+            2 => m.read_u16(addr) as u32,
+            _ => m.read_u32(addr),
+        })
+    }
+
+    /// Store like a cpu does, false if nothing is there.
+    pub fn bus_write(&mut self, addr: u32, size: u32, data: u32) -> bool {
+        let PeripCtx { mems, intr, events, bus } = self;
+        for p in bus.iter_mut().flat_map(|b| b.iter_mut()) {
+            if p.in_range(addr) {
+                let mut ctx = PeripCtx::new(mems, intr, events);
+                match size {
+                    1 => p.write_u8(data as u8, addr, &mut ctx),
+                    2 => p.write_u16(data as u16, addr, &mut ctx),
+                    _ => p.write_u32(data, addr, &mut ctx),
+                }
+                return true;
+            }
+        }
+        match mems.iter_mut().find(|m| m.in_range(addr) && m.in_range(addr + size - 1)) {
+            Some(m) => {
+                match size {
+                    1 => m.write_u8(data as u8, addr),
+                    2 => m.write_u16(data as u16, addr),
+                    _ => m.write_u32(data, addr),
+                }
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+/// Plain register file, class "regs".
 #[derive(Debug)]
 pub struct Perips {
     name: String,
@@ -10,23 +263,28 @@ pub struct Perips {
     size: u32,
 
     intr: u32,
+    irq: u32,
 }
 
-impl MemIO for Perips {
+impl Peripheral for Perips {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
     fn in_range(&self, addr: u32) -> bool {
         addr >= self.start && addr < self.start + self.size * 4
     }
 
-    fn read_u32(&self, addr: u32) -> u32 {
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
         if self.in_range(addr) {
-            return self.registers[((addr - self.start)>>2) as usize];
+            self.registers[((addr - self.start)>>2) as usize]
         } else {
             println!("{addr} is not exist in this perips.");
-            return 0;
+            0
         }
     }
 
-    fn write_u32(&mut self, data: u32, addr: u32) {
+    fn write_u32(&mut self, data: u32, addr: u32, _ctx: &mut PeripCtx) {
         if self.in_range(addr) {
             self.registers[((addr - self.start)>>2) as usize] = data;
         } else {
@@ -34,40 +292,60 @@ impl MemIO for Perips {
         }
     }
 
-    fn dump(&self, _addr: u32) -> String {
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        //bit31 of intr register pulses the irq line for one tick.
+        if self.get_intr() & 0x80000000 == 0x80000000 {
+            self.clear_intr();
+            ctx.raise_intr(self.irq);
+        } else {
+            ctx.lower_intr(self.irq);
+        }
+    }
+
+    fn reset(&mut self, _ctx: &mut PeripCtx) {
+        self.registers.iter_mut().for_each(|r| *r = 0);
+    }
+
+    fn dump(&self) -> String {
         let mut res = String::new();
         res.push_str(&format!("{}: {:08X}+{}, intr:{:08X}\n", self.name, self.start, self.size, self.intr));
         for i in self.registers.iter() {
             res.push_str(&format!("{:08X} ", i));
         }
-        return res;
+        res
     }
 }
 
 impl Perips {
 
-    pub fn new(name: String, start: u32, size: u32, intr: u32) -> Self {
+    pub fn new(name: String, start: u32, size: u32, intr: u32, irq: u32) -> Self {
         Perips {name,
-                registers: vec![0; size as usize], 
-                start, 
+                registers: vec![0; size as usize],
+                start,
                 size,
                 intr,
+                irq,
             }
     }
 
-    // pub fn name(&self) -> &String {
-    //     &self.name
-    // }
-
-    pub fn match_name(&self, name: &String) -> bool {
-        self.name.eq(name)
+    fn intr_index(&self) -> Option<usize> {
+        if self.in_range(self.intr) {
+            Some(((self.intr - self.start)>>2) as usize)
+        } else {
+            None
+        }
     }
 
     pub fn get_intr(&self) -> u32 {
-        self.registers[((self.intr - self.start)>>2) as usize]
+        match self.intr_index() {
+            Some(i) => self.registers[i],
+            None => 0,
+        }
     }
 
     pub fn clear_intr(&mut self) {
-        self.registers[((self.intr - self.start)>>2) as usize] = 0;
+        if let Some(i) = self.intr_index() {
+            self.registers[i] = 0;
+        }
     }
 }
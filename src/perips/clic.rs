@@ -0,0 +1,240 @@
+use crate::config::CPerips;
+use crate::intrrupt::ClicIntr;
+use crate::perips::{PeripCtx, Peripheral};
+
+const CLICCFG: u32 = 0x0000;
+const CLICINFO: u32 = 0x0004;
+const INT_BASE: u32 = 0x1000;
+const MAX_INTERRUPTS: u32 = 4096;
+const CLIC_SIZE: u32 = INT_BASE + 4 * MAX_INTERRUPTS;
+
+//the ids below are the mip bits of the hart (msip 3, mtip 7, meip 11), the
+//others the irq lines of the perips.
+const LOCAL_INTERRUPTS: u32 = 16;
+
+const ATTR_SHV: u8 = 0x01;
+const ATTR_EDGE: u8 = 0x02;
+const ATTR_NEG: u8 = 0x04;
+//m mode only
+const ATTR_MODE: u8 = 0xc0;
+
+/// Core local interrupt controller of a hart, class "clic".
+///
+/// `cliccfg` bits 4:1 are nlbits, the bits of `clicintctl` giving the
+/// level. The word of interrupt i at 0x1000 + 4 * i holds `clicintip`,
+/// `clicintie`, `clicintattr` (shv, trig) and `clicintctl` from the low
+/// byte up. Interrupt i < 16 is mip bit i of the hart, 16 + n the irq line
+/// n. Args: `hart` (mhartid, 0), `interrupts` (64) and `ctl_bits` (8), the
+/// implemented bits of `clicintctl`. The hart takes the clic interrupts
+/// with `mtvec.MODE` 3.
+pub struct Clic {
+    name: String,
+    start: u32,
+    hartid: u32,
+    interrupts: usize,
+    ctl_bits: u32,
+
+    cfg: u8,
+    ip: Vec<bool>,
+    ie: Vec<bool>,
+    attr: Vec<u8>,
+    ctl: Vec<u8>,
+    //the source level seen at the last update, for the edges.
+    prev: Vec<bool>,
+}
+
+impl Clic {
+    pub fn new(cfg: &CPerips) -> Self {
+        let interrupts = (cfg.arg_u64("interrupts", 64) as u32).clamp(LOCAL_INTERRUPTS, MAX_INTERRUPTS) as usize;
+        let mut clic = Clic {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            hartid: cfg.arg_u64("hart", 0) as u32,
+            interrupts,
+            ctl_bits: (cfg.arg_u64("ctl_bits", 8) as u32).min(8),
+            cfg: 0,
+            ip: vec![false; interrupts],
+            ie: vec![false; interrupts],
+            attr: vec![ATTR_MODE; interrupts],
+            ctl: vec![0; interrupts],
+            prev: vec![false; interrupts],
+        };
+        clic.restart();
+        clic
+    }
+
+    fn restart(&mut self) {
+        self.cfg = 0;
+        let ctl = self.write_ctl(0);
+        self.ip.iter_mut().for_each(|p| *p = false);
+        self.ie.iter_mut().for_each(|e| *e = false);
+        self.attr.iter_mut().for_each(|a| *a = ATTR_MODE);
+        self.ctl.iter_mut().for_each(|c| *c = ctl);
+        self.prev.iter_mut().for_each(|p| *p = false);
+    }
+
+    //the unimplemented low bits of clicintctl read as 1.
+    fn write_ctl(&self, data: u8) -> u8 {
+        data | (0xff >> self.ctl_bits) as u8
+    }
+
+    fn nlbits(&self) -> u32 {
+        (((self.cfg >> 1) & 0xf) as u32).min(8)
+    }
+
+    fn level(&self, ctl: u8) -> u8 {
+        let ones = (0xff >> self.nlbits()) as u8;
+        ctl | ones
+    }
+
+    fn source(&self, hart: usize, id: usize, ctx: &PeripCtx) -> bool {
+        let level = if (id as u32) < LOCAL_INTERRUPTS {
+            ctx.hart_pending(hart) & (1 << id) != 0
+        } else {
+            ctx.is_intr_raised(id as u32 - LOCAL_INTERRUPTS)
+        };
+        level ^ (self.attr[id] & ATTR_NEG != 0)
+    }
+
+    //the highest clicintctl wins, then the highest id.
+    fn best(&self) -> Option<ClicIntr> {
+        let mut best: Option<usize> = None;
+        for id in 0..self.interrupts {
+            if self.ip[id] && self.ie[id] && best.is_none_or(|b| self.ctl[id] >= self.ctl[b]) {
+                best = Some(id);
+            }
+        }
+        best.map(|id| ClicIntr {
+            id: id as u32,
+            level: self.level(self.ctl[id]),
+            shv: self.attr[id] & ATTR_SHV != 0,
+        })
+    }
+
+    //byte 0 clicintip, 1 clicintie, 2 clicintattr and 3 clicintctl.
+    fn write_int(&mut self, id: usize, byte: u32, data: u8) {
+        match byte {
+            //only the edge triggered ones are pending by software.
+            0 => if self.attr[id] & ATTR_EDGE != 0 {
+                self.ip[id] = data & 1 != 0;
+            },
+            1 => self.ie[id] = data & 1 != 0,
+            2 => self.attr[id] = (data & (ATTR_SHV | ATTR_EDGE | ATTR_NEG)) | ATTR_MODE,
+            _ => self.ctl[id] = self.write_ctl(data),
+        }
+    }
+
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        let hart = match ctx.hart_index(self.hartid) {
+            Some(h) => h,
+            None => return,
+        };
+        //the hart clears the edge triggered ones it took.
+        for id in ctx.take_clic_acks(hart) {
+            if let Some(a) = self.attr.get(id as usize) {
+                if a & ATTR_EDGE != 0 {
+                    self.ip[id as usize] = false;
+                }
+            }
+        }
+        for id in 0..self.interrupts {
+            let level = self.source(hart, id, ctx);
+            if self.attr[id] & ATTR_EDGE == 0 {
+                self.ip[id] = level;
+            } else if level && !self.prev[id] {
+                self.ip[id] = true;
+            }
+            self.prev[id] = level;
+        }
+        let best = self.best();
+        ctx.set_hart_clic(hart, best);
+    }
+}
+
+impl Peripheral for Clic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < CLIC_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            CLICCFG => self.cfg as u32,
+            CLICINFO => (self.ctl_bits << 21) | self.interrupts as u32,
+            o if o >= INT_BASE => {
+                let id = ((o - INT_BASE) >> 2) as usize;
+                if id < self.interrupts {
+                    (self.ip[id] as u32) | ((self.ie[id] as u32) << 8)
+                        | ((self.attr[id] as u32) << 16) | ((self.ctl[id] as u32) << 24)
+                } else {
+                    0
+                }
+            },
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CLICCFG => self.cfg = (data & 0x1e) as u8,
+            o if o >= INT_BASE => {
+                let id = ((o - INT_BASE) >> 2) as usize;
+                if id < self.interrupts {
+                    //the attr first, ip depends on the trigger.
+                    for byte in [2, 0, 1, 3] {
+                        self.write_int(id, byte, (data >> (8 * byte)) as u8);
+                    }
+                }
+            },
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    //the bytes of an interrupt are registers of their own.
+    fn write_u8(&mut self, data: u8, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CLICCFG => self.cfg = data & 0x1e,
+            o if o >= INT_BASE => {
+                let id = ((o - INT_BASE) >> 2) as usize;
+                if id < self.interrupts {
+                    self.write_int(id, o & 3, data);
+                }
+            },
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    fn write_u16(&mut self, data: u16, addr: u32, ctx: &mut PeripCtx) {
+        self.write_u8(data as u8, addr, ctx);
+        self.write_u8((data >> 8) as u8, addr + 1, ctx);
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        self.update(ctx);
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        u64::MAX
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.restart();
+        self.update(ctx);
+    }
+
+    fn dump(&self) -> String {
+        let mut res = format!("{}: {:08X}, hart:{} nlbits:{}\n", self.name, self.start, self.hartid, self.nlbits());
+        for id in 0..self.interrupts {
+            if self.ip[id] || self.ie[id] {
+                res.push_str(&format!("int{id} ip:{} ie:{} attr:{:X} ctl:{:X} level:{}\n",
+                                self.ip[id] as u8, self.ie[id] as u8, self.attr[id], self.ctl[id], self.level(self.ctl[id])));
+            }
+        }
+        res
+    }
+}
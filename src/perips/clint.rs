@@ -0,0 +1,147 @@
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+
+const MAX_HARTS: usize = 4095;
+
+const MSIP_BASE: u32 = 0x0000;
+const MTIMECMP_BASE: u32 = 0x4000;
+const MTIME: u32 = 0xbff8;
+const CLINT_SIZE: u32 = 0x10000;
+
+const MIP_MSIP: u32 = 1 << 3;
+const MIP_MTIP: u32 = 1 << 7;
+
+/// SiFive compatible core local interruptor, class "clint".
+///
+/// `mtime` counts at `timebase` Hz (args, default 10MHz) while the clint is
+/// ticked at `freq` MHz. `msip` and `mtimecmp` are indexed by mhartid.
+pub struct Clint {
+    name: String,
+    start: u32,
+
+    timebase: u64,
+    freq_hz: u64,
+    acc: u64,
+
+    mtime: u64,
+    mtimecmp: Vec<u64>,
+    msip: Vec<u32>,
+}
+
+impl Clint {
+    pub fn new(cfg: &CPerips) -> Self {
+        let timebase = cfg.arg_u64("timebase", 10_000_000).max(1);
+        let freq_hz = ((cfg.freq as f64) * 1_000_000.0) as u64;
+        Clint {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            timebase,
+            //no clock configured, mtime counts the ticks.
+            freq_hz: if freq_hz == 0 { timebase } else { freq_hz },
+            acc: 0,
+            mtime: 0,
+            mtimecmp: vec![u64::MAX; MAX_HARTS],
+            msip: vec![0; MAX_HARTS],
+        }
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    fn update_pending(&self, ctx: &mut PeripCtx) {
+        for hart in 0..ctx.harts() {
+            let id = ctx.hart_id(hart) as usize;
+            if id < MAX_HARTS {
+                ctx.set_hart_pending(hart, MIP_MSIP, self.msip[id] & 1 == 1);
+                ctx.set_hart_pending(hart, MIP_MTIP, self.mtime >= self.mtimecmp[id]);
+            }
+        }
+    }
+}
+
+impl Peripheral for Clint {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr < self.start + CLINT_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        let offset = addr - self.start;
+        match offset {
+            MTIME => self.mtime as u32,
+            o if o == MTIME + 4 => (self.mtime >> 32) as u32,
+            o if o >= MTIMECMP_BASE && o < MTIMECMP_BASE + 8 * MAX_HARTS as u32 => {
+                let cmp = self.mtimecmp[((o - MTIMECMP_BASE) >> 3) as usize];
+                if o & 4 == 0 { cmp as u32 } else { (cmp >> 32) as u32 }
+            },
+            o if o < MSIP_BASE + 4 * MAX_HARTS as u32 => self.msip[(o >> 2) as usize],
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        let offset = addr - self.start;
+        match offset {
+            MTIME => self.mtime = (self.mtime & 0xffffffff_00000000) | data as u64,
+            o if o == MTIME + 4 => self.mtime = (self.mtime & 0xffffffff) | ((data as u64) << 32),
+            o if o >= MTIMECMP_BASE && o < MTIMECMP_BASE + 8 * MAX_HARTS as u32 => {
+                let cmp = &mut self.mtimecmp[((o - MTIMECMP_BASE) >> 3) as usize];
+                if o & 4 == 0 {
+                    *cmp = (*cmp & 0xffffffff_00000000) | data as u64;
+                } else {
+                    *cmp = (*cmp & 0xffffffff) | ((data as u64) << 32);
+                }
+            },
+            o if o < MSIP_BASE + 4 * MAX_HARTS as u32 => self.msip[(o >> 2) as usize] = data & 1,
+            _ => {},
+        }
+        self.update_pending(ctx);
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        self.acc += self.timebase;
+        self.mtime += self.acc / self.freq_hz;
+        self.acc %= self.freq_hz;
+        self.update_pending(ctx);
+    }
+
+    //until mtime reaches the next mtimecmp.
+    fn idle_ticks(&self) -> u64 {
+        match self.mtimecmp.iter().filter(|&&c| c > self.mtime && c != u64::MAX).min() {
+            Some(cmp) => {
+                let need = (cmp - self.mtime) as u128 * self.freq_hz as u128 - self.acc as u128;
+                (need.div_ceil(self.timebase as u128) - 1).min(u64::MAX as u128) as u64
+            },
+            None => u64::MAX,
+        }
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        let acc = self.acc as u128 + ticks as u128 * self.timebase as u128;
+        self.mtime += (acc / self.freq_hz as u128) as u64;
+        self.acc = (acc % self.freq_hz as u128) as u64;
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.acc = 0;
+        self.mtime = 0;
+        self.mtimecmp.iter_mut().for_each(|c| *c = u64::MAX);
+        self.msip.iter_mut().for_each(|m| *m = 0);
+        self.update_pending(ctx);
+    }
+
+    fn dump(&self) -> String {
+        let mut res = String::new();
+        res.push_str(&format!("{}: {:08X}, mtime:{:016X}\n", self.name, self.start, self.mtime));
+        for (i, (cmp, msip)) in self.mtimecmp.iter().zip(self.msip.iter()).enumerate() {
+            if *cmp != u64::MAX || *msip != 0 {
+                res.push_str(&format!("hart{i} mtimecmp:{:016X} msip:{}\n", cmp, msip));
+            }
+        }
+        res
+    }
+}
@@ -0,0 +1,177 @@
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+
+const CTRL: u32 = 0x00;
+const POLY: u32 = 0x04;
+const INIT: u32 = 0x08;
+const XOROUT: u32 = 0x0c;
+const DATA: u32 = 0x10;
+const CRC_SIZE: u32 = 0x14;
+
+const CTRL_RESET: u32 = 0x01;
+const CTRL_SIZE: u32 = 0x18;
+const CTRL_REFIN: u32 = 0x20;
+const CTRL_REFOUT: u32 = 0x40;
+const CTRL_MASK: u32 = CTRL_SIZE | CTRL_REFIN | CTRL_REFOUT;
+
+/// Crc accelerator, class "crc".
+///
+/// `CTRL` bits 4:3 select a 32, 16 or 8 bits crc, bit 5 reflects the input
+/// bytes, bit 6 the result, and writing bit 0 starts over from `INIT`.
+/// Bytes, half words and words written to `DATA` are fed in memory order
+/// (little endian), reading it gives the crc xored with `XOROUT`. The args
+/// `width`, `poly`, `init`, `xorout`, `reflect_in` and `reflect_out` set
+/// the reset values, CRC-32 by default.
+pub struct Crc {
+    name: String,
+    start: u32,
+    init_regs: [u32; 4],
+
+    ctrl: u32,
+    poly: u32,
+    init: u32,
+    xorout: u32,
+    crc: u32,
+}
+
+impl Crc {
+    pub fn new(cfg: &CPerips) -> Self {
+        let size = match cfg.arg_u64("width", 32) {
+            8 => 2,
+            16 => 1,
+            _ => 0,
+        };
+        let mut ctrl = size << 3;
+        if cfg.arg_bool("reflect_in", true) {
+            ctrl |= CTRL_REFIN;
+        }
+        if cfg.arg_bool("reflect_out", true) {
+            ctrl |= CTRL_REFOUT;
+        }
+        let init_regs = [
+            ctrl,
+            cfg.arg_u64("poly", 0x04c11db7) as u32,
+            cfg.arg_u64("init", 0xffffffff) as u32,
+            cfg.arg_u64("xorout", 0xffffffff) as u32,
+        ];
+        let mut crc = Crc { name: cfg.name.clone(), start: cfg.start, init_regs, ctrl: 0, poly: 0, init: 0, xorout: 0, crc: 0 };
+        crc.restart();
+        crc
+    }
+
+    fn restart(&mut self) {
+        let [ctrl, poly, init, xorout] = self.init_regs;
+        self.ctrl = ctrl;
+        self.poly = poly;
+        self.init = init;
+        self.xorout = xorout;
+        self.crc = init & self.mask();
+    }
+
+    fn width(&self) -> u32 {
+        match (self.ctrl & CTRL_SIZE) >> 3 {
+            1 => 16,
+            2 => 8,
+            _ => 32,
+        }
+    }
+
+    fn mask(&self) -> u32 {
+        u32::MAX >> (32 - self.width())
+    }
+
+    fn feed(&mut self, byte: u8) {
+        let width = self.width();
+        let byte = if self.ctrl & CTRL_REFIN != 0 { byte.reverse_bits() } else { byte };
+        let top = 1 << (width - 1);
+        let mut crc = self.crc ^ ((byte as u32) << (width - 8));
+        for _ in 0..8 {
+            crc = if crc & top != 0 { (crc << 1) ^ self.poly } else { crc << 1 };
+        }
+        self.crc = crc & self.mask();
+    }
+
+    fn result(&self) -> u32 {
+        let crc = if self.ctrl & CTRL_REFOUT != 0 {
+            self.crc.reverse_bits() >> (32 - self.width())
+        } else {
+            self.crc
+        };
+        (crc ^ self.xorout) & self.mask()
+    }
+
+    fn write_data(&mut self, data: u32, bytes: u32) {
+        for b in data.to_le_bytes().iter().take(bytes as usize) {
+            self.feed(*b);
+        }
+    }
+}
+
+impl Peripheral for Crc {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < CRC_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            CTRL => self.ctrl,
+            POLY => self.poly,
+            INIT => self.init,
+            XOROUT => self.xorout,
+            DATA => self.result(),
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, _ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CTRL => {
+                self.ctrl = data & CTRL_MASK;
+                if data & CTRL_RESET != 0 {
+                    self.crc = self.init & self.mask();
+                }
+            },
+            POLY => self.poly = data,
+            INIT => self.init = data,
+            XOROUT => self.xorout = data,
+            DATA => self.write_data(data, 4),
+            _ => {},
+        }
+    }
+
+    fn write_u8(&mut self, data: u8, addr: u32, ctx: &mut PeripCtx) {
+        //any byte of DATA feeds the byte.
+        if (addr - self.start) & !3 == DATA {
+            self.write_data(data as u32, 1);
+        } else {
+            let shift = (addr & 3) * 8;
+            self.write_masked((data as u32) << shift, 0xff << shift, addr & !3, ctx);
+        }
+    }
+
+    fn write_u16(&mut self, data: u16, addr: u32, ctx: &mut PeripCtx) {
+        if (addr - self.start) & !3 == DATA {
+            self.write_data(data as u32, 2);
+        } else {
+            let shift = (addr & 2) * 8;
+            self.write_masked((data as u32) << shift, 0xffff << shift, addr & !3, ctx);
+        }
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        u64::MAX
+    }
+
+    fn reset(&mut self, _ctx: &mut PeripCtx) {
+        self.restart();
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: {:08X}, ctrl:{:X} poly:{:08X} init:{:08X} xorout:{:08X} crc:{:08X}",
+                self.name, self.start, self.ctrl, self.poly, self.init, self.xorout, self.crc)
+    }
+}
@@ -0,0 +1,281 @@
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+
+const INT_STATUS: u32 = 0x00;
+const INT_ENABLE: u32 = 0x04;
+const CH_BASE: u32 = 0x20;
+const CH_STRIDE: u32 = 0x20;
+
+const CH_CTRL: u32 = 0x00;
+const CH_SRC: u32 = 0x04;
+const CH_DST: u32 = 0x08;
+const CH_COUNT: u32 = 0x0c;
+const CH_NEXT: u32 = 0x10;
+const CH_STATUS: u32 = 0x14;
+
+const CTRL_EN: u32 = 0x01;
+const CTRL_SRC_INC: u32 = 0x02;
+const CTRL_DST_INC: u32 = 0x04;
+const CTRL_WIDTH: u32 = 0x30;
+const CTRL_REQ_EN: u32 = 0x80;
+const CTRL_MASK: u32 = 0xffb7;
+
+const STATUS_BUSY: u32 = 0x01;
+const STATUS_DONE: u32 = 0x02;
+const STATUS_ERROR: u32 = 0x04;
+
+#[derive(Default, Clone)]
+struct Channel {
+    ctrl: u32,
+    src: u32,
+    dst: u32,
+    count: u32,
+    next: u32,
+    status: u32,
+}
+
+impl Channel {
+    fn width(&self) -> u32 {
+        1 << ((self.ctrl & CTRL_WIDTH) >> 4).min(2)
+    }
+
+    fn start(&mut self) {
+        self.status = STATUS_BUSY;
+        if !self.count.is_multiple_of(self.width()) {
+            self.status = STATUS_ERROR;
+            self.ctrl &= !CTRL_EN;
+        }
+    }
+}
+
+/// Multi channel dma controller, class "dma".
+///
+/// Channel n has its registers at 0x20 + 0x20n: `CTRL` (bit0 enable,
+/// bit1/bit2 source/destination increment, bits 5:4 width 1/2/4 bytes,
+/// bit7 paced by the request line in bits 15:8), `SRC`, `DST`, `COUNT` in
+/// bytes, `NEXT` the address of a descriptor {ctrl, src, dst, count, next}
+/// loaded when the count runs out, and `STATUS` (busy, done, error).
+/// `INT_STATUS`/`INT_ENABLE` bit n is the end of the chain of channel n and
+/// bit 16+n its bus error, they drive `irq`. The sources and destinations
+/// are mems or perips, at most `bytes_per_tick` (args, default 4) are moved
+/// each tick.
+pub struct Dma {
+    name: String,
+    start: u32,
+    irq: u32,
+    bytes_per_tick: u32,
+
+    int_status: u32,
+    int_enable: u32,
+    channels: Vec<Channel>,
+    next_channel: usize,
+}
+
+impl Dma {
+    pub fn new(cfg: &CPerips) -> Self {
+        let channels = cfg.arg_u64("channels", 4).clamp(1, 16) as usize;
+        Dma {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            bytes_per_tick: cfg.arg_u64("bytes_per_tick", 4).max(1) as u32,
+            int_status: 0,
+            int_enable: 0,
+            channels: vec![Channel::default(); channels],
+            next_channel: 0,
+        }
+    }
+
+    fn size(&self) -> u32 {
+        CH_BASE + CH_STRIDE * self.channels.len() as u32
+    }
+
+    fn fail(&mut self, n: usize) {
+        let ch = &mut self.channels[n];
+        ch.ctrl &= !CTRL_EN;
+        ch.status = STATUS_ERROR;
+        self.int_status |= 1 << (16 + n);
+    }
+
+    //the count ran out, go on with the next descriptor or end the chain.
+    fn block_done(&mut self, n: usize, ctx: &mut PeripCtx) {
+        let next = self.channels[n].next;
+        if next == 0 {
+            let ch = &mut self.channels[n];
+            ch.ctrl &= !CTRL_EN;
+            ch.status = STATUS_DONE;
+            self.int_status |= 1 << n;
+            return;
+        }
+        let mut desc = [0u32; 5];
+        for (i, d) in desc.iter_mut().enumerate() {
+            match ctx.bus_read(next + 4 * i as u32, 4) {
+                Some(v) => *d = v,
+                None => return self.fail(n),
+            }
+        }
+        let ch = &mut self.channels[n];
+        ch.ctrl = (desc[0] & CTRL_MASK) | CTRL_EN;
+        ch.src = desc[1];
+        ch.dst = desc[2];
+        ch.count = desc[3];
+        ch.next = desc[4];
+        ch.start();
+        if ch.status == STATUS_ERROR {
+            self.int_status |= 1 << (16 + n);
+        }
+    }
+
+    //move one item of channel n, returns the bytes moved.
+    fn transfer(&mut self, n: usize, ctx: &mut PeripCtx) -> u32 {
+        let ch = &self.channels[n];
+        if ch.ctrl & CTRL_REQ_EN != 0 && !ctx.is_intr_raised((ch.ctrl >> 8) & 0xff) {
+            return 0;
+        }
+        if ch.count == 0 {
+            self.block_done(n, ctx);
+            return 0;
+        }
+        let width = ch.width();
+        let (src, dst) = (ch.src, ch.dst);
+        let data = match ctx.bus_read(src, width) {
+            Some(d) => d,
+            None => {
+                self.fail(n);
+                return 0;
+            },
+        };
+        if !ctx.bus_write(dst, width, data) {
+            self.fail(n);
+            return 0;
+        }
+
+        let ch = &mut self.channels[n];
+        if ch.ctrl & CTRL_SRC_INC != 0 {
+            ch.src = ch.src.wrapping_add(width);
+        }
+        if ch.ctrl & CTRL_DST_INC != 0 {
+            ch.dst = ch.dst.wrapping_add(width);
+        }
+        ch.count -= width;
+        if ch.count == 0 {
+            self.block_done(n, ctx);
+        }
+        width
+    }
+
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        ctx.set_intr(self.irq, self.int_status & self.int_enable != 0);
+    }
+}
+
+impl Peripheral for Dma {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < self.size()
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            INT_STATUS => self.int_status,
+            INT_ENABLE => self.int_enable,
+            o if o >= CH_BASE => {
+                let ch = &self.channels[((o - CH_BASE) / CH_STRIDE) as usize];
+                match (o - CH_BASE) % CH_STRIDE {
+                    CH_CTRL => ch.ctrl,
+                    CH_SRC => ch.src,
+                    CH_DST => ch.dst,
+                    CH_COUNT => ch.count,
+                    CH_NEXT => ch.next,
+                    CH_STATUS => ch.status,
+                    _ => 0,
+                }
+            },
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            INT_STATUS => self.int_status &= !data,
+            INT_ENABLE => self.int_enable = data,
+            o if o >= CH_BASE => {
+                let n = ((o - CH_BASE) / CH_STRIDE) as usize;
+                let ch = &mut self.channels[n];
+                match (o - CH_BASE) % CH_STRIDE {
+                    CH_CTRL => {
+                        let start = data & CTRL_EN != 0 && ch.ctrl & CTRL_EN == 0;
+                        ch.ctrl = data & CTRL_MASK;
+                        if start {
+                            ch.start();
+                            if ch.status == STATUS_ERROR {
+                                self.int_status |= 1 << (16 + n);
+                            }
+                        }
+                    },
+                    CH_SRC => ch.src = data,
+                    CH_DST => ch.dst = data,
+                    CH_COUNT => ch.count = data,
+                    CH_NEXT => ch.next = data,
+                    _ => {},
+                }
+            },
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            INT_STATUS => 0,
+            _ => self.read_u32(addr, ctx),
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        //round robin over the enabled channels until the budget is spent.
+        let mut budget = self.bytes_per_tick;
+        let channels = self.channels.len();
+        let mut idle = 0;
+        while budget > 0 && idle < channels {
+            let n = self.next_channel;
+            self.next_channel = (n + 1) % channels;
+            let moved = if self.channels[n].ctrl & CTRL_EN != 0 && self.channels[n].width() <= budget {
+                self.transfer(n, ctx)
+            } else {
+                0
+            };
+            if moved == 0 {
+                idle += 1;
+            } else {
+                idle = 0;
+                budget -= moved;
+            }
+        }
+        self.update(ctx);
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        if self.channels.iter().any(|ch| ch.ctrl & CTRL_EN != 0) { 0 } else { u64::MAX }
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.int_status = 0;
+        self.int_enable = 0;
+        self.channels.iter_mut().for_each(|c| *c = Channel::default());
+        self.next_channel = 0;
+        self.update(ctx);
+    }
+
+    fn dump(&self) -> String {
+        let mut res = format!("{}: {:08X}, int_status:{:X} int_enable:{:X}\n", self.name, self.start, self.int_status, self.int_enable);
+        for (n, ch) in self.channels.iter().enumerate() {
+            res.push_str(&format!("ch{n} ctrl:{:X} src:{:08X} dst:{:08X} count:{} next:{:08X} status:{:X}\n",
+                                    ch.ctrl, ch.src, ch.dst, ch.count, ch.next, ch.status));
+        }
+        res
+    }
+}
@@ -0,0 +1,288 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+
+const CTRL: u32 = 0x00;
+const BASE: u32 = 0x04;
+const WIDTH: u32 = 0x08;
+const HEIGHT: u32 = 0x0c;
+const FORMAT: u32 = 0x10;
+const STRIDE: u32 = 0x14;
+const FLIP: u32 = 0x18;
+const FB_SIZE: u32 = 0x1c;
+
+const CTRL_EN: u32 = 0x01;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Gray8,
+    Rgb565,
+    Rgb888,
+    Xrgb8888,
+}
+
+impl Format {
+    fn parse(s: &str) -> Option<Format> {
+        match s {
+            "gray8" => Some(Format::Gray8),
+            "rgb565" => Some(Format::Rgb565),
+            "rgb888" => Some(Format::Rgb888),
+            "xrgb8888" => Some(Format::Xrgb8888),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self) -> u32 {
+        match self {
+            Format::Gray8 => 1,
+            Format::Rgb565 => 2,
+            Format::Rgb888 => 3,
+            Format::Xrgb8888 => 4,
+        }
+    }
+
+    fn rgb(&self, p: &[u8]) -> [u8; 3] {
+        match self {
+            Format::Gray8 => [p[0]; 3],
+            Format::Rgb565 => {
+                let v = u16::from_le_bytes([p[0], p[1]]);
+                let (r, g, b) = ((v >> 11) as u8, ((v >> 5) & 0x3f) as u8, (v & 0x1f) as u8);
+                [(r << 3) | (r >> 2), (g << 2) | (g >> 4), (b << 3) | (b >> 2)]
+            },
+            Format::Rgb888 => [p[0], p[1], p[2]],
+            //a little endian 0x00rrggbb word
+            Format::Xrgb8888 => [p[2], p[1], p[0]],
+        }
+    }
+}
+
+/// Framebuffer in the mems, class "framebuffer".
+///
+/// `CTRL` bit 0 enables it, `BASE` is the address of the pixels, `STRIDE`
+/// the bytes of a line and `WIDTH`/`HEIGHT`/`FORMAT` (0 gray8, 1 rgb565,
+/// 2 rgb888, 3 xrgb8888) are read only. A write to `FLIP` dumps the frame
+/// to `{out}{n:04}.{image}`, reading it gives the frames dumped. Args:
+/// `width` (320), `height` (240), `format` ("rgb565"), `base` (0), `out`
+/// ("frame"), `image` ("ppm" or "png") and `every`, also dump every that
+/// many ticks when not 0.
+pub struct Framebuffer {
+    name: String,
+    start: u32,
+    width: u32,
+    height: u32,
+    format: Format,
+    out: String,
+    png: bool,
+    every: u64,
+    init_base: u32,
+
+    ctrl: u32,
+    base: u32,
+    stride: u32,
+    frames: u32,
+    ticks: u64,
+}
+
+impl Framebuffer {
+    pub fn new(cfg: &CPerips) -> Self {
+        let format = cfg.arg_str("format").map_or(Format::Rgb565, |f| {
+            Format::parse(f).unwrap_or_else(|| {
+                eprintln!("framebuffer format {f} not supported, use rgb565.");
+                Format::Rgb565
+            })
+        });
+        let width = cfg.arg_u64("width", 320).max(1) as u32;
+        let base = cfg.arg_u64("base", 0) as u32;
+        Framebuffer {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            width,
+            height: cfg.arg_u64("height", 240).max(1) as u32,
+            format,
+            out: cfg.arg_str("out").unwrap_or("frame").to_owned(),
+            png: cfg.arg_str("image") == Some("png"),
+            every: cfg.arg_u64("every", 0),
+            init_base: base,
+            ctrl: 0,
+            base,
+            stride: width * format.bytes(),
+            frames: 0,
+            ticks: 0,
+        }
+    }
+
+    /// The frame as rgb888 lines, None if it is outside the mems.
+    fn frame(&self, ctx: &PeripCtx) -> Option<Vec<u8>> {
+        let bytes = self.format.bytes();
+        let line_len = self.width.checked_mul(bytes)?;
+        if self.height as u64 * line_len as u64 > u32::MAX as u64 {
+            return None;
+        }
+        //every line must be in the mems before the frame is allocated.
+        for y in 0..self.height {
+            let addr = y.checked_mul(self.stride).and_then(|o| self.base.checked_add(o))?;
+            if ctx.dma_len(addr, line_len) != line_len {
+                return None;
+            }
+        }
+        let size = (self.width as usize).checked_mul(self.height as usize)?.checked_mul(3)?;
+        let mut res = Vec::with_capacity(size);
+        let mut line = vec![0u8; line_len as usize];
+        for y in 0..self.height {
+            if !ctx.dma_read(self.base + y * self.stride, &mut line) {
+                return None;
+            }
+            for p in line.chunks(bytes as usize) {
+                res.extend(self.format.rgb(p));
+            }
+        }
+        Some(res)
+    }
+
+    fn save_frame(&mut self, ctx: &PeripCtx) {
+        let rgb = match self.frame(ctx) {
+            Some(f) => f,
+            None => {
+                eprintln!("framebuffer {} at {:08X} is outside the mems.", self.name, self.base);
+                return;
+            },
+        };
+        let path = format!("{}{:04}.{}", self.out, self.frames, if self.png { "png" } else { "ppm" });
+        let res = File::create(&path).and_then(|f| {
+            let mut w = BufWriter::new(f);
+            if self.png {
+                write_png(&mut w, self.width, self.height, &rgb)
+            } else {
+                write!(w, "P6\n{} {}\n255\n", self.width, self.height).and_then(|_| w.write_all(&rgb))
+            }?;
+            w.flush()
+        });
+        match res {
+            Ok(_) => self.frames += 1,
+            Err(e) => eprintln!("framebuffer {}: can't write {path}. {e}", self.name),
+        }
+    }
+}
+
+fn crc32(data: &[u8], crc: u32) -> u32 {
+    let mut crc = !crc;
+    for b in data {
+        crc ^= *b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn png_chunk(w: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> std::io::Result<()> {
+    w.write_all(&(data.len() as u32).to_be_bytes())?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    w.write_all(&crc32(data, crc32(kind, 0)).to_be_bytes())
+}
+
+//an rgb png with the image data in stored (not compressed) deflate blocks.
+fn write_png(w: &mut impl Write, width: u32, height: u32, rgb: &[u8]) -> std::io::Result<()> {
+    w.write_all(b"\x89PNG\r\n\x1a\n")?;
+    let mut ihdr = Vec::new();
+    ihdr.extend(width.to_be_bytes());
+    ihdr.extend(height.to_be_bytes());
+    ihdr.extend([8, 2, 0, 0, 0]);
+    png_chunk(w, b"IHDR", &ihdr)?;
+
+    //each line starts with filter type 0.
+    let mut raw = Vec::with_capacity(rgb.len() + height as usize);
+    for line in rgb.chunks((width * 3) as usize) {
+        raw.push(0);
+        raw.extend(line);
+    }
+    let mut zlib = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = raw.chunks(0xffff).collect();
+    for (i, block) in blocks.iter().enumerate() {
+        zlib.push((i + 1 == blocks.len()) as u8);
+        let len = block.len() as u16;
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend(*block);
+    }
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in raw.iter() {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    zlib.extend(((b << 16) | a).to_be_bytes());
+    png_chunk(w, b"IDAT", &zlib)?;
+    png_chunk(w, b"IEND", &[])
+}
+
+impl Peripheral for Framebuffer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < FB_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            CTRL => self.ctrl,
+            BASE => self.base,
+            WIDTH => self.width,
+            HEIGHT => self.height,
+            FORMAT => self.format as u32,
+            STRIDE => self.stride,
+            FLIP => self.frames,
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CTRL => self.ctrl = data & CTRL_EN,
+            BASE => self.base = data,
+            STRIDE => self.stride = data.max(self.width * self.format.bytes()),
+            FLIP if self.ctrl & CTRL_EN != 0 => self.save_frame(ctx),
+            _ => {},
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        if self.every == 0 || self.ctrl & CTRL_EN == 0 {
+            return;
+        }
+        self.ticks += 1;
+        if self.ticks >= self.every {
+            self.ticks = 0;
+            self.save_frame(ctx);
+        }
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        if self.every == 0 || self.ctrl & CTRL_EN == 0 {
+            return u64::MAX;
+        }
+        self.every.saturating_sub(self.ticks + 1)
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        if self.every != 0 && self.ctrl & CTRL_EN != 0 {
+            self.ticks += ticks;
+        }
+    }
+
+    fn reset(&mut self, _ctx: &mut PeripCtx) {
+        self.ctrl = 0;
+        self.base = self.init_base;
+        self.stride = self.width * self.format.bytes();
+        self.ticks = 0;
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: {:08X}, ctrl:{:X} base:{:08X} {}x{} {:?} stride:{} frames:{}",
+                self.name, self.start, self.ctrl, self.base, self.width, self.height, self.format, self.stride, self.frames)
+    }
+}
@@ -0,0 +1,233 @@
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+use crate::rv32_actor::SocEvent;
+
+const DIR: u32 = 0x00;
+const IN: u32 = 0x04;
+const OUT: u32 = 0x08;
+const SET: u32 = 0x0c;
+const CLR: u32 = 0x10;
+const TGL: u32 = 0x14;
+const RISE_IE: u32 = 0x18;
+const FALL_IE: u32 = 0x1c;
+const HIGH_IE: u32 = 0x20;
+const LOW_IE: u32 = 0x24;
+const IP: u32 = 0x28;
+const GPIO_SIZE: u32 = 0x2c;
+
+/// 32 pins gpio controller, class "gpio".
+///
+/// A pin is an output when its `DIR` bit is set, `IN` reads the level of
+/// all pins. `IP` latches the enabled edges (write 1 to clear), the level
+/// interrupts stay pending while the level holds. Args: `stimulus` a file
+/// of `tick pin level` lines driving the input pins.
+pub struct Gpio {
+    name: String,
+    start: u32,
+    irq: u32,
+
+    dir: u32,
+    input: u32,
+    out: u32,
+    rise_ie: u32,
+    fall_ie: u32,
+    high_ie: u32,
+    low_ie: u32,
+    ip: u32,
+
+    ticks: u64,
+    stimulus: Vec<(u64, u32, bool)>,
+    next_stimulus: usize,
+}
+
+impl Gpio {
+    pub fn new(cfg: &CPerips) -> Self {
+        let stimulus = match cfg.arg_str("stimulus") {
+            Some(path) => read_stimulus(path),
+            None => Vec::new(),
+        };
+        Gpio {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            dir: 0,
+            input: 0,
+            out: 0,
+            rise_ie: 0,
+            fall_ie: 0,
+            high_ie: 0,
+            low_ie: 0,
+            ip: 0,
+            ticks: 0,
+            stimulus,
+            next_stimulus: 0,
+        }
+    }
+
+    fn pins(&self) -> u32 {
+        (self.dir & self.out) | (!self.dir & self.input)
+    }
+
+    //latch the edges from `old` to the current pin levels and report the outputs changes.
+    fn update(&mut self, old: u32, old_out: u32, ctx: &mut PeripCtx) {
+        let pins = self.pins();
+        self.ip |= pins & !old & self.rise_ie;
+        self.ip |= !pins & old & self.fall_ie;
+        self.ip |= (pins & self.high_ie) | (!pins & self.low_ie);
+
+        let out = self.dir & self.out;
+        let changed = out ^ old_out;
+        for pin in 0..32 {
+            if changed & (1 << pin) != 0 {
+                ctx.emit(SocEvent::Pin { perip: self.name.clone(), pin, level: out & (1 << pin) != 0 });
+            }
+        }
+
+        ctx.set_intr(self.irq, self.ip != 0);
+    }
+}
+
+fn read_stimulus(path: &str) -> Vec<(u64, u32, bool)> {
+    let mut res = Vec::new();
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            for line in text.lines() {
+                let line = line.split('#').next().unwrap_or("");
+                let items: Vec<&str> = line.split_ascii_whitespace().collect();
+                if items.is_empty() {
+                    continue;
+                }
+                match (items.len(), items[0].parse::<u64>(), items.get(1).map(|p| p.parse::<u32>())) {
+                    (3, Ok(tick), Some(Ok(pin))) if pin < 32 => res.push((tick, pin, items[2] != "0")),
+                    _ => println!("gpio stimulus line \"{line}\" ignored."),
+                }
+            }
+        },
+        Err(e) => println!("gpio stimulus {path} open failed. {e}"),
+    }
+    res.sort_by_key(|s| s.0);
+    res
+}
+
+impl Peripheral for Gpio {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < GPIO_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            DIR => self.dir,
+            IN => self.pins(),
+            OUT => self.out,
+            RISE_IE => self.rise_ie,
+            FALL_IE => self.fall_ie,
+            HIGH_IE => self.high_ie,
+            LOW_IE => self.low_ie,
+            IP => self.ip,
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        let old = self.pins();
+        let old_out = self.dir & self.out;
+        match addr - self.start {
+            DIR => self.dir = data,
+            OUT => self.out = data,
+            SET => self.out |= data,
+            CLR => self.out &= !data,
+            TGL => self.out ^= data,
+            RISE_IE => self.rise_ie = data,
+            FALL_IE => self.fall_ie = data,
+            HIGH_IE => self.high_ie = data,
+            LOW_IE => self.low_ie = data,
+            IP => self.ip &= !data,
+            _ => {},
+        }
+        self.update(old, old_out, ctx);
+    }
+
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            IP => 0,
+            _ => self.read_u32(addr, ctx),
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        let old = self.pins();
+        while let Some(&(tick, pin, level)) = self.stimulus.get(self.next_stimulus) {
+            if tick > self.ticks {
+                break;
+            }
+            if level {
+                self.input |= 1 << pin;
+            } else {
+                self.input &= !(1 << pin);
+            }
+            self.next_stimulus += 1;
+        }
+        self.ticks += 1;
+        self.update(old, self.dir & self.out, ctx);
+    }
+
+    //until the next stimulus.
+    fn idle_ticks(&self) -> u64 {
+        match self.stimulus.get(self.next_stimulus) {
+            Some(&(tick, _, _)) => tick.saturating_sub(self.ticks),
+            None => u64::MAX,
+        }
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        self.ticks += ticks;
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        let old = self.pins();
+        let old_out = self.dir & self.out;
+        self.dir = 0;
+        self.out = 0;
+        self.rise_ie = 0;
+        self.fall_ie = 0;
+        self.high_ie = 0;
+        self.low_ie = 0;
+        self.ticks = 0;
+        self.next_stimulus = 0;
+        self.update(old, old_out, ctx);
+        self.ip = 0;
+        ctx.set_intr(self.irq, false);
+    }
+
+    fn set_pin(&mut self, pin: u32, level: bool, ctx: &mut PeripCtx) -> bool {
+        if pin >= 32 {
+            return false;
+        }
+        let old = self.pins();
+        if level {
+            self.input |= 1 << pin;
+        } else {
+            self.input &= !(1 << pin);
+        }
+        self.update(old, self.dir & self.out, ctx);
+        true
+    }
+
+    fn get_pin(&self, pin: u32) -> Option<bool> {
+        if pin < 32 {
+            Some(self.pins() & (1 << pin) != 0)
+        } else {
+            None
+        }
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: {:08X}, dir:{:08X} in:{:08X} out:{:08X} rise:{:08X} fall:{:08X} high:{:08X} low:{:08X} ip:{:08X}",
+                self.name, self.start, self.dir, self.pins(), self.out, self.rise_ie, self.fall_ie,
+                self.high_ie, self.low_ie, self.ip)
+    }
+}
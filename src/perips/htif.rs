@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+
+use crate::chardev::{self, CharDev};
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+use crate::rv32_actor::SocEvent;
+
+const SYS_WRITE: u64 = 64;
+const SYS_EXIT: u64 = 93;
+
+/// Berkeley host target interface, class "htif".
+///
+/// It maps the 64 bits `tohost` and `fromhost` words over the mems, a
+/// command runs when the upper half of `tohost` is written. The addresses
+/// come from the `tohost`/`fromhost` args or else from the symbols of a
+/// loaded elf. Device 0 exits (payload bit 0 set, code in the upper bits)
+/// or runs the syscall at the payload address (write, exit), device 1
+/// writes a char. The output goes to the `chardev` arg (default "stdout").
+pub struct Htif {
+    name: String,
+    tohost: Option<u32>,
+    fromhost: Option<u32>,
+    host: Box<dyn CharDev>,
+
+    tohost_val: u64,
+    fromhost_val: u64,
+}
+
+impl Htif {
+    pub fn new(cfg: &CPerips) -> Self {
+        Htif {
+            name: cfg.name.clone(),
+            tohost: cfg.args.get("tohost").and_then(|v| v.as_u64()).map(|a| a as u32),
+            fromhost: cfg.args.get("fromhost").and_then(|v| v.as_u64()).map(|a| a as u32),
+            host: chardev::open(cfg.arg_str("chardev").unwrap_or("stdout")),
+            tohost_val: 0,
+            fromhost_val: 0,
+        }
+    }
+
+    fn read_u64(ctx: &PeripCtx, addr: u32) -> Option<u64> {
+        let mut buf = [0u8; 8];
+        if ctx.dma_read(addr, &mut buf) {
+            Some(u64::from_le_bytes(buf))
+        } else {
+            None
+        }
+    }
+
+    fn at(reg: Option<u32>, addr: u32) -> bool {
+        reg.is_some_and(|r| addr >= r && addr - r < 8)
+    }
+
+    fn exit(&mut self, code: u32, ctx: &mut PeripCtx) {
+        ctx.emit(SocEvent::Halt { perip: self.name.clone(), code });
+    }
+
+    //magic mem: which, arg0, arg1, arg2..., the return value replaces which.
+    fn syscall(&mut self, magic: u32, ctx: &mut PeripCtx) {
+        let mut args = [0u64; 4];
+        for (i, a) in args.iter_mut().enumerate() {
+            *a = Htif::read_u64(ctx, magic.wrapping_add(8 * i as u32)).unwrap_or(0);
+        }
+        let ret = match args[0] {
+            SYS_WRITE if args[1] == 1 || args[1] == 2 => {
+                //the buffer must be in the mems before it is allocated.
+                let (addr, len) = (args[2], args[3]);
+                if addr <= u32::MAX as u64 && len <= u32::MAX as u64 && ctx.dma_len(addr as u32, len as u32) as u64 == len {
+                    let mut buf = vec![0u8; len as usize];
+                    ctx.dma_read(addr as u32, &mut buf);
+                    for b in buf.iter() {
+                        self.host.write_byte(*b);
+                    }
+                    args[3]
+                } else {
+                    (-14i64) as u64//EFAULT
+                }
+            },
+            SYS_EXIT => {
+                self.exit(args[1] as u32, ctx);
+                0
+            },
+            n => {
+                println!("htif syscall {n} not supported.");
+                (-38i64) as u64//ENOSYS
+            },
+        };
+        ctx.dma_write(magic, &ret.to_le_bytes());
+    }
+
+    fn command(&mut self, ctx: &mut PeripCtx) {
+        let cmd = self.tohost_val;
+        if cmd == 0 {
+            return;
+        }
+        self.tohost_val = 0;
+
+        let device = cmd >> 56;
+        let command = (cmd >> 48) & 0xff;
+        let payload = cmd & 0xffff_ffff_ffff;
+        match (device, command) {
+            (0, 0) if payload & 1 != 0 => self.exit((payload >> 1) as u32, ctx),
+            (0, 0) => {
+                self.syscall(payload as u32, ctx);
+                self.fromhost_val = 1;
+            },
+            (1, 1) => {
+                self.host.write_byte(payload as u8);
+                self.fromhost_val = (1 << 56) | (1 << 48);
+            },
+            _ => println!("htif device {device} command {command} not supported."),
+        }
+    }
+}
+
+impl Peripheral for Htif {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        Htif::at(self.tohost, addr) || Htif::at(self.fromhost, addr)
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        let (val, base) = if Htif::at(self.tohost, addr) {
+            (self.tohost_val, self.tohost.unwrap_or(0))
+        } else {
+            (self.fromhost_val, self.fromhost.unwrap_or(0))
+        };
+        (val >> ((addr - base) * 8)) as u32
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        if Htif::at(self.tohost, addr) {
+            let base = self.tohost.unwrap_or(0);
+            if addr - base == 0 {
+                self.tohost_val = (self.tohost_val & !0xffff_ffff) | data as u64;
+            } else {
+                self.tohost_val = (self.tohost_val & 0xffff_ffff) | ((data as u64) << 32);
+                self.command(ctx);
+            }
+        } else if addr == self.fromhost.unwrap_or(0) {
+            self.fromhost_val = (self.fromhost_val & !0xffff_ffff) | data as u64;
+        } else {
+            self.fromhost_val = (self.fromhost_val & 0xffff_ffff) | ((data as u64) << 32);
+        }
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        u64::MAX
+    }
+
+    fn reset(&mut self, _ctx: &mut PeripCtx) {
+        self.tohost_val = 0;
+        self.fromhost_val = 0;
+    }
+
+    fn set_symbols(&mut self, symbols: &HashMap<String, u32>) {
+        if self.tohost.is_none() {
+            self.tohost = symbols.get("tohost").copied();
+        }
+        if self.fromhost.is_none() {
+            self.fromhost = symbols.get("fromhost").copied();
+        }
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: tohost:{:08X?} {:016X} fromhost:{:08X?} {:016X}",
+                self.name, self.tohost, self.tohost_val, self.fromhost, self.fromhost_val)
+    }
+}
@@ -0,0 +1,281 @@
+use crate::config::CPerips;
+use crate::perips::i2c_eeprom::I2cEeprom;
+use crate::perips::i2c_sensor::I2cSensor;
+use crate::perips::{PeripCtx, Peripheral};
+
+const PRER_LO: u32 = 0;
+const PRER_HI: u32 = 1;
+const CTR: u32 = 2;
+const TXR_RXR: u32 = 3;
+const CR_SR: u32 = 4;
+const REGS: u32 = 5;
+
+const CTR_EN: u8 = 0x80;
+const CTR_IEN: u8 = 0x40;
+
+const CR_STA: u8 = 0x80;
+const CR_STO: u8 = 0x40;
+const CR_RD: u8 = 0x20;
+const CR_WR: u8 = 0x10;
+const CR_ACK: u8 = 0x08;
+const CR_IACK: u8 = 0x01;
+
+const SR_RXACK: u8 = 0x80;
+const SR_BUSY: u8 = 0x40;
+const SR_TIP: u8 = 0x02;
+const SR_IF: u8 = 0x01;
+
+/// A device on the i2c bus.
+pub trait I2cSlave {
+    /// A (repeated) start addressed `addr` (7 bits), true to ack it.
+    fn start(&mut self, addr: u8, read: bool) -> bool;
+    /// A byte written by the master, true to ack it.
+    fn write(&mut self, data: u8) -> bool;
+    /// A byte read by the master, `ack` false on the last one.
+    fn read(&mut self, ack: bool) -> u8;
+    /// The stop ending the transfer of this slave.
+    fn stop(&mut self);
+    /// Called on each tick of the master.
+    fn tick(&mut self) {}
+
+    /// Advance by `ticks` ticks of the master at once.
+    fn skip(&mut self, _ticks: u64) {}
+}
+
+/// Create an i2c slave from its config, e.g. {"class": "24cxx", "addr": 80, ...}.
+/// Classes: `24cxx` (see `I2cEeprom`) and `sensor` (see `I2cSensor`).
+pub fn open_slave(cfg: &serde_json::Value) -> Option<Box<dyn I2cSlave>> {
+    match cfg.get("class").and_then(|c| c.as_str()) {
+        Some("24cxx") => Some(Box::new(I2cEeprom::new(cfg))),
+        Some("sensor") => Some(Box::new(I2cSensor::new(cfg))),
+        class => {
+            println!("i2c slave class {class:?} not found.");
+            None
+        },
+    }
+}
+
+/// I2c master with the OpenCores i2c register set, class "i2c".
+///
+/// The byte registers PRERlo, PRERhi, CTR, TXR/RXR and CR/SR are
+/// 1 << `reg_shift` (args, default 2) bytes apart. A byte takes
+/// 9 * 5 * (prescale + 1) ticks, then SR.IF is set and drives `irq` when
+/// CTR.IEN is. Args: `slaves` a list of slave configs, the first to ack an
+/// address gets the transfer.
+pub struct I2c {
+    name: String,
+    start: u32,
+    irq: u32,
+    reg_shift: u32,
+
+    slaves: Vec<Box<dyn I2cSlave>>,
+
+    prer: u16,
+    ctr: u8,
+    txr: u8,
+    rxr: u8,
+    cr: u8,
+    sr: u8,
+    //the slave which acked the last address
+    target: Option<usize>,
+    busy_cnt: u32,
+}
+
+impl I2c {
+    pub fn new(cfg: &CPerips) -> Self {
+        let mut slaves = Vec::new();
+        if let Some(list) = cfg.args.get("slaves").and_then(|s| s.as_array()) {
+            slaves.extend(list.iter().filter_map(open_slave));
+        }
+        Self::with_slaves(cfg, slaves)
+    }
+
+    /// A master with custom slaves.
+    pub fn with_slaves(cfg: &CPerips, slaves: Vec<Box<dyn I2cSlave>>) -> Self {
+        I2c {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            reg_shift: cfg.arg_u64("reg_shift", 2).min(2) as u32,
+            slaves,
+            prer: 0xffff,
+            ctr: 0,
+            txr: 0,
+            rxr: 0,
+            cr: 0,
+            sr: 0,
+            target: None,
+            busy_cnt: 0,
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(t) = self.target.take() {
+            self.slaves[t].stop();
+        }
+        self.sr &= !SR_BUSY;
+    }
+
+    //the byte of the command in cr is on the bus.
+    fn transfer(&mut self) {
+        let cr = self.cr;
+        if cr & CR_STA != 0 {
+            self.sr |= SR_BUSY;
+        }
+        let mut ack = false;
+        if cr & CR_WR != 0 {
+            if cr & CR_STA != 0 {
+                let (addr, read) = (self.txr >> 1, self.txr & 1 != 0);
+                if let Some(t) = self.target.take() {
+                    self.slaves[t].stop();
+                }
+                self.target = self.slaves.iter_mut().position(|s| s.start(addr, read));
+                ack = self.target.is_some();
+            } else if let Some(t) = self.target {
+                ack = self.slaves[t].write(self.txr);
+            }
+        } else if cr & CR_RD != 0 {
+            //the master acks with CR.ACK cleared.
+            self.rxr = match self.target {
+                Some(t) => self.slaves[t].read(cr & CR_ACK == 0),
+                None => 0xff,
+            };
+            ack = cr & CR_ACK == 0;
+        }
+        if ack {
+            self.sr &= !SR_RXACK;
+        } else {
+            self.sr |= SR_RXACK;
+        }
+        if cr & CR_STO != 0 {
+            self.stop();
+        }
+        self.cr = 0;
+        self.sr = (self.sr & !SR_TIP) | SR_IF;
+    }
+
+    fn read_reg(&mut self, reg: u32) -> u8 {
+        match reg {
+            PRER_LO => self.prer as u8,
+            PRER_HI => (self.prer >> 8) as u8,
+            CTR => self.ctr,
+            TXR_RXR => self.rxr,
+            CR_SR => self.sr,
+            _ => 0,
+        }
+    }
+
+    fn write_reg(&mut self, reg: u32, data: u8) {
+        match reg {
+            //the prescaler is only changed with the core disabled.
+            PRER_LO if self.ctr & CTR_EN == 0 => self.prer = (self.prer & 0xff00) | data as u16,
+            PRER_HI if self.ctr & CTR_EN == 0 => self.prer = (self.prer & 0xff) | ((data as u16) << 8),
+            CTR => self.ctr = data & (CTR_EN | CTR_IEN),
+            TXR_RXR => self.txr = data,
+            CR_SR if self.ctr & CTR_EN != 0 => {
+                if data & CR_IACK != 0 {
+                    self.sr &= !SR_IF;
+                }
+                let cmd = data & (CR_STA | CR_STO | CR_RD | CR_WR | CR_ACK);
+                if cmd & (CR_RD | CR_WR) != 0 {
+                    self.cr = cmd;
+                    self.sr |= SR_TIP;
+                    self.busy_cnt = 9 * 5 * (self.prer as u32 + 1);
+                } else if cmd & CR_STO != 0 {
+                    self.stop();
+                }
+            },
+            _ => {},
+        }
+    }
+
+    fn reg(&self, addr: u32) -> u32 {
+        (addr - self.start) >> self.reg_shift
+    }
+
+    //a store wider than the register stride writes each register it covers.
+    fn write_lanes(&mut self, data: u32, bytes: u32, addr: u32) {
+        let step = 1 << self.reg_shift;
+        for i in 0..bytes {
+            if (addr - self.start + i).is_multiple_of(step) {
+                self.write_reg(self.reg(addr + i), (data >> (8 * i)) as u8);
+            }
+        }
+    }
+
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        ctx.set_intr(self.irq, self.ctr & CTR_IEN != 0 && self.sr & SR_IF != 0);
+    }
+}
+
+impl Peripheral for I2c {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < REGS << self.reg_shift
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        self.read_reg(self.reg(addr)) as u32
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        self.write_lanes(data, 4, addr);
+        self.update(ctx);
+    }
+
+    fn read_u8(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u8 {
+        self.read_reg(self.reg(addr))
+    }
+
+    fn write_u8(&mut self, data: u8, addr: u32, ctx: &mut PeripCtx) {
+        self.write_reg(self.reg(addr), data);
+        self.update(ctx);
+    }
+
+    fn write_u16(&mut self, data: u16, addr: u32, ctx: &mut PeripCtx) {
+        self.write_lanes(data as u32, 2, addr);
+        self.update(ctx);
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        self.slaves.iter_mut().for_each(|s| s.tick());
+        if self.sr & SR_TIP != 0 {
+            self.busy_cnt = self.busy_cnt.saturating_sub(1);
+            if self.busy_cnt == 0 {
+                self.transfer();
+            }
+        }
+        self.update(ctx);
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        if self.sr & SR_TIP != 0 { self.busy_cnt.saturating_sub(1) as u64 } else { u64::MAX }
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        self.slaves.iter_mut().for_each(|s| s.skip(ticks));
+        if self.sr & SR_TIP != 0 {
+            self.busy_cnt -= ticks as u32;
+        }
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.stop();
+        self.prer = 0xffff;
+        self.ctr = 0;
+        self.txr = 0;
+        self.rxr = 0;
+        self.cr = 0;
+        self.sr = 0;
+        self.busy_cnt = 0;
+        self.update(ctx);
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: {:08X}, prer:{:04X} ctr:{:02X} txr:{:02X} rxr:{:02X} sr:{:02X} target:{:?}",
+                self.name, self.start, self.prer, self.ctr, self.txr, self.rxr, self.sr, self.target)
+    }
+}
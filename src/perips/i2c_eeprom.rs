@@ -0,0 +1,147 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::perips::i2c::I2cSlave;
+
+/// 24Cxx i2c eeprom slave, class "24cxx".
+///
+/// Writes start with the word address (2 bytes above 2KiB, else 1 byte
+/// and the upper bits in the low bits of the device address) followed by
+/// the data, buffered in a page and programmed at the stop. Reads go on
+/// from the current address. Args: `addr` (default 0x50), `size` (default
+/// 256), `page` (default 8) and `file`, a host file holding the contents,
+/// created if missing and written back on each page write.
+pub struct I2cEeprom {
+    addr: u8,
+    data: Vec<u8>,
+    page_size: u32,
+    addr_bytes: u32,
+    file: Option<File>,
+
+    ptr: u32,
+    //bytes written since the start, the address ones included
+    pos: u32,
+    page: Vec<(u32, u8)>,
+}
+
+impl I2cEeprom {
+    pub fn new(cfg: &serde_json::Value) -> Self {
+        let size = cfg.get("size").and_then(|s| s.as_u64()).unwrap_or(256).max(1) as usize;
+        let mut data = vec![0xff; size];
+        let file = cfg.get("file").and_then(|f| f.as_str()).and_then(|path| {
+            let mut f = match OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path) {
+                Ok(f) => f,
+                Err(e) => {
+                    println!("24cxx: can't open {path}: {e}.");
+                    return None;
+                },
+            };
+            let mut buf = Vec::new();
+            if let Err(e) = f.read_to_end(&mut buf) {
+                println!("24cxx: can't read {path}: {e}.");
+            }
+            let n = buf.len().min(size);
+            data[..n].copy_from_slice(&buf[..n]);
+            Some((f, n))
+        });
+        let (file, loaded) = match file {
+            Some((f, n)) => (Some(f), n),
+            None => (None, size),
+        };
+        let mut dev = I2cEeprom {
+            addr: cfg.get("addr").and_then(|a| a.as_u64()).unwrap_or(0x50) as u8,
+            data,
+            page_size: cfg.get("page").and_then(|p| p.as_u64()).unwrap_or(8).max(1) as u32,
+            addr_bytes: if size > 2048 { 2 } else { 1 },
+            file,
+            ptr: 0,
+            pos: 0,
+            page: Vec::new(),
+        };
+        //a new or short file gets the erased tail.
+        if loaded < size {
+            dev.persist(loaded, size);
+        }
+        dev
+    }
+
+    fn size(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    //device address bits used as word address bits by the small parts.
+    fn block_mask(&self) -> u8 {
+        if self.addr_bytes == 1 {
+            ((self.size().max(256) / 256 - 1) & 7) as u8
+        } else {
+            0
+        }
+    }
+
+    fn persist(&mut self, start: usize, end: usize) {
+        if let Some(f) = self.file.as_mut() {
+            let res = f.seek(SeekFrom::Start(start as u64)).and_then(|_| f.write_all(&self.data[start..end]));
+            if let Err(e) = res {
+                println!("24cxx: can't write the file: {e}.");
+            }
+        }
+    }
+}
+
+impl I2cSlave for I2cEeprom {
+    fn start(&mut self, addr: u8, _read: bool) -> bool {
+        let mask = self.block_mask();
+        if addr & !mask != self.addr & !mask {
+            return false;
+        }
+        if self.addr_bytes == 1 {
+            self.ptr = (((addr & mask) as u32) << 8) | (self.ptr & 0xff);
+        }
+        self.pos = 0;
+        self.page.clear();
+        true
+    }
+
+    fn write(&mut self, data: u8) -> bool {
+        if self.pos < self.addr_bytes {
+            self.ptr = if self.addr_bytes == 2 && self.pos == 0 {
+                (data as u32) << 8
+            } else {
+                (self.ptr & !0xff) | data as u32
+            };
+            self.ptr %= self.size();
+        } else {
+            //the address wraps inside the page, the last write wins.
+            let page = self.ptr - self.ptr % self.page_size;
+            let a = page + (self.ptr + self.pos - self.addr_bytes) % self.page_size;
+            self.page.retain(|(p, _)| *p != a);
+            self.page.push((a, data));
+        }
+        self.pos += 1;
+        true
+    }
+
+    fn read(&mut self, _ack: bool) -> u8 {
+        let b = self.data[self.ptr as usize];
+        self.ptr = (self.ptr + 1) % self.size();
+        b
+    }
+
+    fn stop(&mut self) {
+        if self.page.is_empty() {
+            return;
+        }
+        let page = std::mem::take(&mut self.page);
+        for (a, b) in page.iter() {
+            if let Some(d) = self.data.get_mut(*a as usize) {
+                *d = *b;
+            }
+        }
+        let start = self.ptr - self.ptr % self.page_size;
+        let end = (start + self.page_size).min(self.size());
+        self.persist(start as usize, end as usize);
+        //the next current address read goes on after the last byte.
+        let written = self.pos - self.addr_bytes;
+        self.ptr = (start + (self.ptr + written) % self.page_size) % self.size();
+    }
+}
@@ -0,0 +1,100 @@
+use crate::perips::i2c::I2cSlave;
+
+/// Register map i2c sensor slave, class "sensor".
+///
+/// 256 byte registers, the first byte written after the address is the
+/// register pointer, the next bytes are written from there and reads go on
+/// from there, the pointer increments after each byte. Args: `addr`
+/// (default 0x48), `regs` the initial values as {"reg": value}, and `script`
+/// a list of {"tick", "reg", "value"} applied when the ticks of the master
+/// reach `tick`, e.g. a temperature going up.
+pub struct I2cSensor {
+    addr: u8,
+    regs: [u8; 256],
+    script: Vec<(u64, u8, u8)>,
+
+    ticks: u64,
+    next_step: usize,
+    ptr: u8,
+    pos: u32,
+}
+
+impl I2cSensor {
+    pub fn new(cfg: &serde_json::Value) -> Self {
+        let mut init = [0u8; 256];
+        if let Some(regs) = cfg.get("regs").and_then(|r| r.as_object()) {
+            for (name, val) in regs.iter() {
+                let reg = match name.strip_prefix("0x") {
+                    Some(hex) => u8::from_str_radix(hex, 16),
+                    None => name.parse::<u8>(),
+                };
+                match (reg, val.as_u64()) {
+                    (Ok(r), Some(v)) => init[r as usize] = v as u8,
+                    _ => println!("sensor: bad register {name} = {val}."),
+                }
+            }
+        }
+        let mut script: Vec<(u64, u8, u8)> = cfg.get("script").and_then(|s| s.as_array()).map_or(Vec::new(), |steps| {
+            steps.iter().map(|s| {
+                let field = |k: &str| s.get(k).and_then(|v| v.as_u64()).unwrap_or(0);
+                (field("tick"), field("reg") as u8, field("value") as u8)
+            }).collect()
+        });
+        script.sort_by_key(|s| s.0);
+        I2cSensor {
+            addr: cfg.get("addr").and_then(|a| a.as_u64()).unwrap_or(0x48) as u8,
+            regs: init,
+            script,
+            ticks: 0,
+            next_step: 0,
+            ptr: 0,
+            pos: 0,
+        }
+    }
+
+    /// Set a register from the host.
+    pub fn set_reg(&mut self, reg: u8, value: u8) {
+        self.regs[reg as usize] = value;
+    }
+}
+
+impl I2cSlave for I2cSensor {
+    fn start(&mut self, addr: u8, _read: bool) -> bool {
+        self.pos = 0;
+        addr == self.addr
+    }
+
+    fn write(&mut self, data: u8) -> bool {
+        if self.pos == 0 {
+            self.ptr = data;
+        } else {
+            self.regs[self.ptr as usize] = data;
+            self.ptr = self.ptr.wrapping_add(1);
+        }
+        self.pos += 1;
+        true
+    }
+
+    fn read(&mut self, _ack: bool) -> u8 {
+        let b = self.regs[self.ptr as usize];
+        self.ptr = self.ptr.wrapping_add(1);
+        b
+    }
+
+    fn stop(&mut self) {}
+
+    fn tick(&mut self) {
+        self.skip(1);
+    }
+
+    fn skip(&mut self, ticks: u64) {
+        self.ticks += ticks;
+        while let Some(&(tick, reg, value)) = self.script.get(self.next_step) {
+            if tick > self.ticks {
+                break;
+            }
+            self.regs[reg as usize] = value;
+            self.next_step += 1;
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+
+const PRIORITY_BASE: u32 = 0x000000;
+const PENDING_BASE: u32 = 0x001000;
+const ENABLE_BASE: u32 = 0x002000;
+const ENABLE_STRIDE: u32 = 0x80;
+const CONTEXT_BASE: u32 = 0x200000;
+const CONTEXT_STRIDE: u32 = 0x1000;
+const PLIC_SIZE: u32 = 0x4000000;
+
+const MIP_SEIP: u32 = 1 << 9;
+const MIP_MEIP: u32 = 1 << 11;
+
+/// Platform level interrupt controller, class "plic".
+///
+/// Source n is the irq line n of the perips, source 0 does not exist.
+/// Context 2*mhartid targets the m mode (meip) of a hart and 2*mhartid+1
+/// its s mode (seip).
+pub struct Plic {
+    name: String,
+    start: u32,
+    sources: usize,
+
+    priority: Vec<u32>,
+    pending: Vec<bool>,
+    claimed: Vec<bool>,
+
+    enable: Vec<Vec<u32>>,
+    threshold: Vec<u32>,
+}
+
+impl Plic {
+    pub fn new(cfg: &CPerips) -> Self {
+        let sources = (cfg.arg_u64("sources", 32) as usize).clamp(1, 1023) + 1;
+        Plic {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            sources,
+            priority: vec![0; sources],
+            pending: vec![false; sources],
+            claimed: vec![false; sources],
+            enable: Vec::new(),
+            threshold: Vec::new(),
+        }
+    }
+
+    fn contexts(&mut self, ctx: &PeripCtx) -> usize {
+        let n = (0..ctx.harts()).map(|h| ctx.hart_id(h) as usize + 1).max().unwrap_or(0) * 2;
+        if self.enable.len() < n {
+            self.enable.resize(n, vec![0; self.sources.div_ceil(32)]);
+            self.threshold.resize(n, 0);
+        }
+        n
+    }
+
+    fn enabled(&self, context: usize, src: usize) -> bool {
+        self.enable[context][src / 32] & (1 << (src % 32)) != 0
+    }
+
+    //highest priority pending source above the threshold, the lowest id wins a tie.
+    fn best(&self, context: usize) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for src in 1..self.sources {
+            if self.pending[src] && self.enabled(context, src) && self.priority[src] > self.threshold[context]
+                && best.is_none_or(|b| self.priority[src] > self.priority[b]) {
+                best = Some(src);
+            }
+        }
+        best
+    }
+
+    fn claim(&mut self, context: usize) -> u32 {
+        match self.best(context) {
+            Some(src) => {
+                self.pending[src] = false;
+                self.claimed[src] = true;
+                src as u32
+            },
+            None => 0,
+        }
+    }
+
+    fn complete(&mut self, context: usize, src: u32) {
+        let src = src as usize;
+        if src > 0 && src < self.sources && self.enabled(context, src) {
+            self.claimed[src] = false;
+        }
+    }
+
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        let contexts = self.contexts(ctx);
+        //level gateway, a source is pending again after it was completed.
+        for src in 1..self.sources {
+            if !self.claimed[src] && ctx.is_intr_raised(src as u32) {
+                self.pending[src] = true;
+            }
+        }
+        for context in 0..contexts {
+            if let Some(hart) = ctx.hart_index((context / 2) as u32) {
+                let mask = if context % 2 == 0 { MIP_MEIP } else { MIP_SEIP };
+                let level = self.best(context).is_some();
+                ctx.set_hart_pending(hart, mask, level);
+            }
+        }
+    }
+}
+
+impl Peripheral for Plic {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < PLIC_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        let contexts = self.contexts(ctx) as u32;
+        let offset = addr - self.start;
+        let res = match offset {
+            o if o < PENDING_BASE => {
+                self.priority.get(((o - PRIORITY_BASE) >> 2) as usize).copied().unwrap_or(0)
+            },
+            o if o < ENABLE_BASE => {
+                let word = ((o - PENDING_BASE) >> 2) as usize;
+                (0..32).filter(|b| self.pending.get(word * 32 + b).copied().unwrap_or(false))
+                       .fold(0, |acc, b| acc | (1 << b))
+            },
+            o if o < ENABLE_BASE + ENABLE_STRIDE * contexts => {
+                let context = ((o - ENABLE_BASE) / ENABLE_STRIDE) as usize;
+                let word = (((o - ENABLE_BASE) % ENABLE_STRIDE) >> 2) as usize;
+                self.enable[context].get(word).copied().unwrap_or(0)
+            },
+            o if o >= CONTEXT_BASE && o < CONTEXT_BASE + CONTEXT_STRIDE * contexts => {
+                let context = ((o - CONTEXT_BASE) / CONTEXT_STRIDE) as usize;
+                match (o - CONTEXT_BASE) % CONTEXT_STRIDE {
+                    0 => self.threshold[context],
+                    4 => self.claim(context),
+                    _ => 0,
+                }
+            },
+            _ => 0,
+        };
+        self.update(ctx);
+        res
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        let contexts = self.contexts(ctx) as u32;
+        let offset = addr - self.start;
+        match offset {
+            o if o < PENDING_BASE => {
+                if let Some(p) = self.priority.get_mut(((o - PRIORITY_BASE) >> 2) as usize) {
+                    *p = data & 0x07;
+                }
+            },
+            o if o < ENABLE_BASE => {},
+            o if o < ENABLE_BASE + ENABLE_STRIDE * contexts => {
+                let context = ((o - ENABLE_BASE) / ENABLE_STRIDE) as usize;
+                let word = (((o - ENABLE_BASE) % ENABLE_STRIDE) >> 2) as usize;
+                if let Some(e) = self.enable[context].get_mut(word) {
+                    //source 0 is hardwired to 0.
+                    *e = if word == 0 { data & !1 } else { data };
+                }
+            },
+            o if o >= CONTEXT_BASE && o < CONTEXT_BASE + CONTEXT_STRIDE * contexts => {
+                let context = ((o - CONTEXT_BASE) / CONTEXT_STRIDE) as usize;
+                match (o - CONTEXT_BASE) % CONTEXT_STRIDE {
+                    0 => self.threshold[context] = data & 0x07,
+                    4 => self.complete(context, data),
+                    _ => {},
+                }
+            },
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    //the claim register claims on a read.
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        let o = addr - self.start;
+        if o >= CONTEXT_BASE && (o - CONTEXT_BASE) % CONTEXT_STRIDE == 4 {
+            0
+        } else {
+            self.read_u32(addr, ctx)
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        self.update(ctx);
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        u64::MAX
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.priority.iter_mut().for_each(|p| *p = 0);
+        self.pending.iter_mut().for_each(|p| *p = false);
+        self.claimed.iter_mut().for_each(|c| *c = false);
+        self.enable.iter_mut().for_each(|e| e.iter_mut().for_each(|w| *w = 0));
+        self.threshold.iter_mut().for_each(|t| *t = 0);
+        self.update(ctx);
+    }
+
+    fn dump(&self) -> String {
+        let mut res = String::new();
+        res.push_str(&format!("{}: {:08X}, sources:{}\n", self.name, self.start, self.sources - 1));
+        for src in 1..self.sources {
+            if self.priority[src] != 0 || self.pending[src] || self.claimed[src] {
+                res.push_str(&format!("src{src} priority:{} pending:{} claimed:{}\n",
+                                self.priority[src], self.pending[src], self.claimed[src]));
+            }
+        }
+        for (context, (en, th)) in self.enable.iter().zip(self.threshold.iter()).enumerate() {
+            res.push_str(&format!("context{context} threshold:{th} enable:"));
+            for w in en.iter() {
+                res.push_str(&format!("{:08X} ", w));
+            }
+            res.push('\n');
+        }
+        res
+    }
+}
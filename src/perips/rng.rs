@@ -0,0 +1,139 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+
+const CTRL: u32 = 0x0;
+const STATUS: u32 = 0x4;
+const DATA: u32 = 0x8;
+const SEED: u32 = 0xc;
+const RNG_SIZE: u32 = 0x10;
+
+const CTRL_EN: u32 = 0x1;
+const CTRL_IE: u32 = 0x2;
+
+const STATUS_READY: u32 = 0x1;
+
+/// Random number generator, class "rng".
+///
+/// `CTRL` bit 0 enables it and bit 1 drives `irq` while a number is ready
+/// (`STATUS` bit 0). Each read of `DATA` takes a number, the next one is
+/// ready `latency` ticks later (args, default 0). The numbers come from a
+/// splitmix64 generator seeded by the `seed` arg (default 0) so the runs
+/// are reproducible, "host" seeds it from the host time. A write to `SEED`
+/// reseeds it.
+pub struct Rng {
+    name: String,
+    start: u32,
+    irq: u32,
+    seed: u64,
+    latency: u32,
+
+    ctrl: u32,
+    state: u64,
+    wait: u32,
+}
+
+impl Rng {
+    pub fn new(cfg: &CPerips) -> Self {
+        let seed = match cfg.args.get("seed") {
+            Some(s) if s.as_str() == Some("host") => {
+                SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+            },
+            Some(s) => s.as_u64().unwrap_or(0),
+            None => 0,
+        };
+        Rng {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            seed,
+            latency: cfg.arg_u64("latency", 0) as u32,
+            ctrl: 0,
+            state: seed,
+            wait: 0,
+        }
+    }
+
+    fn next(&mut self) -> u32 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        ((z ^ (z >> 31)) >> 32) as u32
+    }
+
+    fn status(&self) -> u32 {
+        if self.ctrl & CTRL_EN != 0 && self.wait == 0 { STATUS_READY } else { 0 }
+    }
+
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        ctx.set_intr(self.irq, self.ctrl & CTRL_IE != 0 && self.status() != 0);
+    }
+}
+
+impl Peripheral for Rng {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < RNG_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        let res = match addr - self.start {
+            CTRL => self.ctrl,
+            STATUS => self.status(),
+            DATA if self.status() != 0 => {
+                self.wait = self.latency;
+                self.next()
+            },
+            _ => 0,
+        };
+        self.update(ctx);
+        res
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CTRL => self.ctrl = data & (CTRL_EN | CTRL_IE),
+            SEED => self.state = data as u64,
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            DATA => 0,
+            _ => self.read_u32(addr, ctx),
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        if self.wait > 0 {
+            self.wait -= 1;
+            self.update(ctx);
+        }
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        if self.wait == 0 { u64::MAX } else { (self.wait - 1) as u64 }
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        self.wait = self.wait.saturating_sub(ticks as u32);
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.ctrl = 0;
+        self.state = self.seed;
+        self.wait = 0;
+        self.update(ctx);
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: {:08X}, ctrl:{:X} status:{:X} state:{:016X}", self.name, self.start, self.ctrl, self.status(), self.state)
+    }
+}
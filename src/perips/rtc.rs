@@ -0,0 +1,271 @@
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+
+const CTRL: u32 = 0x00;
+const SECONDS: u32 = 0x04;
+const SUBSECONDS: u32 = 0x08;
+const DATE: u32 = 0x0c;
+const TIME: u32 = 0x10;
+const ALARM: u32 = 0x14;
+const STATUS: u32 = 0x18;
+const RTC_SIZE: u32 = 0x1c;
+
+const CTRL_EN: u32 = 0x1;
+const CTRL_ALARM_IE: u32 = 0x2;
+
+const STATUS_ALARM: u32 = 0x1;
+
+const NS: u64 = 1_000_000_000;
+const DAY: u64 = 86400;
+
+//days since 1970-01-01 of a date, and back.
+fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + (m <= 2) as i64, m, d)
+}
+
+/// Real time clock, class "rtc".
+///
+/// `SECONDS` counts the seconds since 1970-01-01 and `SUBSECONDS` the
+/// 1/32768 of a second, latched when `SECONDS` is read. `DATE` is
+/// year << 16 | month << 8 | day and `TIME` weekday << 24 | hour << 16 |
+/// minute << 8 | second (weekday 0 on sunday), writing `SECONDS`, `DATE` or
+/// `TIME` sets the clock. `STATUS` bit 0 (W1C) is set once `SECONDS`
+/// reaches `ALARM` and drives `irq` with `CTRL` bit 1, `CTRL` bit 0 runs the
+/// clock. Args: `time` "virtual" (default) counts the ticks of the rtc at
+/// `freq` from `epoch` (seconds, default 0), "host" follows the host clock.
+pub struct Rtc {
+    name: String,
+    start: u32,
+    irq: u32,
+    freq_hz: u64,
+    host: bool,
+    epoch: u64,
+
+    ctrl: u32,
+    alarm: u32,
+    armed: bool,
+    status: u32,
+    //the time when the clock was set or started, plus what ran since.
+    base_ns: u64,
+    ticks: u64,
+    host_ref: Option<Instant>,
+    subseconds: u32,
+}
+
+impl Rtc {
+    pub fn new(cfg: &CPerips) -> Self {
+        let host = cfg.arg_str("time") == Some("host");
+        let epoch = cfg.arg_u64("epoch", 0);
+        let freq_hz = (cfg.freq as f64 * 1_000_000.0) as u64;
+        let mut rtc = Rtc {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            freq_hz: freq_hz.max(1),
+            host,
+            epoch,
+            ctrl: 0,
+            alarm: u32::MAX,
+            armed: false,
+            status: 0,
+            base_ns: 0,
+            ticks: 0,
+            host_ref: None,
+            subseconds: 0,
+        };
+        rtc.restart();
+        rtc
+    }
+
+    fn restart(&mut self) {
+        self.ctrl = 0;
+        self.alarm = u32::MAX;
+        self.armed = false;
+        self.status = 0;
+        self.ticks = 0;
+        self.host_ref = None;
+        self.subseconds = 0;
+        self.base_ns = if self.host {
+            SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64)
+        } else {
+            self.epoch * NS
+        };
+        //the host clock never stops.
+        if self.host {
+            self.ctrl = CTRL_EN;
+            self.host_ref = Some(Instant::now());
+        }
+    }
+
+    fn now_ns(&self) -> u64 {
+        let elapsed = match self.host_ref {
+            Some(r) => r.elapsed().as_nanos() as u64,
+            None => (self.ticks as u128 * NS as u128 / self.freq_hz as u128) as u64,
+        };
+        self.base_ns + elapsed
+    }
+
+    fn set_secs(&mut self, secs: u64) {
+        self.base_ns = secs * NS;
+        self.ticks = 0;
+        if self.host_ref.is_some() {
+            self.host_ref = Some(Instant::now());
+        }
+    }
+
+    fn set_running(&mut self, on: bool) {
+        if on == (self.ctrl & CTRL_EN != 0) {
+            return;
+        }
+        self.base_ns = self.now_ns();
+        self.ticks = 0;
+        self.host_ref = if on && self.host { Some(Instant::now()) } else { None };
+    }
+
+    fn secs(&self) -> u64 {
+        self.now_ns() / NS
+    }
+
+    fn date(&self) -> u32 {
+        let (y, m, d) = civil_from_days((self.secs() / DAY) as i64);
+        ((y as u32) << 16) | (m << 8) | d
+    }
+
+    fn time(&self) -> u32 {
+        let secs = self.secs();
+        let days = secs / DAY;
+        let s = secs % DAY;
+        let weekday = (days + 4) % 7;
+        ((weekday as u32) << 24) | (((s / 3600) as u32) << 16) | ((((s / 60) % 60) as u32) << 8) | (s % 60) as u32
+    }
+
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        if self.armed && self.secs() >= self.alarm as u64 {
+            self.armed = false;
+            self.status |= STATUS_ALARM;
+        }
+        ctx.set_intr(self.irq, self.ctrl & CTRL_ALARM_IE != 0 && self.status & STATUS_ALARM != 0);
+    }
+}
+
+impl Peripheral for Rtc {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < RTC_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            CTRL => self.ctrl,
+            SECONDS => {
+                let ns = self.now_ns();
+                self.subseconds = ((ns % NS) * 32768 / NS) as u32;
+                (ns / NS) as u32
+            },
+            SUBSECONDS => self.subseconds,
+            DATE => self.date(),
+            TIME => self.time(),
+            ALARM => self.alarm,
+            STATUS => self.status,
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CTRL => {
+                self.set_running(data & CTRL_EN != 0);
+                self.ctrl = data & (CTRL_EN | CTRL_ALARM_IE);
+            },
+            SECONDS => self.set_secs(data as u64),
+            DATE => {
+                let (y, m, d) = ((data >> 16) as i64, (data >> 8) & 0xff, data & 0xff);
+                let days = days_from_civil(y, m.clamp(1, 12), d.clamp(1, 31)).max(0) as u64;
+                self.set_secs(days * DAY + self.secs() % DAY);
+            },
+            TIME => {
+                let (h, m, s) = (((data >> 16) & 0xff) as u64, ((data >> 8) & 0xff) as u64, (data & 0xff) as u64);
+                self.set_secs(self.secs() / DAY * DAY + (h.min(23) * 3600 + m.min(59) * 60 + s.min(59)));
+            },
+            ALARM => {
+                self.alarm = data;
+                self.armed = true;
+            },
+            STATUS => self.status &= !data,
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    //reading SECONDS would latch SUBSECONDS.
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            SECONDS => self.secs() as u32,
+            STATUS => 0,
+            _ => self.read_u32(addr, ctx),
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        if self.ctrl & CTRL_EN != 0 && !self.host {
+            self.ticks += 1;
+        }
+        if self.armed {
+            self.update(ctx);
+        }
+    }
+
+    //until the alarm, the host clock runs on its own.
+    fn idle_ticks(&self) -> u64 {
+        if self.host {
+            return if self.armed { 0 } else { u64::MAX };
+        }
+        if !self.armed || self.ctrl & CTRL_EN == 0 {
+            return u64::MAX;
+        }
+        let target = (self.alarm as u64 * NS).saturating_sub(self.base_ns) as u128;
+        let ticks = (target * self.freq_hz as u128).div_ceil(NS as u128);
+        (ticks.saturating_sub(self.ticks as u128 + 1)).min(u64::MAX as u128) as u64
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        if self.ctrl & CTRL_EN != 0 && !self.host {
+            self.ticks += ticks;
+        }
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.restart();
+        self.update(ctx);
+    }
+
+    fn dump(&self) -> String {
+        let (date, time) = (self.date(), self.time());
+        format!("{}: {:08X}, ctrl:{:X} {:04}-{:02}-{:02} {:02}:{:02}:{:02} alarm:{} status:{:X}",
+                self.name, self.start, self.ctrl, date >> 16, (date >> 8) & 0xff, date & 0xff,
+                (time >> 16) & 0xff, (time >> 8) & 0xff, time & 0xff, self.alarm, self.status)
+    }
+}
@@ -0,0 +1,251 @@
+use std::collections::VecDeque;
+
+use crate::config::CPerips;
+use crate::perips::spi_nor::SpiNor;
+use crate::perips::{PeripCtx, Peripheral};
+
+const CS: u32 = 0x00;
+const TXDATA: u32 = 0x04;
+const RXDATA: u32 = 0x08;
+const STATUS: u32 = 0x0c;
+const DIV: u32 = 0x10;
+const IE: u32 = 0x14;
+const IP: u32 = 0x18;
+const SPI_SIZE: u32 = 0x1c;
+
+const CS_ACTIVE: u32 = 0x100;
+
+const STATUS_BUSY: u32 = 0x01;
+const STATUS_RX_VALID: u32 = 0x02;
+const STATUS_TX_FULL: u32 = 0x04;
+
+const RXDATA_EMPTY: u32 = 0x8000_0000;
+
+const FIFO_SIZE: usize = 8;
+
+/// A device on the spi bus, it sees the bytes while its chip select is active.
+pub trait SpiSlave {
+    /// The chip select is asserted (true) or released.
+    fn select(&mut self, active: bool);
+    /// Shift one byte out of the master and return the one shifted in.
+    fn transfer(&mut self, mosi: u8) -> u8;
+}
+
+/// Create a spi slave from its config, e.g. {"class": "spi_nor", "cs": 0, ...}.
+/// Classes: `spi_nor` (see `SpiNor`).
+pub fn open_slave(cfg: &serde_json::Value) -> Option<Box<dyn SpiSlave>> {
+    match cfg.get("class").and_then(|c| c.as_str()) {
+        Some("spi_nor") => Some(Box::new(SpiNor::new(cfg))),
+        class => {
+            println!("spi slave class {class:?} not found.");
+            None
+        },
+    }
+}
+
+/// Spi master, class "spi".
+///
+/// `CS` bits 7:0 pick the slave and bit 8 asserts its chip select. A byte
+/// written to `TXDATA` is shifted in 16 * (`DIV` + 1) ticks and the byte
+/// shifted in goes to `RXDATA` (bit 31 set when empty), both through 8
+/// bytes fifos. `IP`/`IE` bit 0 is set when all the bytes are shifted and
+/// drives `irq`. Args: `slaves` a list of slave configs with their `cs`.
+pub struct Spi {
+    name: String,
+    start: u32,
+    irq: u32,
+
+    slaves: Vec<(u32, Box<dyn SpiSlave>)>,
+
+    cs: u32,
+    div: u32,
+    ie: u32,
+    ip: u32,
+    tx_fifo: VecDeque<u8>,
+    rx_fifo: VecDeque<u8>,
+    shift_cnt: u64,
+}
+
+impl Spi {
+    pub fn new(cfg: &CPerips) -> Self {
+        let mut slaves = Vec::new();
+        if let Some(list) = cfg.args.get("slaves").and_then(|s| s.as_array()) {
+            for s in list {
+                if let Some(slave) = open_slave(s) {
+                    slaves.push((s.get("cs").and_then(|c| c.as_u64()).unwrap_or(0) as u32, slave));
+                }
+            }
+        }
+        Self::with_slaves(cfg, slaves)
+    }
+
+    /// A master with custom slaves, (cs, slave) pairs.
+    pub fn with_slaves(cfg: &CPerips, slaves: Vec<(u32, Box<dyn SpiSlave>)>) -> Self {
+        Spi {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            slaves,
+            cs: 0,
+            div: 0,
+            ie: 0,
+            ip: 0,
+            tx_fifo: VecDeque::new(),
+            rx_fifo: VecDeque::new(),
+            shift_cnt: 0,
+        }
+    }
+
+    fn slave(&mut self) -> Option<&mut Box<dyn SpiSlave>> {
+        let cs = self.cs & 0xff;
+        self.slaves.iter_mut().find(|(c, _)| *c == cs).map(|(_, s)| s)
+    }
+
+    fn status(&self) -> u32 {
+        let mut status = 0;
+        if !self.tx_fifo.is_empty() {
+            status |= STATUS_BUSY;
+        }
+        if !self.rx_fifo.is_empty() {
+            status |= STATUS_RX_VALID;
+        }
+        if self.tx_fifo.len() >= FIFO_SIZE {
+            status |= STATUS_TX_FULL;
+        }
+        status
+    }
+
+    //a byte is 8 bits of 2 * (div + 1) ticks each, in u64 so a big div can't overflow.
+    fn byte_ticks(&self) -> u64 {
+        16 * (self.div as u64 + 1)
+    }
+
+    //shift the byte at the head of the tx fifo, a released bus reads 0xff.
+    fn shift(&mut self) {
+        if let Some(b) = self.tx_fifo.pop_front() {
+            let active = self.cs & CS_ACTIVE != 0;
+            let rx = match self.slave() {
+                Some(s) if active => s.transfer(b),
+                _ => 0xff,
+            };
+            if self.rx_fifo.len() < FIFO_SIZE {
+                self.rx_fifo.push_back(rx);
+            }
+            if self.tx_fifo.is_empty() {
+                self.ip |= 1;
+            }
+        }
+    }
+
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        ctx.set_intr(self.irq, self.ip & self.ie != 0);
+    }
+}
+
+impl Peripheral for Spi {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < SPI_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        let res = match addr - self.start {
+            CS => self.cs,
+            RXDATA => self.rx_fifo.pop_front().map_or(RXDATA_EMPTY, |b| b as u32),
+            STATUS => self.status(),
+            DIV => self.div,
+            IE => self.ie,
+            IP => self.ip,
+            _ => 0,
+        };
+        self.update(ctx);
+        res
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CS => {
+                let old_active = self.cs & CS_ACTIVE != 0;
+                let old = self.cs & 0xff;
+                self.cs = data & 0x1ff;
+                let active = self.cs & CS_ACTIVE != 0;
+                if old_active && (!active || old != self.cs & 0xff) {
+                    if let Some((_, s)) = self.slaves.iter_mut().find(|(c, _)| *c == old) {
+                        s.select(false);
+                    }
+                }
+                if active && (!old_active || old != self.cs & 0xff) {
+                    if let Some(s) = self.slave() {
+                        s.select(true);
+                    }
+                }
+            },
+            TXDATA if self.tx_fifo.len() < FIFO_SIZE => {
+                self.tx_fifo.push_back(data as u8);
+                self.ip &= !1;
+            },
+            DIV => self.div = data,
+            IE => self.ie = data & 1,
+            IP => self.ip &= !data,
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            RXDATA | IP => 0,
+            _ => self.read_u32(addr, ctx),
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        if self.tx_fifo.is_empty() {
+            return;
+        }
+        self.shift_cnt += 1;
+        if self.shift_cnt >= self.byte_ticks() {
+            self.shift_cnt = 0;
+            self.shift();
+        }
+        self.update(ctx);
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        if self.tx_fifo.is_empty() {
+            return u64::MAX;
+        }
+        self.byte_ticks().saturating_sub(self.shift_cnt + 1)
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        if !self.tx_fifo.is_empty() {
+            self.shift_cnt += ticks;
+        }
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        if self.cs & CS_ACTIVE != 0 {
+            if let Some(s) = self.slave() {
+                s.select(false);
+            }
+        }
+        self.cs = 0;
+        self.div = 0;
+        self.ie = 0;
+        self.ip = 0;
+        self.tx_fifo.clear();
+        self.rx_fifo.clear();
+        self.shift_cnt = 0;
+        self.update(ctx);
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: {:08X}, cs:{:X} div:{} status:{:X} ie:{:X} ip:{:X} slaves:{:?}",
+                self.name, self.start, self.cs, self.div, self.status(), self.ie, self.ip,
+                self.slaves.iter().map(|(c, _)| *c).collect::<Vec<u32>>())
+    }
+}
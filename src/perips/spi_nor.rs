@@ -0,0 +1,206 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::perips::spi::SpiSlave;
+
+const CMD_WRSR: u8 = 0x01;
+const CMD_PP: u8 = 0x02;
+const CMD_READ: u8 = 0x03;
+const CMD_WRDI: u8 = 0x04;
+const CMD_RDSR: u8 = 0x05;
+const CMD_WREN: u8 = 0x06;
+const CMD_FAST_READ: u8 = 0x0b;
+const CMD_SE: u8 = 0x20;
+const CMD_CE: u8 = 0x60;
+const CMD_JEDEC_ID: u8 = 0x9f;
+const CMD_CE2: u8 = 0xc7;
+const CMD_BE: u8 = 0xd8;
+
+const SR_WEL: u8 = 0x02;
+//block protect bits, kept but not enforced
+const SR_BP: u8 = 0x9c;
+
+const PAGE_SIZE: u32 = 256;
+const SECTOR_SIZE: u32 = 4096;
+const BLOCK_SIZE: u32 = 0x10000;
+
+/// JEDEC spi nor flash slave, class "spi_nor".
+///
+/// It knows read, fast read, page program, sector/block/chip erase, the
+/// status register, write enable/disable and the JEDEC ID, with 3 bytes
+/// addresses. Programming only clears bits, erasing sets them back to
+/// 0xff. Args: `size` (default 4MiB), `jedec_id` (default 0xef4016) and
+/// `file`, a host file holding the contents, created if missing and
+/// written back on each program and erase.
+pub struct SpiNor {
+    data: Vec<u8>,
+    jedec_id: u32,
+    file: Option<File>,
+
+    status: u8,
+    cmd: Option<u8>,
+    //bytes of the current command after the opcode
+    pos: u32,
+    addr: u32,
+    page: Vec<(u32, u8)>,
+}
+
+impl SpiNor {
+    pub fn new(cfg: &serde_json::Value) -> Self {
+        let size = cfg.get("size").and_then(|s| s.as_u64()).unwrap_or(0x400000).max(1) as usize;
+        let mut data = vec![0xff; size];
+        let file = cfg.get("file").and_then(|f| f.as_str()).and_then(|path| {
+            let mut f = match OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path) {
+                Ok(f) => f,
+                Err(e) => {
+                    println!("spi nor: can't open {path}: {e}.");
+                    return None;
+                },
+            };
+            let mut buf = Vec::new();
+            if let Err(e) = f.read_to_end(&mut buf) {
+                println!("spi nor: can't read {path}: {e}.");
+            }
+            let n = buf.len().min(size);
+            data[..n].copy_from_slice(&buf[..n]);
+            Some((f, n))
+        });
+        let (file, loaded) = match file {
+            Some((f, n)) => (Some(f), n),
+            None => (None, size),
+        };
+        let mut dev = SpiNor {
+            data,
+            jedec_id: cfg.get("jedec_id").and_then(|j| j.as_u64()).unwrap_or(0xef4016) as u32,
+            file,
+            status: 0,
+            cmd: None,
+            pos: 0,
+            addr: 0,
+            page: Vec::new(),
+        };
+        //a new or short file gets the erased tail.
+        if loaded < size {
+            dev.persist(loaded, size);
+        }
+        dev
+    }
+
+    fn offset(&self, addr: u32) -> usize {
+        addr as usize % self.data.len()
+    }
+
+    //write data[start..end] back to the host file.
+    fn persist(&mut self, start: usize, end: usize) {
+        if let Some(f) = self.file.as_mut() {
+            let res = f.seek(SeekFrom::Start(start as u64)).and_then(|_| f.write_all(&self.data[start..end]));
+            if let Err(e) = res {
+                println!("spi nor: can't write the file: {e}.");
+            }
+        }
+    }
+
+    fn erase(&mut self, addr: u32, size: u32) {
+        let start = self.offset(addr) & !(size as usize - 1);
+        let end = (start + size as usize).min(self.data.len());
+        self.data[start..end].fill(0xff);
+        self.persist(start, end);
+    }
+
+    //program and erase run when the chip select is released.
+    fn finish(&mut self) {
+        let cmd = match self.cmd.take() {
+            Some(c) => c,
+            None => return,
+        };
+        if self.status & SR_WEL == 0 {
+            return;
+        }
+        match cmd {
+            CMD_PP if self.pos >= 3 => {
+                let page = std::mem::take(&mut self.page);
+                for (addr, b) in page.iter() {
+                    let o = self.offset(*addr);
+                    self.data[o] &= b;
+                }
+                let start = self.offset(self.addr & !(PAGE_SIZE - 1));
+                let end = (start + PAGE_SIZE as usize).min(self.data.len());
+                self.persist(start, end);
+            },
+            CMD_SE if self.pos >= 3 => self.erase(self.addr, SECTOR_SIZE),
+            CMD_BE if self.pos >= 3 => self.erase(self.addr, BLOCK_SIZE),
+            CMD_CE | CMD_CE2 => {
+                self.data.fill(0xff);
+                self.persist(0, self.data.len());
+            },
+            CMD_WRSR if self.pos >= 1 => {},
+            _ => return,
+        }
+        self.status &= !SR_WEL;
+    }
+}
+
+impl SpiSlave for SpiNor {
+    fn select(&mut self, active: bool) {
+        if !active {
+            self.finish();
+        }
+        self.cmd = None;
+        self.pos = 0;
+        self.page.clear();
+    }
+
+    fn transfer(&mut self, mosi: u8) -> u8 {
+        let cmd = match self.cmd {
+            Some(c) => c,
+            None => {
+                self.cmd = Some(mosi);
+                match mosi {
+                    CMD_WREN => self.status |= SR_WEL,
+                    CMD_WRDI => self.status &= !SR_WEL,
+                    _ => {},
+                }
+                return 0xff;
+            },
+        };
+        let pos = self.pos;
+        self.pos += 1;
+        match cmd {
+            CMD_RDSR => self.status,
+            CMD_WRSR => {
+                if pos == 0 && self.status & SR_WEL != 0 {
+                    self.status = (self.status & !SR_BP) | (mosi & SR_BP);
+                }
+                0xff
+            },
+            CMD_JEDEC_ID => match pos {
+                0..=2 => (self.jedec_id >> (16 - 8 * pos)) as u8,
+                _ => 0xff,
+            },
+            CMD_READ | CMD_FAST_READ | CMD_PP | CMD_SE | CMD_BE if pos < 3 => {
+                self.addr = (self.addr << 8 | mosi as u32) & 0xff_ffff;
+                0xff
+            },
+            CMD_READ => {
+                let b = self.data[self.offset(self.addr)];
+                self.addr = self.addr.wrapping_add(1);
+                b
+            },
+            CMD_FAST_READ if pos == 3 => 0xff,
+            CMD_FAST_READ => {
+                let b = self.data[self.offset(self.addr)];
+                self.addr = self.addr.wrapping_add(1);
+                b
+            },
+            CMD_PP => {
+                //the address wraps inside the page, the last write wins.
+                let page = self.addr & !(PAGE_SIZE - 1);
+                let a = page | (self.addr.wrapping_add(pos - 3) & (PAGE_SIZE - 1));
+                self.page.retain(|(p, _)| *p != a);
+                self.page.push((a, mosi));
+                0xff
+            },
+            _ => 0xff,
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+use crate::rv32_actor::SocEvent;
+
+const FINISHER_FAIL: u32 = 0x3333;
+const FINISHER_PASS: u32 = 0x5555;
+const FINISHER_RESET: u32 = 0x7777;
+
+/// SiFive test finisher compatible power and reset controller, class "syscon".
+///
+/// Writing 0x5555 to the register powers off with code 0, 0x3333 with the
+/// code in the upper 16 bits, and 0x7777 resets the soc (`keep_ram` arg,
+/// default true).
+pub struct Syscon {
+    name: String,
+    start: u32,
+    keep_ram: bool,
+}
+
+impl Syscon {
+    pub fn new(cfg: &CPerips) -> Self {
+        Syscon {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            keep_ram: cfg.arg_bool("keep_ram", true),
+        }
+    }
+}
+
+impl Peripheral for Syscon {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < 4
+    }
+
+    fn read_u32(&mut self, _addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        0
+    }
+
+    fn write_u32(&mut self, data: u32, _addr: u32, ctx: &mut PeripCtx) {
+        match data & 0xffff {
+            FINISHER_PASS => ctx.emit(SocEvent::Halt { perip: self.name.clone(), code: 0 }),
+            FINISHER_FAIL => ctx.emit(SocEvent::Halt { perip: self.name.clone(), code: data >> 16 }),
+            FINISHER_RESET => ctx.emit(SocEvent::Reset { perip: self.name.clone(), keep_ram: self.keep_ram }),
+            _ => {},
+        }
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        u64::MAX
+    }
+}
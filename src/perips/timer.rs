@@ -0,0 +1,237 @@
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+use crate::rv32_actor::SocEvent;
+
+const CTRL: u32 = 0x00;
+const PRESCALE: u32 = 0x04;
+const COUNT: u32 = 0x08;
+const TOP: u32 = 0x0c;
+const IP: u32 = 0x10;
+const IE: u32 = 0x14;
+const CH_BASE: u32 = 0x20;
+
+const CTRL_EN: u32 = 0x01;
+const CTRL_ONESHOT: u32 = 0x02;
+
+const CH_CMP_EN: u32 = 0x01;
+const CH_PWM_EN: u32 = 0x02;
+const CH_PWM_INV: u32 = 0x04;
+
+const IP_OVF: u32 = 0x01;
+
+/// General purpose timer with compare channels and pwm, class "timer".
+///
+/// The counter advances every `PRESCALE + 1` clocks and wraps after `TOP`
+/// (periodic) or stops there (one-shot). Channel n has `CMP` at
+/// 0x20 + 8n and `CH_CTRL` at 0x24 + 8n, its pwm output is high while the
+/// counter is below `CMP` and is pin n of the timer. `IP`/`IE` bit 0 is
+/// the overflow, bit n+1 the compare match of channel n. The overflow
+/// drives `irq`, the compares `cmp_irq` (args, default `irq`).
+pub struct Timer {
+    name: String,
+    start: u32,
+    irq: u32,
+    cmp_irq: u32,
+
+    ctrl: u32,
+    prescale: u32,
+    count: u32,
+    top: u32,
+    ip: u32,
+    ie: u32,
+    cmp: Vec<u32>,
+    ch_ctrl: Vec<u32>,
+
+    pre_cnt: u32,
+    pwm: Vec<bool>,
+}
+
+impl Timer {
+    pub fn new(cfg: &CPerips) -> Self {
+        let channels = cfg.arg_u64("channels", 4).clamp(1, 31) as usize;
+        Timer {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            cmp_irq: cfg.arg_u64("cmp_irq", cfg.irq as u64) as u32,
+            ctrl: 0,
+            prescale: 0,
+            count: 0,
+            top: u32::MAX,
+            ip: 0,
+            ie: 0,
+            cmp: vec![0; channels],
+            ch_ctrl: vec![0; channels],
+            pre_cnt: 0,
+            pwm: vec![false; channels],
+        }
+    }
+
+    fn size(&self) -> u32 {
+        CH_BASE + 8 * self.cmp.len() as u32
+    }
+
+    fn pwm_level(&self, ch: usize) -> bool {
+        if self.ch_ctrl[ch] & CH_PWM_EN == 0 {
+            return false;
+        }
+        (self.count < self.cmp[ch]) ^ (self.ch_ctrl[ch] & CH_PWM_INV != 0)
+    }
+
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        for ch in 0..self.pwm.len() {
+            let level = self.pwm_level(ch);
+            if level != self.pwm[ch] {
+                self.pwm[ch] = level;
+                ctx.emit(SocEvent::Pin { perip: self.name.clone(), pin: ch as u32, level });
+            }
+        }
+
+        let pending = self.ip & self.ie;
+        if self.irq == self.cmp_irq {
+            ctx.set_intr(self.irq, pending != 0);
+        } else {
+            ctx.set_intr(self.irq, pending & IP_OVF != 0);
+            ctx.set_intr(self.cmp_irq, pending & !IP_OVF != 0);
+        }
+    }
+
+    fn count(&mut self) {
+        if self.count >= self.top {
+            self.ip |= IP_OVF;
+            self.count = 0;
+            if self.ctrl & CTRL_ONESHOT != 0 {
+                self.ctrl &= !CTRL_EN;
+            }
+        } else {
+            self.count += 1;
+        }
+
+        for ch in 0..self.cmp.len() {
+            if self.ch_ctrl[ch] & CH_CMP_EN != 0 && self.count == self.cmp[ch] {
+                self.ip |= 1 << (ch + 1);
+            }
+        }
+    }
+}
+
+impl Peripheral for Timer {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < self.size()
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            CTRL => self.ctrl,
+            PRESCALE => self.prescale,
+            COUNT => self.count,
+            TOP => self.top,
+            IP => self.ip,
+            IE => self.ie,
+            o if o >= CH_BASE => {
+                let ch = ((o - CH_BASE) >> 3) as usize;
+                if o & 4 == 0 { self.cmp[ch] } else { self.ch_ctrl[ch] }
+            },
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CTRL => {
+                self.ctrl = data & (CTRL_EN | CTRL_ONESHOT);
+                self.pre_cnt = 0;
+            },
+            PRESCALE => self.prescale = data,
+            COUNT => self.count = data,
+            TOP => self.top = data,
+            IP => self.ip &= !data,
+            IE => self.ie = data,
+            o if o >= CH_BASE => {
+                let ch = ((o - CH_BASE) >> 3) as usize;
+                if o & 4 == 0 {
+                    self.cmp[ch] = data;
+                } else {
+                    self.ch_ctrl[ch] = data & (CH_CMP_EN | CH_PWM_EN | CH_PWM_INV);
+                }
+            },
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            IP => 0,
+            _ => self.read_u32(addr, ctx),
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        if self.ctrl & CTRL_EN == 0 {
+            return;
+        }
+        if self.pre_cnt < self.prescale {
+            self.pre_cnt += 1;
+            return;
+        }
+        self.pre_cnt = 0;
+        self.count();
+        self.update(ctx);
+    }
+
+    //until the counter reaches a compare, pwm edge or the overflow.
+    fn idle_ticks(&self) -> u64 {
+        if self.ctrl & CTRL_EN == 0 {
+            return u64::MAX;
+        }
+        let mut steps = self.top as u64 - self.count.min(self.top) as u64 + 1;
+        for ch in 0..self.cmp.len() {
+            if self.ch_ctrl[ch] & (CH_CMP_EN | CH_PWM_EN) != 0 && self.cmp[ch] > self.count {
+                steps = steps.min((self.cmp[ch] - self.count) as u64);
+            }
+        }
+        let period = self.prescale as u64 + 1;
+        (self.prescale - self.pre_cnt.min(self.prescale)) as u64 + (steps - 1) * period
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        if self.ctrl & CTRL_EN == 0 {
+            return;
+        }
+        let period = self.prescale as u64 + 1;
+        let total = self.pre_cnt as u64 + ticks;
+        self.count += (total / period) as u32;
+        self.pre_cnt = (total % period) as u32;
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.ctrl = 0;
+        self.prescale = 0;
+        self.count = 0;
+        self.top = u32::MAX;
+        self.ip = 0;
+        self.ie = 0;
+        self.cmp.iter_mut().for_each(|c| *c = 0);
+        self.ch_ctrl.iter_mut().for_each(|c| *c = 0);
+        self.pre_cnt = 0;
+        self.update(ctx);
+    }
+
+    fn get_pin(&self, pin: u32) -> Option<bool> {
+        self.pwm.get(pin as usize).copied()
+    }
+
+    fn dump(&self) -> String {
+        let mut res = format!("{}: {:08X}, ctrl:{:X} prescale:{} count:{} top:{} ip:{:X} ie:{:X}\n",
+                                self.name, self.start, self.ctrl, self.prescale, self.count, self.top, self.ip, self.ie);
+        for ch in 0..self.cmp.len() {
+            res.push_str(&format!("ch{ch} cmp:{} ctrl:{:X} pwm:{}\n", self.cmp[ch], self.ch_ctrl[ch], self.pwm[ch] as u32));
+        }
+        res
+    }
+}
@@ -0,0 +1,363 @@
+use std::collections::VecDeque;
+
+use crate::chardev::{self, CharDev};
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+
+const FIFO_SIZE: usize = 16;
+
+const IER_RDI: u8 = 0x01;
+const IER_THRI: u8 = 0x02;
+const IER_RLSI: u8 = 0x04;
+
+const IIR_NO_INT: u8 = 0x01;
+const IIR_RLSI: u8 = 0x06;
+const IIR_RDI: u8 = 0x04;
+const IIR_CTI: u8 = 0x0c;
+const IIR_THRI: u8 = 0x02;
+const IIR_FIFO: u8 = 0xc0;
+
+const FCR_ENABLE: u8 = 0x01;
+const FCR_CLEAR_RX: u8 = 0x02;
+const FCR_CLEAR_TX: u8 = 0x04;
+
+const LCR_DLAB: u8 = 0x80;
+const MCR_LOOP: u8 = 0x10;
+
+const LSR_DR: u8 = 0x01;
+const LSR_OE: u8 = 0x02;
+const LSR_THRE: u8 = 0x20;
+const LSR_TEMT: u8 = 0x40;
+
+/// 16550 compatible uart, class "uart16550".
+///
+/// Args: `chardev` the host side (see `chardev::open`, default "stdio"),
+/// `reg_shift` the register stride 1 << reg_shift, `clock` the input clock
+/// in Hz for the divisor (default 1843200).
+pub struct Uart16550 {
+    name: String,
+    start: u32,
+    reg_shift: u32,
+    irq: u32,
+
+    host: Box<dyn CharDev>,
+
+    clock: u64,
+    freq_hz: u64,
+    char_ticks: u64,
+    tx_cnt: u64,
+    rx_cnt: u64,
+    rx_idle: u64,
+
+    rx_fifo: VecDeque<u8>,
+    tx_fifo: VecDeque<u8>,
+
+    ier: u8,
+    fcr: u8,
+    lcr: u8,
+    mcr: u8,
+    lsr: u8,
+    scr: u8,
+    dll: u8,
+    dlm: u8,
+
+    thre_pending: bool,
+}
+
+impl Uart16550 {
+    pub fn new(cfg: &CPerips) -> Self {
+        Self::with_chardev(cfg, chardev::open(cfg.arg_str("chardev").unwrap_or("stdio")))
+    }
+
+    pub fn with_chardev(cfg: &CPerips, host: Box<dyn CharDev>) -> Self {
+        let mut uart = Uart16550 {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            reg_shift: cfg.arg_u64("reg_shift", 0) as u32,
+            irq: cfg.irq,
+            host,
+            clock: cfg.arg_u64("clock", 1_843_200).max(1),
+            freq_hz: ((cfg.freq as f64) * 1_000_000.0) as u64,
+            char_ticks: 0,
+            tx_cnt: 0,
+            rx_cnt: 0,
+            rx_idle: 0,
+            rx_fifo: VecDeque::new(),
+            tx_fifo: VecDeque::new(),
+            ier: 0,
+            fcr: 0,
+            lcr: 0,
+            mcr: 0,
+            lsr: LSR_THRE | LSR_TEMT,
+            scr: 0,
+            dll: 0,
+            dlm: 0,
+            thre_pending: false,
+        };
+        uart.update_char_ticks();
+        uart
+    }
+
+    //ticks to shift one 10 bits frame, 0 when the divisor is not set.
+    fn update_char_ticks(&mut self) {
+        let divisor = ((self.dlm as u64) << 8) | self.dll as u64;
+        self.char_ticks = self.freq_hz * 16 * 10 * divisor / self.clock;
+    }
+
+    fn fifo_depth(&self) -> usize {
+        if self.fcr & FCR_ENABLE != 0 { FIFO_SIZE } else { 1 }
+    }
+
+    fn rx_trigger(&self) -> usize {
+        if self.fcr & FCR_ENABLE == 0 {
+            return 1;
+        }
+        match self.fcr >> 6 {
+            0 => 1,
+            1 => 4,
+            2 => 8,
+            _ => 14,
+        }
+    }
+
+    fn iir(&self) -> u8 {
+        let fifo = if self.fcr & FCR_ENABLE != 0 { IIR_FIFO } else { 0 };
+        let id = if self.ier & IER_RLSI != 0 && self.lsr & LSR_OE != 0 {
+            IIR_RLSI
+        } else if self.ier & IER_RDI != 0 && self.rx_fifo.len() >= self.rx_trigger() {
+            IIR_RDI
+        } else if self.ier & IER_RDI != 0 && !self.rx_fifo.is_empty() && self.rx_idle >= 4 {
+            IIR_CTI
+        } else if self.ier & IER_THRI != 0 && self.thre_pending {
+            IIR_THRI
+        } else {
+            IIR_NO_INT
+        };
+        fifo | id
+    }
+
+    fn update_lsr(&mut self) {
+        self.lsr &= !(LSR_DR | LSR_THRE | LSR_TEMT);
+        if !self.rx_fifo.is_empty() {
+            self.lsr |= LSR_DR;
+        }
+        if self.tx_fifo.is_empty() {
+            self.lsr |= LSR_THRE | LSR_TEMT;
+        }
+    }
+
+    fn update_intr(&mut self, ctx: &mut PeripCtx) {
+        self.update_lsr();
+        ctx.set_intr(self.irq, self.iir() & IIR_NO_INT == 0);
+    }
+
+    fn push_rx(&mut self, b: u8) {
+        if self.rx_fifo.len() < self.fifo_depth() {
+            self.rx_fifo.push_back(b);
+        } else {
+            self.lsr |= LSR_OE;
+        }
+        self.rx_idle = 0;
+    }
+
+    fn transmit(&mut self) {
+        if let Some(b) = self.tx_fifo.pop_front() {
+            if self.mcr & MCR_LOOP != 0 {
+                self.push_rx(b);
+            } else {
+                self.host.write_byte(b);
+            }
+            if self.tx_fifo.is_empty() {
+                self.thre_pending = true;
+            }
+        }
+    }
+
+    fn receive(&mut self) {
+        if self.mcr & MCR_LOOP == 0 && self.rx_fifo.len() < self.fifo_depth() {
+            if let Some(b) = self.host.read_byte() {
+                self.push_rx(b);
+                return;
+            }
+        }
+        self.rx_idle += 1;
+    }
+
+    fn reg_read(&mut self, index: u32) -> u8 {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match index {
+            0 if dlab => self.dll,
+            0 => {
+                let b = self.rx_fifo.pop_front().unwrap_or(0);
+                self.rx_idle = 0;
+                b
+            },
+            1 if dlab => self.dlm,
+            1 => self.ier,
+            2 => {
+                let iir = self.iir();
+                if iir & 0x0f == IIR_THRI {
+                    self.thre_pending = false;
+                }
+                iir
+            },
+            3 => self.lcr,
+            4 => self.mcr,
+            5 => {
+                self.update_lsr();
+                let lsr = self.lsr;
+                self.lsr &= !LSR_OE;
+                lsr
+            },
+            //cts, dsr, dcd asserted
+            6 => 0xb0,
+            7 => self.scr,
+            _ => 0,
+        }
+    }
+
+    fn reg_write(&mut self, index: u32, data: u8) {
+        let dlab = self.lcr & LCR_DLAB != 0;
+        match index {
+            0 if dlab => {
+                self.dll = data;
+                self.update_char_ticks();
+            },
+            0 => {
+                if self.tx_fifo.len() < self.fifo_depth() {
+                    self.tx_fifo.push_back(data);
+                }
+                self.thre_pending = false;
+                if self.char_ticks == 0 {
+                    self.transmit();
+                }
+            },
+            1 if dlab => {
+                self.dlm = data;
+                self.update_char_ticks();
+            },
+            1 => {
+                //enabling thri with an empty fifo raises it at once.
+                if data & IER_THRI != 0 && self.ier & IER_THRI == 0 && self.tx_fifo.is_empty() {
+                    self.thre_pending = true;
+                }
+                self.ier = data & 0x0f;
+            },
+            2 => {
+                if data & FCR_CLEAR_RX != 0 {
+                    self.rx_fifo.clear();
+                }
+                if data & FCR_CLEAR_TX != 0 {
+                    self.tx_fifo.clear();
+                }
+                self.fcr = data & 0xc1;
+            },
+            3 => self.lcr = data,
+            4 => self.mcr = data & 0x1f,
+            7 => self.scr = data,
+            _ => {},
+        }
+    }
+}
+
+impl Peripheral for Uart16550 {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < (8 << self.reg_shift)
+    }
+
+    fn read_u8(&mut self, addr: u32, ctx: &mut PeripCtx) -> u8 {
+        let res = self.reg_read((addr - self.start) >> self.reg_shift);
+        self.update_intr(ctx);
+        res
+    }
+
+    fn write_u8(&mut self, data: u8, addr: u32, ctx: &mut PeripCtx) {
+        self.reg_write((addr - self.start) >> self.reg_shift, data);
+        self.update_intr(ctx);
+    }
+
+    fn read_u16(&mut self, addr: u32, ctx: &mut PeripCtx) -> u16 {
+        self.read_u8(addr, ctx) as u16
+    }
+
+    fn write_u16(&mut self, data: u16, addr: u32, ctx: &mut PeripCtx) {
+        self.write_u8(data as u8, addr, ctx);
+    }
+
+    fn read_u32(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        self.read_u8(addr, ctx) as u32
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        self.write_u8(data as u8, addr, ctx);
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        if self.char_ticks == 0 {
+            self.transmit();
+            self.receive();
+        } else {
+            self.tx_cnt += 1;
+            if self.tx_cnt >= self.char_ticks {
+                self.tx_cnt = 0;
+                self.transmit();
+            }
+            self.rx_cnt += 1;
+            if self.rx_cnt >= self.char_ticks {
+                self.rx_cnt = 0;
+                self.receive();
+            }
+        }
+        self.update_intr(ctx);
+    }
+
+    //the host input is polled once a char time.
+    fn idle_ticks(&self) -> u64 {
+        if !self.tx_fifo.is_empty() || (!self.rx_fifo.is_empty() && self.rx_idle < 4) {
+            return 0;
+        }
+        if self.mcr & MCR_LOOP != 0 || !self.host.has_input() {
+            return u64::MAX;
+        }
+        self.char_ticks.saturating_sub(self.rx_cnt + 1)
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        if self.char_ticks == 0 {
+            self.rx_idle = self.rx_idle.saturating_add(ticks);
+            return;
+        }
+        self.tx_cnt = (self.tx_cnt + ticks) % self.char_ticks;
+        self.rx_idle = self.rx_idle.saturating_add((self.rx_cnt + ticks) / self.char_ticks);
+        self.rx_cnt = (self.rx_cnt + ticks) % self.char_ticks;
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.rx_fifo.clear();
+        self.tx_fifo.clear();
+        self.ier = 0;
+        self.fcr = 0;
+        self.lcr = 0;
+        self.mcr = 0;
+        self.lsr = LSR_THRE | LSR_TEMT;
+        self.scr = 0;
+        self.dll = 0;
+        self.dlm = 0;
+        self.thre_pending = false;
+        self.tx_cnt = 0;
+        self.rx_cnt = 0;
+        self.rx_idle = 0;
+        self.update_char_ticks();
+        self.update_intr(ctx);
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: {:08X}, ier:{:02X} iir:{:02X} fcr:{:02X} lcr:{:02X} mcr:{:02X} lsr:{:02X} div:{} rx:{} tx:{}",
+                self.name, self.start, self.ier, self.iir(), self.fcr, self.lcr, self.mcr, self.lsr,
+                ((self.dlm as u32) << 8) | self.dll as u32, self.rx_fifo.len(), self.tx_fifo.len())
+    }
+}
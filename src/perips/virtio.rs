@@ -0,0 +1,461 @@
+use crate::config::CPerips;
+use crate::perips::virtio_blk::VirtioBlk;
+use crate::perips::virtio_console::VirtioConsole;
+use crate::perips::{PeripCtx, Peripheral};
+
+const MAGIC_VALUE: u32 = 0x000;
+const VERSION: u32 = 0x004;
+const DEVICE_ID: u32 = 0x008;
+const VENDOR_ID: u32 = 0x00c;
+const DEVICE_FEATURES: u32 = 0x010;
+const DEVICE_FEATURES_SEL: u32 = 0x014;
+const DRIVER_FEATURES: u32 = 0x020;
+const DRIVER_FEATURES_SEL: u32 = 0x024;
+const QUEUE_SEL: u32 = 0x030;
+const QUEUE_NUM_MAX: u32 = 0x034;
+const QUEUE_NUM: u32 = 0x038;
+const QUEUE_READY: u32 = 0x044;
+const QUEUE_NOTIFY: u32 = 0x050;
+const INTERRUPT_STATUS: u32 = 0x060;
+const INTERRUPT_ACK: u32 = 0x064;
+const STATUS: u32 = 0x070;
+const QUEUE_DESC_LOW: u32 = 0x080;
+const QUEUE_DESC_HIGH: u32 = 0x084;
+const QUEUE_DRIVER_LOW: u32 = 0x090;
+const QUEUE_DRIVER_HIGH: u32 = 0x094;
+const QUEUE_DEVICE_LOW: u32 = 0x0a0;
+const QUEUE_DEVICE_HIGH: u32 = 0x0a4;
+const CONFIG_GENERATION: u32 = 0x0fc;
+const CONFIG: u32 = 0x100;
+const VIRTIO_SIZE: u32 = 0x200;
+
+//"virt" and "ZEMU"
+const MAGIC: u32 = 0x74726976;
+const VENDOR: u32 = 0x554d455a;
+
+const INT_USED_BUFFER: u32 = 0x01;
+const INT_CONFIG_CHANGE: u32 = 0x02;
+
+const STATUS_DRIVER_OK: u32 = 0x04;
+const STATUS_DEVICE_NEEDS_RESET: u32 = 0x40;
+
+const F_VERSION_1: u64 = 1 << 32;
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+const AVAIL_F_NO_INTERRUPT: u16 = 1;
+
+const QUEUE_SIZE: u32 = 256;
+
+/// A buffer of a descriptor chain, `write` when the device writes it.
+#[derive(Debug, Clone, Copy)]
+pub struct Buf {
+    pub addr: u32,
+    pub len: u32,
+    pub write: bool,
+}
+
+/// The descriptor chain of an available buffer.
+#[derive(Debug)]
+pub struct DescChain {
+    pub head: u16,
+    pub bufs: Vec<Buf>,
+}
+
+impl DescChain {
+    /// The bytes of the device readable buffers, in order.
+    pub fn read(&self, ctx: &PeripCtx) -> Option<Vec<u8>> {
+        let total = self.bufs.iter().filter(|b| !b.write).try_fold(0u32, |a, b| a.checked_add(b.len))?;
+        let mut res = Vec::with_capacity(total as usize);
+        for b in self.bufs.iter().filter(|b| !b.write) {
+            let mut buf = vec![0u8; b.len as usize];
+            if !ctx.dma_read(b.addr, &mut buf) {
+                return None;
+            }
+            res.extend(buf);
+        }
+        Some(res)
+    }
+
+    /// Room of the device writable buffers.
+    pub fn write_len(&self) -> u32 {
+        self.bufs.iter().filter(|b| b.write).fold(0u32, |a, b| a.saturating_add(b.len))
+    }
+
+    /// Spread `data` over the device writable buffers, returns the bytes written.
+    pub fn write(&self, ctx: &mut PeripCtx, data: &[u8]) -> u32 {
+        let mut done = 0;
+        for b in self.bufs.iter().filter(|b| b.write) {
+            if done == data.len() {
+                break;
+            }
+            let n = (b.len as usize).min(data.len() - done);
+            if !ctx.dma_write(b.addr, &data[done..done + n]) {
+                break;
+            }
+            done += n;
+        }
+        done as u32
+    }
+}
+
+/// A split virtqueue in the guest mems.
+#[derive(Debug, Default, Clone)]
+pub struct Virtqueue {
+    num: u32,
+    ready: bool,
+    desc: u32,
+    avail: u32,
+    used: u32,
+    last_avail: u16,
+    //a ring or a descriptor was outside the mems, the driver must reset the device.
+    broken: bool,
+}
+
+impl Virtqueue {
+    fn read_u16(&mut self, ctx: &PeripCtx, addr: u32) -> Option<u16> {
+        let mut buf = [0u8; 2];
+        if ctx.dma_read(addr, &mut buf) {
+            Some(u16::from_le_bytes(buf))
+        } else {
+            eprintln!("virtqueue ring at {addr:x} is outside the mems.");
+            self.broken = true;
+            None
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready && self.num != 0 && !self.broken
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    /// Take the next available chain, None if there is none or it is broken.
+    pub fn pop(&mut self, ctx: &PeripCtx) -> Option<DescChain> {
+        if !self.is_ready() || self.read_u16(ctx, self.avail.wrapping_add(2))? == self.last_avail {
+            return None;
+        }
+        let slot = self.last_avail as u32 % self.num;
+        let head = self.read_u16(ctx, self.avail.wrapping_add(4 + 2 * slot))?;
+        self.last_avail = self.last_avail.wrapping_add(1);
+
+        let mut bufs = Vec::new();
+        let mut i = head;
+        //a chain never has more descriptors than the queue.
+        for _ in 0..self.num {
+            let mut d = [0u8; 16];
+            if i as u32 >= self.num || !ctx.dma_read(self.desc.wrapping_add(16 * i as u32), &mut d) {
+                eprintln!("virtqueue descriptor {i} broken.");
+                self.broken = true;
+                return None;
+            }
+            let flags = u16::from_le_bytes([d[12], d[13]]);
+            let buf = Buf {
+                addr: u32::from_le_bytes([d[0], d[1], d[2], d[3]]),
+                len: u32::from_le_bytes([d[8], d[9], d[10], d[11]]),
+                write: flags & DESC_F_WRITE != 0,
+            };
+            //the buffers are in the mems, so are the copies of them.
+            if ctx.dma_len(buf.addr, buf.len) != buf.len {
+                eprintln!("virtqueue buffer {:x}+{:x} is outside the mems.", buf.addr, buf.len);
+                self.broken = true;
+                return None;
+            }
+            bufs.push(buf);
+            if flags & DESC_F_NEXT == 0 {
+                break;
+            }
+            i = u16::from_le_bytes([d[14], d[15]]);
+        }
+        Some(DescChain { head, bufs })
+    }
+
+    /// Return the chain of `head` to the driver with `len` bytes written,
+    /// true when the driver wants an interrupt.
+    pub fn push(&mut self, ctx: &mut PeripCtx, head: u16, len: u32) -> bool {
+        let idx = match self.read_u16(ctx, self.used.wrapping_add(2)) {
+            Some(i) => i,
+            None => return false,
+        };
+        let elem = self.used.wrapping_add(4 + 8 * (idx as u32 % self.num));
+        if !ctx.dma_write(elem, &(head as u32).to_le_bytes()) || !ctx.dma_write(elem.wrapping_add(4), &len.to_le_bytes()) {
+            eprintln!("virtqueue used ring at {:x} is outside the mems.", self.used);
+            self.broken = true;
+            return false;
+        }
+        ctx.dma_write(self.used.wrapping_add(2), &idx.wrapping_add(1).to_le_bytes());
+        self.read_u16(ctx, self.avail).is_some_and(|f| f & AVAIL_F_NO_INTERRUPT == 0)
+    }
+}
+
+/// The device side of a virtio device behind the mmio transport.
+pub trait VirtioDevice {
+    fn device_id(&self) -> u32;
+    /// Device specific feature bits, VERSION_1 is added by the transport.
+    fn features(&self) -> u64 {
+        0
+    }
+    fn queues(&self) -> usize;
+    fn read_config(&self, offset: u32) -> u8;
+    fn write_config(&mut self, _offset: u32, _data: u8) {}
+    /// The driver notified `queue`, true when an interrupt is due.
+    fn notify(&mut self, queue: usize, vqs: &mut [Virtqueue], ctx: &mut PeripCtx) -> bool;
+    /// Called on each tick with the device running, true when an interrupt is due.
+    fn tick(&mut self, _vqs: &mut [Virtqueue], _ctx: &mut PeripCtx) -> bool {
+        false
+    }
+
+    /// See `Peripheral::idle_ticks`, the device only reacts to `notify` by default.
+    fn idle_ticks(&self) -> u64 {
+        u64::MAX
+    }
+    fn reset(&mut self) {}
+}
+
+/// Create a virtio device from the `device` arg of a transport.
+/// Devices: `blk` (see `VirtioBlk`) and `console` (see `VirtioConsole`).
+pub fn open_device(cfg: &CPerips) -> Option<Box<dyn VirtioDevice>> {
+    match cfg.arg_str("device") {
+        Some("blk") => Some(Box::new(VirtioBlk::new(cfg))),
+        Some("console") => Some(Box::new(VirtioConsole::new(cfg))),
+        device => {
+            eprintln!("virtio device {device:?} not found.");
+            None
+        },
+    }
+}
+
+/// Virtio mmio transport (version 2), class "virtio_mmio".
+///
+/// The `device` arg picks the device, without one it reads as device id 0
+/// which drivers skip. The virtqueues live in the guest mems and are
+/// reached by dma, a used buffer sets InterruptStatus bit 0 and drives `irq`.
+pub struct VirtioMmio {
+    name: String,
+    start: u32,
+    irq: u32,
+
+    device: Option<Box<dyn VirtioDevice>>,
+    vqs: Vec<Virtqueue>,
+
+    device_features_sel: u32,
+    driver_features: u64,
+    driver_features_sel: u32,
+    queue_sel: u32,
+    int_status: u32,
+    status: u32,
+}
+
+impl VirtioMmio {
+    pub fn new(cfg: &CPerips) -> Self {
+        Self::with_device(cfg, open_device(cfg))
+    }
+
+    /// A transport of a custom device.
+    pub fn with_device(cfg: &CPerips, device: Option<Box<dyn VirtioDevice>>) -> Self {
+        let queues = device.as_ref().map_or(0, |d| d.queues());
+        VirtioMmio {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            device,
+            vqs: vec![Virtqueue::default(); queues],
+            device_features_sel: 0,
+            driver_features: 0,
+            driver_features_sel: 0,
+            queue_sel: 0,
+            int_status: 0,
+            status: 0,
+        }
+    }
+
+    fn features(&self) -> u64 {
+        self.device.as_ref().map_or(0, |d| d.features() | F_VERSION_1)
+    }
+
+    fn queue(&mut self) -> Option<&mut Virtqueue> {
+        self.vqs.get_mut(self.queue_sel as usize)
+    }
+
+    fn reset_device(&mut self) {
+        self.vqs.iter_mut().for_each(|q| *q = Virtqueue::default());
+        if let Some(d) = self.device.as_mut() {
+            d.reset();
+        }
+        self.device_features_sel = 0;
+        self.driver_features = 0;
+        self.driver_features_sel = 0;
+        self.queue_sel = 0;
+        self.int_status = 0;
+        self.status = 0;
+    }
+
+    //a broken queue sets DEVICE_NEEDS_RESET and tells the driver by a
+    //configuration change interrupt.
+    fn update(&mut self, ctx: &mut PeripCtx) {
+        if self.status & STATUS_DEVICE_NEEDS_RESET == 0 && self.vqs.iter().any(|q| q.is_broken()) {
+            self.status |= STATUS_DEVICE_NEEDS_RESET;
+            self.int_status |= INT_CONFIG_CHANGE;
+        }
+        ctx.set_intr(self.irq, self.int_status != 0);
+    }
+}
+
+impl Peripheral for VirtioMmio {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < VIRTIO_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        let offset = addr - self.start;
+        match offset {
+            MAGIC_VALUE => MAGIC,
+            VERSION => 2,
+            DEVICE_ID => self.device.as_ref().map_or(0, |d| d.device_id()),
+            VENDOR_ID => VENDOR,
+            DEVICE_FEATURES => match self.device_features_sel {
+                0 => self.features() as u32,
+                1 => (self.features() >> 32) as u32,
+                _ => 0,
+            },
+            QUEUE_NUM_MAX if self.queue().is_some() => QUEUE_SIZE,
+            QUEUE_READY => self.queue().map_or(0, |q| q.ready as u32),
+            INTERRUPT_STATUS => self.int_status,
+            STATUS => self.status,
+            CONFIG_GENERATION => 0,
+            o if o >= CONFIG => match self.device.as_ref() {
+                Some(d) => (0..4).map(|i| (d.read_config(o - CONFIG + i) as u32) << (8 * i)).sum(),
+                None => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        let offset = addr - self.start;
+        match offset {
+            DEVICE_FEATURES_SEL => self.device_features_sel = data,
+            DRIVER_FEATURES => match self.driver_features_sel {
+                0 => self.driver_features = (self.driver_features & !0xffff_ffff) | data as u64,
+                1 => self.driver_features = (self.driver_features & 0xffff_ffff) | ((data as u64) << 32),
+                _ => {},
+            },
+            DRIVER_FEATURES_SEL => self.driver_features_sel = data,
+            QUEUE_SEL => self.queue_sel = data,
+            QUEUE_NUM | QUEUE_READY | QUEUE_DESC_LOW | QUEUE_DRIVER_LOW | QUEUE_DEVICE_LOW => {
+                if let Some(q) = self.queue() {
+                    match offset {
+                        QUEUE_NUM => q.num = data.min(QUEUE_SIZE),
+                        QUEUE_READY => q.ready = data & 1 != 0,
+                        QUEUE_DESC_LOW => q.desc = data,
+                        QUEUE_DRIVER_LOW => q.avail = data,
+                        _ => q.used = data,
+                    }
+                }
+            },
+            //the mems are 32 bits.
+            QUEUE_DESC_HIGH | QUEUE_DRIVER_HIGH | QUEUE_DEVICE_HIGH => {},
+            QUEUE_NOTIFY => {
+                let queue = data as usize;
+                if let Some(d) = self.device.as_mut() {
+                    if queue < self.vqs.len() && d.notify(queue, &mut self.vqs, ctx) {
+                        self.int_status |= INT_USED_BUFFER;
+                    }
+                }
+            },
+            INTERRUPT_ACK => self.int_status &= !data,
+            STATUS => {
+                if data == 0 {
+                    self.reset_device();
+                } else {
+                    self.status = data | (self.status & STATUS_DEVICE_NEEDS_RESET);
+                }
+            },
+            o if o >= CONFIG => if let Some(d) = self.device.as_mut() {
+                for i in 0..4 {
+                    d.write_config(o - CONFIG + i, (data >> (8 * i)) as u8);
+                }
+            },
+            _ => {},
+        }
+        self.update(ctx);
+    }
+
+    fn read_u8(&mut self, addr: u32, ctx: &mut PeripCtx) -> u8 {
+        let offset = addr - self.start;
+        match self.device.as_ref() {
+            Some(d) if offset >= CONFIG => d.read_config(offset - CONFIG),
+            _ => (self.read_u32(addr & !3, ctx) >> ((addr & 3) * 8)) as u8,
+        }
+    }
+
+    fn read_u16(&mut self, addr: u32, ctx: &mut PeripCtx) -> u16 {
+        (self.read_u8(addr, ctx) as u16) | ((self.read_u8(addr + 1, ctx) as u16) << 8)
+    }
+
+    //the config space takes bytes, the registers a merged word.
+    fn write_u8(&mut self, data: u8, addr: u32, ctx: &mut PeripCtx) {
+        let offset = addr - self.start;
+        match self.device.as_mut() {
+            Some(d) if offset >= CONFIG => {
+                d.write_config(offset - CONFIG, data);
+                self.update(ctx);
+            },
+            _ => {
+                let shift = (addr & 3) * 8;
+                self.write_masked((data as u32) << shift, 0xff << shift, addr & !3, ctx);
+            },
+        }
+    }
+
+    fn write_u16(&mut self, data: u16, addr: u32, ctx: &mut PeripCtx) {
+        if addr - self.start >= CONFIG {
+            self.write_u8(data as u8, addr, ctx);
+            self.write_u8((data >> 8) as u8, addr + 1, ctx);
+        } else {
+            let shift = (addr & 2) * 8;
+            self.write_masked((data as u32) << shift, 0xffff << shift, addr & !3, ctx);
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        if self.status & STATUS_DRIVER_OK == 0 {
+            return;
+        }
+        if let Some(d) = self.device.as_mut() {
+            if d.tick(&mut self.vqs, ctx) {
+                self.int_status |= INT_USED_BUFFER;
+            }
+        }
+        self.update(ctx);
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        match self.device.as_ref() {
+            Some(d) if self.status & STATUS_DRIVER_OK != 0 => d.idle_ticks(),
+            _ => u64::MAX,
+        }
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.reset_device();
+        self.update(ctx);
+    }
+
+    fn dump(&self) -> String {
+        let mut res = format!("{}: {:08X}, device:{} status:{:X} features:{:X} int_status:{:X}\n",
+                              self.name, self.start, self.device.as_ref().map_or(0, |d| d.device_id()),
+                              self.status, self.driver_features, self.int_status);
+        for (n, q) in self.vqs.iter().enumerate() {
+            res.push_str(&format!("vq{n} num:{} ready:{} desc:{:08X} avail:{:08X} used:{:08X} last_avail:{}\n",
+                                  q.num, q.ready, q.desc, q.avail, q.used, q.last_avail));
+        }
+        res
+    }
+}
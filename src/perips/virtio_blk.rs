@@ -0,0 +1,153 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::config::CPerips;
+use crate::perips::virtio::{DescChain, VirtioDevice, Virtqueue};
+use crate::perips::PeripCtx;
+
+const VIRTIO_ID_BLOCK: u32 = 2;
+
+const F_RO: u64 = 1 << 5;
+const F_FLUSH: u64 = 1 << 9;
+
+const T_IN: u32 = 0;
+const T_OUT: u32 = 1;
+const T_FLUSH: u32 = 4;
+const T_GET_ID: u32 = 8;
+
+const S_OK: u8 = 0;
+const S_IOERR: u8 = 1;
+const S_UNSUPP: u8 = 2;
+
+const SECTOR_SIZE: u64 = 512;
+const ID_LEN: usize = 20;
+
+/// Virtio block device, `device` "blk" of a virtio_mmio transport.
+///
+/// One request queue over the host disk image in the `image` arg, read
+/// only with `readonly`. Reads, writes, flushes and the device id are
+/// supported, the writes go straight to the image.
+pub struct VirtioBlk {
+    name: String,
+    image: Option<File>,
+    capacity: u64,
+    readonly: bool,
+}
+
+impl VirtioBlk {
+    pub fn new(cfg: &CPerips) -> Self {
+        let readonly = cfg.arg_bool("readonly", false);
+        let image = cfg.arg_str("image").and_then(|path| {
+            match OpenOptions::new().read(true).write(!readonly).open(path) {
+                Ok(f) => Some(f),
+                Err(e) => {
+                    eprintln!("virtio blk: can't open {path}: {e}.");
+                    None
+                },
+            }
+        });
+        let capacity = image.as_ref().and_then(|f| f.metadata().ok()).map_or(0, |m| m.len() / SECTOR_SIZE);
+        VirtioBlk { name: cfg.name.clone(), image, capacity, readonly }
+    }
+
+    //returns the status and the data read.
+    fn request(&mut self, req: &[u8], chain: &DescChain) -> (u8, Vec<u8>) {
+        if req.len() < 16 {
+            return (S_IOERR, Vec::new());
+        }
+        let kind = u32::from_le_bytes([req[0], req[1], req[2], req[3]]);
+        let sector = u64::from_le_bytes([req[8], req[9], req[10], req[11], req[12], req[13], req[14], req[15]]);
+        let data = &req[16..];
+        let size = self.capacity * SECTOR_SIZE;
+        //the byte offset of the request, None past the end of the disk.
+        let offset = sector.checked_mul(SECTOR_SIZE).filter(|o| *o <= size);
+        let image = match self.image.as_mut() {
+            Some(f) => f,
+            None if kind == T_GET_ID => return (S_OK, self.id()),
+            None => return (S_IOERR, Vec::new()),
+        };
+        match kind {
+            T_IN => {
+                //the status byte takes the last writable byte.
+                let len = chain.write_len().saturating_sub(1) as u64;
+                let offset = match offset {
+                    Some(o) if len <= size - o => o,
+                    _ => return (S_IOERR, Vec::new()),
+                };
+                let mut buf = vec![0u8; len as usize];
+                match image.seek(SeekFrom::Start(offset)).and_then(|_| image.read_exact(&mut buf)) {
+                    Ok(_) => (S_OK, buf),
+                    Err(e) => {
+                        eprintln!("virtio blk {}: read failed. {e}", self.name);
+                        (S_IOERR, Vec::new())
+                    },
+                }
+            },
+            T_OUT if self.readonly => (S_IOERR, Vec::new()),
+            T_OUT => {
+                let offset = match offset {
+                    Some(o) if data.len() as u64 <= size - o => o,
+                    _ => return (S_IOERR, Vec::new()),
+                };
+                match image.seek(SeekFrom::Start(offset)).and_then(|_| image.write_all(data)) {
+                    Ok(_) => (S_OK, Vec::new()),
+                    Err(e) => {
+                        eprintln!("virtio blk {}: write failed. {e}", self.name);
+                        (S_IOERR, Vec::new())
+                    },
+                }
+            },
+            T_FLUSH => match image.sync_data() {
+                Ok(_) => (S_OK, Vec::new()),
+                Err(_) => (S_IOERR, Vec::new()),
+            },
+            T_GET_ID => (S_OK, self.id()),
+            _ => (S_UNSUPP, Vec::new()),
+        }
+    }
+
+    fn id(&self) -> Vec<u8> {
+        let mut id = self.name.clone().into_bytes();
+        id.resize(ID_LEN, 0);
+        id
+    }
+}
+
+impl VirtioDevice for VirtioBlk {
+    fn device_id(&self) -> u32 {
+        VIRTIO_ID_BLOCK
+    }
+
+    fn features(&self) -> u64 {
+        F_FLUSH | if self.readonly { F_RO } else { 0 }
+    }
+
+    fn queues(&self) -> usize {
+        1
+    }
+
+    //capacity in sectors, the other fields are behind features not offered.
+    fn read_config(&self, offset: u32) -> u8 {
+        match offset {
+            0..=7 => (self.capacity >> (8 * offset)) as u8,
+            _ => 0,
+        }
+    }
+
+    fn notify(&mut self, queue: usize, vqs: &mut [Virtqueue], ctx: &mut PeripCtx) -> bool {
+        let mut intr = false;
+        while let Some(chain) = vqs[queue].pop(ctx) {
+            let (status, data) = match chain.read(ctx) {
+                Some(req) => self.request(&req, &chain),
+                None => (S_IOERR, Vec::new()),
+            };
+            //the data then the status byte, in the last writable byte.
+            let mut out = data;
+            out.resize(chain.write_len().saturating_sub(1) as usize, 0);
+            out.push(status);
+            let written = chain.write(ctx, &out);
+            intr |= vqs[queue].push(ctx, chain.head, written);
+        }
+        intr
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::VecDeque;
+
+use crate::chardev::{self, CharDev};
+use crate::config::CPerips;
+use crate::perips::virtio::{VirtioDevice, Virtqueue};
+use crate::perips::PeripCtx;
+
+const VIRTIO_ID_CONSOLE: u32 = 3;
+
+const RECEIVEQ: usize = 0;
+const TRANSMITQ: usize = 1;
+
+/// Virtio console device, `device` "console" of a virtio_mmio transport.
+///
+/// A single port: the transmit queue goes to the `chardev` arg (see
+/// `chardev::open`, default "stdio") and the host input fills the buffers
+/// of the receive queue as they are made available.
+pub struct VirtioConsole {
+    host: Box<dyn CharDev>,
+    input: VecDeque<u8>,
+}
+
+impl VirtioConsole {
+    pub fn new(cfg: &CPerips) -> Self {
+        Self::with_chardev(chardev::open(cfg.arg_str("chardev").unwrap_or("stdio")))
+    }
+
+    pub fn with_chardev(host: Box<dyn CharDev>) -> Self {
+        VirtioConsole { host, input: VecDeque::new() }
+    }
+
+    fn receive(&mut self, vq: &mut Virtqueue, ctx: &mut PeripCtx) -> bool {
+        let mut intr = false;
+        while !self.input.is_empty() {
+            let chain = match vq.pop(ctx) {
+                Some(c) => c,
+                None => break,
+            };
+            let n = (chain.write_len() as usize).min(self.input.len());
+            let data: Vec<u8> = self.input.drain(..n).collect();
+            let written = chain.write(ctx, &data);
+            intr |= vq.push(ctx, chain.head, written);
+        }
+        intr
+    }
+}
+
+impl VirtioDevice for VirtioConsole {
+    fn device_id(&self) -> u32 {
+        VIRTIO_ID_CONSOLE
+    }
+
+    fn queues(&self) -> usize {
+        2
+    }
+
+    //cols, rows and max_nr_ports are behind features not offered.
+    fn read_config(&self, _offset: u32) -> u8 {
+        0
+    }
+
+    fn notify(&mut self, queue: usize, vqs: &mut [Virtqueue], ctx: &mut PeripCtx) -> bool {
+        match queue {
+            TRANSMITQ => {
+                let mut intr = false;
+                while let Some(chain) = vqs[TRANSMITQ].pop(ctx) {
+                    if let Some(data) = chain.read(ctx) {
+                        for b in data.iter() {
+                            self.host.write_byte(*b);
+                        }
+                    }
+                    intr |= vqs[TRANSMITQ].push(ctx, chain.head, 0);
+                }
+                intr
+            },
+            RECEIVEQ => self.receive(&mut vqs[RECEIVEQ], ctx),
+            _ => false,
+        }
+    }
+
+    fn tick(&mut self, vqs: &mut [Virtqueue], ctx: &mut PeripCtx) -> bool {
+        while let Some(b) = self.host.read_byte() {
+            self.input.push_back(b);
+        }
+        //the receive buffers are taken only once the driver is ready for them.
+        if self.input.is_empty() || !vqs[RECEIVEQ].is_ready() {
+            return false;
+        }
+        self.receive(&mut vqs[RECEIVEQ], ctx)
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        if self.input.is_empty() && !self.host.has_input() { u64::MAX } else { 0 }
+    }
+
+    fn reset(&mut self) {
+        self.input.clear();
+    }
+}
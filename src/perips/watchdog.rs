@@ -0,0 +1,174 @@
+use crate::config::CPerips;
+use crate::perips::{PeripCtx, Peripheral};
+use crate::rv32_actor::SocEvent;
+
+const CTRL: u32 = 0x00;
+const LOAD: u32 = 0x04;
+const COUNT: u32 = 0x08;
+const KEY: u32 = 0x0c;
+const STATUS: u32 = 0x10;
+const WDT_SIZE: u32 = 0x14;
+
+const CTRL_EN: u32 = 0x01;
+
+const KEY_KICK: u32 = 0x5a5a_a5a5;
+const KEY_UNLOCK: u32 = 0x1acc_e551;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Intr,
+    Reset,
+    Stop,
+}
+
+/// Watchdog timer, class "watchdog".
+///
+/// `COUNT` counts down from `LOAD` while enabled, writing the kick key to
+/// `KEY` reloads it. `CTRL` and `LOAD` only take the write following the
+/// unlock key. On expiry `STATUS` bit 0 is set and the `action` arg picks
+/// what happens: "intr" raises `irq` (default), "reset" resets the soc
+/// (`keep_ram` arg, default true) and "stop" halts the simulation. Args
+/// `timeout` and `enable` arm the watchdog out of reset.
+pub struct Watchdog {
+    name: String,
+    start: u32,
+    irq: u32,
+    action: Action,
+    keep_ram: bool,
+    timeout: u32,
+    enable: bool,
+
+    ctrl: u32,
+    load: u32,
+    count: u32,
+    unlocked: bool,
+    status: u32,
+}
+
+impl Watchdog {
+    pub fn new(cfg: &CPerips) -> Self {
+        let action = match cfg.arg_str("action").unwrap_or("intr") {
+            "reset" => Action::Reset,
+            "stop" => Action::Stop,
+            "intr" => Action::Intr,
+            a => {
+                eprintln!("watchdog {} action {a} not supported, use intr.", cfg.name);
+                Action::Intr
+            },
+        };
+        let timeout = cfg.arg_u64("timeout", u32::MAX as u64) as u32;
+        let enable = cfg.arg_bool("enable", false);
+        Watchdog {
+            name: cfg.name.clone(),
+            start: cfg.start,
+            irq: cfg.irq,
+            action,
+            keep_ram: cfg.arg_bool("keep_ram", true),
+            timeout,
+            enable,
+            ctrl: if enable { CTRL_EN } else { 0 },
+            load: timeout,
+            count: timeout,
+            unlocked: false,
+            status: 0,
+        }
+    }
+
+    fn expire(&mut self, ctx: &mut PeripCtx) {
+        self.status |= 1;
+        self.count = self.load;
+        eprintln!("watchdog {} expired.", self.name);
+        match self.action {
+            Action::Intr => ctx.set_intr(self.irq, true),
+            Action::Reset => ctx.emit(SocEvent::Reset { perip: self.name.clone(), keep_ram: self.keep_ram }),
+            Action::Stop => ctx.emit(SocEvent::Halt { perip: self.name.clone(), code: 1 }),
+        }
+    }
+}
+
+impl Peripheral for Watchdog {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn in_range(&self, addr: u32) -> bool {
+        addr >= self.start && addr - self.start < WDT_SIZE
+    }
+
+    fn read_u32(&mut self, addr: u32, _ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            CTRL => self.ctrl,
+            LOAD => self.load,
+            COUNT => self.count,
+            STATUS => self.status,
+            _ => 0,
+        }
+    }
+
+    fn write_u32(&mut self, data: u32, addr: u32, ctx: &mut PeripCtx) {
+        match addr - self.start {
+            CTRL if self.unlocked => {
+                self.ctrl = data & CTRL_EN;
+                self.count = self.load;
+                self.unlocked = false;
+            },
+            LOAD if self.unlocked => {
+                self.load = data;
+                self.count = data;
+                self.unlocked = false;
+            },
+            KEY => match data {
+                KEY_KICK => self.count = self.load,
+                KEY_UNLOCK => self.unlocked = true,
+                _ => {},
+            },
+            STATUS => {
+                self.status &= !data;
+                ctx.set_intr(self.irq, self.status != 0 && self.action == Action::Intr);
+            },
+            _ => {},
+        }
+    }
+
+    fn merge_read(&mut self, addr: u32, ctx: &mut PeripCtx) -> u32 {
+        match addr - self.start {
+            STATUS => 0,
+            _ => self.read_u32(addr, ctx),
+        }
+    }
+
+    fn tick(&mut self, ctx: &mut PeripCtx) {
+        if self.ctrl & CTRL_EN == 0 {
+            return;
+        }
+        if self.count == 0 {
+            self.expire(ctx);
+        } else {
+            self.count -= 1;
+        }
+    }
+
+    fn idle_ticks(&self) -> u64 {
+        if self.ctrl & CTRL_EN == 0 { u64::MAX } else { self.count as u64 }
+    }
+
+    fn skip(&mut self, ticks: u64, _ctx: &mut PeripCtx) {
+        if self.ctrl & CTRL_EN != 0 {
+            self.count -= ticks as u32;
+        }
+    }
+
+    fn reset(&mut self, ctx: &mut PeripCtx) {
+        self.ctrl = if self.enable { CTRL_EN } else { 0 };
+        self.load = self.timeout;
+        self.count = self.timeout;
+        self.unlocked = false;
+        self.status = 0;
+        ctx.set_intr(self.irq, false);
+    }
+
+    fn dump(&self) -> String {
+        format!("{}: {:08X}, ctrl:{:X} load:{} count:{} status:{:X} action:{:?}",
+                self.name, self.start, self.ctrl, self.load, self.count, self.status, self.action)
+    }
+}
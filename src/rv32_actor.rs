@@ -1,25 +1,87 @@
+use std::collections::HashMap;
+
 mod com_reg;
 mod csr_reg;
+mod clock;
 pub mod cpu;
+mod semihost;
+mod usermode;
 
-use crate::intrrupt::IntrType;
+use crate::intrrupt::{IntrLines, IntrType};
+use crate::rv32_actor::clock::Clock;
 use crate::rv32_actor::cpu::Rv32Cpu;
-use crate::perips::Perips;
-use crate::mem::{Mem, MemIO};
+use crate::rv32_actor::semihost::Semihost;
+use crate::rv32_actor::usermode::UserMode;
+use crate::perips::{PeripCtx, Peripheral};
+use crate::mem::{self, Mem, MemIO};
 
 const REG_NAME:[&str; 32] = ["zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", 
                 "s0", "s1", "a0", "a1", "a2", "a3", "a4", "a5",
                 "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7",
                 "s8", "s9", "s10", "s11", "t3", "t4", "t5", "t6"];
 
+const USER_STACK_SIZE: u32 = 0x100000;
+
+macro_rules! trace {
+    ($cpu:expr, $($arg:tt)*) => {
+        if $cpu.trace() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Events reported to the hooks added with `Rv32Actor::add_hook`.
+#[derive(Debug, Clone)]
+pub enum SocEvent {
+    Instr { cpu: usize, pc: u32, instr: u32 },
+    Trap { cpu: usize, cause: u32, epc: u32 },
+    Pin { perip: String, pin: u32, level: bool },
+    /// A perip resets the soc, the ram is cleared unless `keep_ram`.
+    Reset { perip: String, keep_ram: bool },
+    /// A perip stops the simulation, `run_until` returns `StopReason::Halt`.
+    Halt { perip: String, code: u32 },
+}
+
+pub type SocHook = Box<dyn FnMut(&SocEvent)>;
+
+/// Why `Rv32Actor::run_until` returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    MaxTicks,
+    Breakpoint { cpu: usize, pc: u32 },
+    Condition,
+    Halt { code: u32 },
+}
+
 pub struct Rv32Actor {
     name: String,
-    tick_cnt: u32,
+    tick_cnt: u64,
+    trace: bool,
 
     cpus: Vec<Rv32Cpu>,
 
     mems: Vec<Mem>,
-    perips: Vec<Perips>,
+    perips: Vec<Box<dyn Peripheral>>,
+
+    intr: IntrLines,
+
+    images: Vec<(u32, Vec<u8>)>,
+    symbols: HashMap<String, u32>,
+    semihost: Semihost,
+    semihosting: bool,
+    fast_forward: bool,
+    //the soc ticks at the fastest clock, the cpus and perips on their edges.
+    soc_hz: u64,
+    cpu_clocks: Vec<Clock>,
+    perip_clocks: Vec<Clock>,
+    usermode: Option<UserMode>,
+
+    breakpoints: Vec<u32>,
+    //(cpu, pc), only that cpu stops.
+    cpu_breakpoints: Vec<(usize, u32)>,
+    hooks: Vec<SocHook>,
+    events: Vec<SocEvent>,
+    halt: Option<u32>,
 }
 
 impl Rv32Actor {
@@ -27,116 +89,545 @@ impl Rv32Actor {
         Rv32Actor{
                     name,
                     tick_cnt: 0,
+                    trace: true,
                     cpus: Vec::new(),
                     mems: Vec::new(),
                     perips: Vec::new(),
+                    intr: IntrLines::new(),
+                    images: Vec::new(),
+                    symbols: HashMap::new(),
+                    semihost: Semihost::default(),
+                    semihosting: false,
+                    fast_forward: true,
+                    soc_hz: 1,
+                    cpu_clocks: Vec::new(),
+                    perip_clocks: Vec::new(),
+                    usermode: None,
+                    breakpoints: Vec::new(),
+                    cpu_breakpoints: Vec::new(),
+                    hooks: Vec::new(),
+                    events: Vec::new(),
+                    halt: None,
                 }
     }
 
-    pub fn add_cpu(&mut self, cpu: Rv32Cpu) {
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    /// The cpu gets the next index as its mhartid, see `set_hartid`.
+    pub fn add_cpu(&mut self, mut cpu: Rv32Cpu) {
+        cpu.set_trace(self.trace);
+        cpu.set_hartid(self.cpus.len() as u32);
+        let clock = Clock::new(cpu.freq());
+        self.soc_hz = self.soc_hz.max(clock.hz());
+        self.cpu_clocks.push(clock);
         self.cpus.push(cpu);
+        self.intr.set_harts(self.cpus.len());
+    }
+
+    /// Set the mhartid of the cpu `cpu`, the clint, plic and clic address
+    /// the harts by it.
+    pub fn set_hartid(&mut self, cpu: usize, id: u32) {
+        if let Some(c) = self.cpus.get_mut(cpu) {
+            c.set_hartid(id);
+            self.intr.set_hart_id(cpu, id);
+        }
+    }
+
+    /// The index of the cpu with mhartid `id`.
+    pub fn hart_index(&self, id: u32) -> Option<usize> {
+        self.cpus.iter().position(|c| c.hartid() == id)
     }
 
     pub fn add_mem(&mut self, mem: Mem) {
         self.mems.push(mem);
     }
 
-    pub fn add_perips(&mut self, p: Perips) {
+    /// The perip ticks on every soc tick, see `add_clocked_perips`.
+    pub fn add_perips(&mut self, p: Box<dyn Peripheral>) {
+        self.add_clocked_perips(p, 0.0);
+    }
+
+    /// The perip ticks at `freq` MHz, 0 at the soc clock.
+    pub fn add_clocked_perips(&mut self, p: Box<dyn Peripheral>, freq: f32) {
+        let clock = Clock::new(freq);
+        self.soc_hz = self.soc_hz.max(clock.hz());
+        self.perip_clocks.push(clock);
         self.perips.push(p);
     }
 
+    /// Print every executed instruction, on by default.
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+        for cpu in self.cpus.iter_mut() {
+            cpu.set_trace(trace);
+        }
+    }
+
+    /// Serve the semihosting calls of the firmware, they give it access to
+    /// the host files.
+    pub fn set_semihosting(&mut self, on: bool) {
+        self.semihosting = on;
+    }
+
+    /// While all the harts wait in a `wfi`, `run_until` jumps over the ticks
+    /// the perips tell nothing happens in, see `Peripheral::idle_ticks`.
+    pub fn set_fast_forward(&mut self, on: bool) {
+        self.fast_forward = on;
+    }
+
+    /// What the semihosting SYS_GET_CMDLINE returns.
+    pub fn set_cmdline(&mut self, cmdline: &str) {
+        self.semihost.set_cmdline(cmdline);
+    }
+
+    pub fn add_hook<F: FnMut(&SocEvent) + 'static>(&mut self, hook: F) {
+        self.hooks.push(Box::new(hook));
+    }
+
+    pub fn add_breakpoint(&mut self, pc: u32) {
+        if !self.breakpoints.contains(&pc) {
+            self.breakpoints.push(pc);
+        }
+    }
+
+    /// A breakpoint only the cpu `cpu` stops at.
+    pub fn add_cpu_breakpoint(&mut self, cpu: usize, pc: u32) {
+        if !self.cpu_breakpoints.contains(&(cpu, pc)) {
+            self.cpu_breakpoints.push((cpu, pc));
+        }
+    }
+
+    /// Remove the breakpoints at `pc`, the ones of a single cpu too.
+    pub fn remove_breakpoint(&mut self, pc: u32) {
+        self.breakpoints.retain(|&b| b != pc);
+        self.cpu_breakpoints.retain(|&(_, b)| b != pc);
+    }
+
+    pub fn breakpoints(&self) -> &[u32] {
+        &self.breakpoints
+    }
+
+    pub fn cpu_breakpoints(&self) -> &[(usize, u32)] {
+        &self.cpu_breakpoints
+    }
+
     pub fn fill_mem(&mut self, m_index: usize, data: Vec<u8>, pos: u32) {
         if m_index < self.mems.len() {
             self.mems[m_index].fill(data, pos);
         }
     }
 
+    /// Copy an image to `addr`, false if it does not fit in the mems. A
+    /// loaded image is loaded again by a reset that clears the ram.
+    pub fn load_image(&mut self, addr: u32, data: &[u8]) -> bool {
+        let loaded = mem::write_bytes(&mut self.mems, addr, data);
+        if loaded {
+            self.images.push((addr, data.to_vec()));
+        }
+        loaded
+    }
+
+    pub fn load_bin_file(&mut self, path: &str, addr: u32) -> Result<(), Box<dyn std::error::Error>> {
+        let bytes = crate::bin_file::read_file(path)?;
+        if self.load_image(addr, &bytes) {
+            Ok(())
+        } else {
+            Err(format!("{path} does not fit in mem at {addr:08x}.").into())
+        }
+    }
+
+    /// Load the segments of an elf file and hand its symbols to the perips,
+    /// the cpus start (and restart after a reset) at its entry point, which
+    /// is returned.
+    pub fn load_elf_file(&mut self, path: &str) -> Result<u32, Box<dyn std::error::Error>> {
+        let elf = crate::elf_file::read_file(path)?;
+        for (addr, data) in elf.segments.iter() {
+            if !self.load_image(*addr, data) {
+                return Err(format!("{path} segment does not fit in mem at {addr:08x}.").into());
+            }
+        }
+        for cpu in self.cpus.iter_mut() {
+            cpu.set_pc(elf.entry);
+            cpu.set_rst_pc(elf.entry);
+        }
+        for p in self.perips.iter_mut() {
+            p.set_symbols(&elf.symbols);
+        }
+        self.symbols.extend(elf.symbols);
+        Ok(elf.entry)
+    }
+
+    /// Run a linux user program like `qemu-riscv32`: cpu0 starts at the
+    /// entry of the elf with argv, envp and auxv on a stack at the top of
+    /// the mems, and its `ecall`s are served on the host.
+    pub fn load_user_program(&mut self, path: &str, args: &[String], envs: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let entry = self.load_elf_file(path)?;
+        let brk = self.images.iter().map(|(a, d)| a + d.len() as u32).max().unwrap_or(0).next_multiple_of(4096);
+        let top = self.mems.iter().map(|m| m.end()).max().unwrap_or(0);
+        let sp = UserMode::setup_stack(&mut self.mems, top, entry, args, envs)
+                    .ok_or("no room for the user stack.")?;
+        let brk_limit = top.saturating_sub(USER_STACK_SIZE).max(brk);
+
+        let cpu = self.cpus.first_mut().ok_or("no cpu for the user program.")?;
+        cpu.set_rs(2, sp);
+        self.usermode = Some(UserMode::new(brk, brk_limit));
+        Ok(())
+    }
+
+    /// Load an elf file, or else a raw binary at `addr`.
+    pub fn load_file(&mut self, path: &str, addr: u32) -> Result<(), Box<dyn std::error::Error>> {
+        if crate::elf_file::is_elf(&crate::bin_file::read_file(path)?) {
+            self.load_elf_file(path).map(|_| ())
+        } else {
+            self.load_bin_file(path, addr)
+        }
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<u32> {
+        self.symbols.get(name).copied()
+    }
+
+    pub fn read_mem(&self, addr: u32, buf: &mut [u8]) -> bool {
+        mem::read_bytes(&self.mems, addr, buf)
+    }
+
+    pub fn write_mem(&mut self, addr: u32, data: &[u8]) -> bool {
+        mem::write_bytes(&mut self.mems, addr, data)
+    }
+
+    pub fn read_mem_u32(&self, addr: u32) -> Option<u32> {
+        let mut buf = [0u8; 4];
+        if self.read_mem(addr, &mut buf) {
+            Some(u32::from_le_bytes(buf))
+        } else {
+            None
+        }
+    }
+
+    pub fn write_mem_u32(&mut self, addr: u32, data: u32) -> bool {
+        self.write_mem(addr, &data.to_le_bytes())
+    }
+
+    /// Bus read, goes to the perips as the cpu load does.
+    pub fn bus_read_u32(&mut self, addr: u32) -> u32 {
+        let res = Rv32Actor::load(&mut self.mems, &mut self.perips, &mut self.intr, &mut self.events, addr, 4);
+        self.flush_events();
+        res
+    }
+
+    /// Bus write, goes to the perips as the cpu store does.
+    pub fn bus_write_u32(&mut self, addr: u32, data: u32) {
+        Rv32Actor::store(&mut self.mems, &mut self.perips, &mut self.intr, &mut self.events, addr, 4, data);
+        self.flush_events();
+    }
+
+    /// Drive a pin of a perips (e.g. a gpio input), false if it has no such pin.
+    pub fn set_pin(&mut self, name: &str, pin: u32, level: bool) -> bool {
+        let mut res = false;
+        if let Some(p) = self.perips.iter_mut().find(|p| p.name() == name) {
+            res = p.set_pin(pin, level, &mut PeripCtx::new(&mut self.mems, &mut self.intr, &mut self.events));
+        }
+        self.flush_events();
+        res
+    }
+
+    pub fn get_pin(&self, name: &str, pin: u32) -> Option<bool> {
+        self.perip(name)?.get_pin(pin)
+    }
+
+    pub fn cpu_count(&self) -> usize {
+        self.cpus.len()
+    }
+
+    pub fn cpu(&self, index: usize) -> Option<&Rv32Cpu> {
+        self.cpus.get(index)
+    }
+
+    pub fn cpu_mut(&mut self, index: usize) -> Option<&mut Rv32Cpu> {
+        self.cpus.get_mut(index)
+    }
+
+    pub fn perip(&self, name: &str) -> Option<&dyn Peripheral> {
+        self.perips.iter().find(|p| p.name() == name).map(|p| p.as_ref())
+    }
+
+    pub fn perip_mut(&mut self, name: &str) -> Option<&mut Box<dyn Peripheral>> {
+        self.perips.iter_mut().find(|p| p.name() == name)
+    }
+
+    /// A register of the boot hart, mhartid 0 (or else cpu0).
     pub fn get_rs(&self, index: u32) -> u32 {
-        self.cpus[0].get_rs(index)
+        self.get_cpu_rs(self.hart_index(0).unwrap_or(0), index)
+    }
+
+    /// A register of the cpu `cpu`, 0 if there is no such cpu.
+    pub fn get_cpu_rs(&self, cpu: usize, index: u32) -> u32 {
+        self.cpus.get(cpu).map_or(0, |c| c.get_rs(index))
     }
 
-    pub fn get_tick(&self) -> u32 {
+    /// The soc ticks, at the fastest clock of the cpus and perips.
+    pub fn get_tick(&self) -> u64 {
         self.tick_cnt
     }
 
-    fn handle_exception(&mut self) {
-        let mut intrrupt_en = false;
+    /// The simulated seconds since the soc was built, resets don't stop it.
+    pub fn sim_time(&self) -> f64 {
+        self.tick_cnt as f64 / self.soc_hz as f64
+    }
+
+    /// The clock edges the cpu `cpu` had, it executes on them.
+    pub fn cpu_cycles(&self, cpu: usize) -> u64 {
+        self.cpu_clocks.get(cpu).map_or(0, |c| c.cycles())
+    }
 
+    /// Reset the cpus and the perips, a reset without `keep_ram` clears the
+    /// mems and loads the images again.
+    pub fn reset(&mut self, keep_ram: bool) {
         for cpu in self.cpus.iter_mut() {
-            let mut status = cpu.read_csr(0x300);//mstatus
-            if status & 0x08 == 0x08 {
-                match cpu.exception() {
-                    IntrType::None => {intrrupt_en = false;},
-                    _ => {intrrupt_en = true;},
-                };
+            cpu.reset();
+        }
+        if !keep_ram {
+            for m in self.mems.iter_mut() {
+                m.clear();
+            }
+            for (addr, data) in self.images.iter() {
+                mem::write_bytes(&mut self.mems, *addr, data);
+            }
+        }
+        self.intr.clear();
+        let mut ctx = PeripCtx::new(&mut self.mems, &mut self.intr, &mut self.events);
+        for p in self.perips.iter_mut() {
+            p.reset(&mut ctx);
+        }
+        self.flush_events();
+    }
 
-                if intrrupt_en {
-                    cpu.write_csr(0x341, cpu.get_pc());//mepc
-                    cpu.write_csr(0x342, 0x02);//mcause
-                    status &= !0x08;
-                    cpu.write_csr(0x300, status);//mstatus
+    /// Tick until `stop` returns true, a cpu reaches a breakpoint or
+    /// `max_ticks` ticks are done. At least one tick is always run, so a
+    /// stop at a breakpoint can be resumed. The ticks skipped by the fast
+    /// forward count but `stop` only sees the one after them.
+    pub fn run_until<F>(&mut self, max_ticks: u32, mut stop: F) -> StopReason
+    where
+        F: FnMut(&Rv32Actor) -> bool,
+    {
+        let mut ticks = 0;
+        while ticks < max_ticks {
+            let skip = self.idle_ticks().min((max_ticks - ticks - 1) as u64) as u32;
+            if skip > 0 {
+                self.skip(skip);
+                ticks += skip;
+            }
+            self.tick();
+            ticks += 1;
 
-                    let pc = cpu.read_csr(0x305);//mtvec
-                    cpu.set_pc(pc);
-                    cpu.set_exception(IntrType::None);
+            if let Some(code) = self.halt.take() {
+                return StopReason::Halt { code };
+            }
+            if stop(self) {
+                return StopReason::Condition;
+            }
+            for (i, cpu) in self.cpus.iter().enumerate() {
+                if self.breakpoints.contains(&cpu.get_pc()) || self.cpu_breakpoints.contains(&(i, cpu.get_pc())) {
+                    return StopReason::Breakpoint { cpu: i, pc: cpu.get_pc() };
                 }
             }
         }
-        // todo!
-        if intrrupt_en {
-            return;
+        StopReason::MaxTicks
+    }
+
+    pub fn run(&mut self, max_ticks: u32) -> StopReason {
+        self.run_until(max_ticks, |_| false)
+    }
+
+    //the soc ticks to skip, 0 unless all the harts wait. A clock that had no
+    //edge since the interrupts changed stops it before its next one.
+    fn idle_ticks(&self) -> u64 {
+        if !self.fast_forward || self.cpus.is_empty() || !self.cpus.iter().all(|c| c.is_waiting()) {
+            return 0;
+        }
+        let cpus = self.cpu_clocks.iter().filter(|c| c.is_stale()).map(|c| c.ticks_for(0, self.soc_hz));
+        let perips = self.perips.iter().zip(self.perip_clocks.iter()).map(|(p, c)| {
+            c.ticks_for(if c.is_stale() { 0 } else { p.idle_ticks() }, self.soc_hz)
+        });
+        cpus.chain(perips).min().unwrap_or(u64::MAX)
+    }
+
+    fn skip(&mut self, ticks: u32) {
+        if self.trace {
+            println!("--- @ {}, skip {} ticks from tick: {} ---", self.name, ticks, self.tick_cnt);
+        }
+        for c in self.cpu_clocks.iter_mut() {
+            c.skip(ticks as u64, self.soc_hz);
+        }
+        let mut ctx = PeripCtx::new(&mut self.mems, &mut self.intr, &mut self.events);
+        for (p, c) in self.perips.iter_mut().zip(self.perip_clocks.iter_mut()) {
+            let edges = c.skip(ticks as u64, self.soc_hz);
+            if edges > 0 {
+                p.skip(edges, &mut ctx);
+            }
+        }
+        self.tick_cnt += ticks as u64;
+    }
+
+    fn emit(hooks: &mut [SocHook], event: SocEvent) {
+        for hook in hooks.iter_mut() {
+            hook(&event);
         }
+    }
 
-        for p in self.perips.iter_mut() {
-            if p.get_intr() & 0x80000000 == 0x80000000 {
-                intrrupt_en = true;
-                p.clear_intr();
-                break;
+    //report the events raised by the perips, then do the resets and halts they ask for.
+    fn flush_events(&mut self) {
+        let mut reset = None;
+        for event in std::mem::take(&mut self.events) {
+            match &event {
+                SocEvent::Reset { perip, keep_ram } => {
+                    eprintln!("{perip} reset the soc at tick {}.", self.tick_cnt);
+                    reset = Some(*keep_ram);
+                },
+                SocEvent::Halt { perip, code } => {
+                    eprintln!("{perip} halt the soc at tick {}, code {code}.", self.tick_cnt);
+                    for cpu in self.cpus.iter() {
+                        eprintln!("{} pc: {:x}", cpu.name(), cpu.get_pc());
+                    }
+                    self.halt = Some(*code);
+                },
+                _ => {},
             }
+            Rv32Actor::emit(&mut self.hooks, event);
+        }
+        if let Some(keep_ram) = reset {
+            self.reset(keep_ram);
         }
+    }
 
-        if intrrupt_en {
-            let mut status = self.cpus[0].read_csr(0x300);//mstatus
-            let pc = self.cpus[0].get_pc();
-            self.cpus[0].write_csr(0x341, pc);//mepc
-            self.cpus[0].write_csr(0x342, 0x80000008);//mcause
-            status &= !0x08;
-            self.cpus[0].write_csr(0x300, status);//mstatus
+    //the synchronous exception of the last instruction goes first, then the
+    //pending interrupts when mstatus.MIE is set. Any of them wakes a wfi.
+    //In clic mode the clic interrupts preempt by level instead of the mip ones.
+    fn handle_exception(&mut self) {
+        for (i, cpu) in self.cpus.iter_mut().enumerate() {
+            cpu.set_mip_hw(self.intr.hart_pending(i));
+            cpu.set_clic(self.intr.hart_clic(i));
+            //a hart takes the traps on the edges of its clock.
+            if !self.cpu_clocks[i].is_due() {
+                continue;
+            }
 
-            let pc = self.cpus[0].read_csr(0x305);//mtvec
-            self.cpus[0].set_pc(pc);
-            self.cpus[0].set_exception(IntrType::None);
+            let (intr, clic) = if cpu.is_clic() {
+                let clic = cpu.clic_interrupt();
+                (clic.map(|c| c.id), clic)
+            } else {
+                (cpu.pending_interrupt(), None)
+            };
+            if intr.is_some() {
+                cpu.set_waiting(false);
+            }
+            let trap = match cpu.exception().cause() {
+                Some(c) => Some(c),
+                None if cpu.read_csr(0x300) & 0x08 != 0 => intr.map(|c| (0x80000000 | c, 0)),//mstatus.MIE
+                None => None,
+            };
+
+            if let Some((cause, tval)) = trap {
+                let epc = cpu.get_pc();
+                match clic {
+                    Some(c) if cause & 0x80000000 != 0 => {
+                        //the hardware vectored ones fetch their entry of the mtvt table.
+                        let entry = if c.shv {
+                            self.intr.ack_clic(i, c.id);
+                            let addr = cpu.read_csr(0x307).wrapping_add(4 * c.id);//mtvt
+                            let entry = self.mems.iter().find(|m| m.in_range(addr)).map(|m| m.read_u32(addr));
+                            if entry.is_none() {
+                                println!("clic vector table entry at {:x} is outside the mems.", addr);
+                            }
+                            entry
+                        } else {
+                            None
+                        };
+                        cpu.trap_clic(c, entry);
+                    },
+                    _ => cpu.trap(cause, tval),
+                }
+                trace!(cpu, "trap cause {:x} at {:x}, to {:x}", cause, epc, cpu.get_pc());
+                Rv32Actor::emit(&mut self.hooks, SocEvent::Trap { cpu: i, cause, epc });
+            }
         }
     }
 
-    fn read_instr(mems: &Vec<Mem>, pc: u32) -> u32 {
+    fn read_instr(mems: &[Mem], pc: u32) -> u32 {
         for m in mems.iter() {
             if m.in_range(pc) {
                 return m.read_u32(pc);
             }
         }
-        return 0;
+        0
     }
 
     pub fn tick(&mut self) {
-        println!("--- @ {}, tick: {} ---", self.name, self.tick_cnt);
-        for cpu in self.cpus.iter_mut() {
+        if self.trace {
+            println!("--- @ {}, tick: {} ---", self.name, self.tick_cnt);
+        }
+        for c in self.cpu_clocks.iter_mut().chain(self.perip_clocks.iter_mut()) {
+            c.tick(self.soc_hz);
+        }
+        for (i, cpu) in self.cpus.iter_mut().enumerate() {
+            if cpu.is_waiting() || !self.cpu_clocks[i].is_due() {
+                continue;
+            }
             let pc = cpu.get_pc();
             let instr = Rv32Actor::read_instr(&self.mems, pc);
             if instr != 0 {
-                println!("[{}] pc: {:x}, instr: {:08x}", cpu.name(), pc, instr);
-                Rv32Actor::execute(cpu, pc, instr, &mut self.mems, &mut self.perips);
+                trace!(cpu, "[{}] pc: {:x}, instr: {:08x}", cpu.name(), pc, instr);
+                Rv32Actor::emit(&mut self.hooks, SocEvent::Instr { cpu: i, pc, instr });
+                match instr {
+                    semihost::EBREAK if self.semihosting
+                                && Rv32Actor::read_instr(&self.mems, pc.wrapping_sub(4)) == semihost::ENTRY
+                                && Rv32Actor::read_instr(&self.mems, pc.wrapping_add(4)) == semihost::EXIT => {
+                        trace!(cpu, "semihosting call {:x}", cpu.get_rs(10));
+                        self.semihost.call(cpu, &mut self.mems, self.cpu_clocks[i].cycles(), &mut self.events);
+                        cpu.set_pc(pc.wrapping_add(4));
+                    },
+                    usermode::ECALL if self.usermode.is_some() => {
+                        trace!(cpu, "syscall {}", cpu.get_rs(17));
+                        if let Some(u) = self.usermode.as_mut() {
+                            u.call(cpu, &mut self.mems, self.cpu_clocks[i].cycles(), &mut self.events);
+                        }
+                        cpu.set_pc(pc.wrapping_add(4));
+                    },
+                    _ => Rv32Actor::execute(cpu, pc, instr, &mut self.mems, &mut self.perips, &mut self.intr, &mut self.events),
+                }
+                if let Some(id) = cpu.take_clic_ack() {
+                    self.intr.ack_clic(i, id);
+                }
             } else {
                 println!("read code failed at pc: {:x}", pc);
                 cpu.set_exception(IntrType::ExceMem(pc));
             }
         }
+
+        //a perip reaches the others over the bus while it ticks.
+        for i in 0..self.perips.len() {
+            if !self.perip_clocks[i].is_due() {
+                continue;
+            }
+            let (before, rest) = self.perips.split_at_mut(i);
+            let (p, after) = rest.split_at_mut(1);
+            let mut ctx = PeripCtx::with_bus(&mut self.mems, &mut self.intr, &mut self.events, before, after);
+            p[0].tick(&mut ctx);
+        }
         self.tick_cnt += 1;
 
         self.handle_exception();
+        if self.intr.take_changed() {
+            for c in self.cpu_clocks.iter_mut().chain(self.perip_clocks.iter_mut()) {
+                c.set_stale();
+            }
+        }
+        self.flush_events();
     }
 
-    fn execute(cpu: &mut Rv32Cpu, pc: u32, instr: u32, mems: &mut Vec<Mem>, perips: &mut Vec<Perips>) {
+    fn execute(cpu: &mut Rv32Cpu, pc: u32, instr: u32, mems: &mut [Mem], perips: &mut [Box<dyn Peripheral>], intr: &mut IntrLines, events: &mut Vec<SocEvent>) {
         //opcode = instr[6:0];
         match instr & 0x7f {
             //lui 7'b0110111
@@ -173,12 +664,12 @@ impl Rv32Actor {
             },
             //load, 7'b0000011
             0x03 => {
-                Rv32Actor::execute_load(cpu, instr, mems, perips);
+                Rv32Actor::execute_load(cpu, instr, mems, perips, intr, events);
                 cpu.set_pc(pc.wrapping_add(4));
             },
             //store, 7'b0100011
             0x23 => {
-                Rv32Actor::execute_store(cpu, instr, mems, perips);
+                Rv32Actor::execute_store(cpu, instr, mems, perips, intr, events);
                 cpu.set_pc(pc.wrapping_add(4));
             },
             //fence  7'b0001111
@@ -191,23 +682,30 @@ impl Rv32Actor {
                 Rv32Actor::execute_sys(cpu, instr);
             },
             //others
-            _ => {
-                panic!("pc: {:x} inllegal instruction. {:08x}.", pc, instr);
-            },
+            _ => Rv32Actor::illegal(cpu, instr),
         }
+        //an illegal instruction traps with mepc at it.
+        if matches!(cpu.exception(), IntrType::ExceInstruction(_)) {
+            cpu.set_pc(pc);
+        }
+    }
+
+    fn illegal(cpu: &mut Rv32Cpu, instr: u32) {
+        trace!(cpu, "illegal instruction {:08x}", instr);
+        cpu.set_exception(IntrType::ExceInstruction(instr));
     }
 
     fn execute_lui(cpu: &mut Rv32Cpu, instr: u32) {
         let imm = instr & 0xfffff000;
         let rd = cpu.set_rd(instr, imm);
-        println!("lui {}, {:x}", REG_NAME[rd], imm);
+        trace!(cpu, "lui {}, {:x}", REG_NAME[rd], imm);
     }
 
     fn execute_auipc(cpu: &mut Rv32Cpu, instr: u32) {
         let pc = cpu.get_pc();
         let imm = instr & 0xfffff000;
         let rd = cpu.set_rd(instr, imm + pc);
-        println!("auipc {}, {:x}", REG_NAME[rd], imm);
+        trace!(cpu, "auipc {}, {:x}", REG_NAME[rd], imm);
     }
 
     fn execute_jal(cpu: &mut Rv32Cpu, instr: u32) {
@@ -218,7 +716,7 @@ impl Rv32Actor {
         let offset = if instr & 0x80000000 == 0x80000000 {0xfff00000 | imm } else { imm };
         let rd = cpu.set_rd(instr, pc + 4);
         cpu.set_pc(pc.wrapping_add(offset));
-        println!("jal {}, {}", REG_NAME[rd], offset as i32);
+        trace!(cpu, "jal {}, {}", REG_NAME[rd], offset as i32);
     }
 
     fn execute_jalr(cpu: &mut Rv32Cpu, instr: u32) {
@@ -228,7 +726,7 @@ impl Rv32Actor {
         let rd = cpu.set_rd(instr, pc + 4);
         let (rs1, r1_data) = cpu.get_rs_1(instr);
         cpu.set_pc((r1_data.wrapping_add(offset)) & 0xfffffffe);
-        println!("jalr {}, {}({})", REG_NAME[rd], offset as i32, REG_NAME[rs1]);
+        trace!(cpu, "jalr {}, {}({})", REG_NAME[rd], offset as i32, REG_NAME[rs1]);
     }
 
     fn execute_jb(cpu: &mut Rv32Cpu, instr: u32) {
@@ -247,17 +745,17 @@ impl Rv32Actor {
                 } else {
                     cpu.set_pc(pc.wrapping_add(4));
                 }
-                println!("beq {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
+                trace!(cpu, "beq {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
             },
             //bne 3'b001
             0x01 => {
                 if rs1_data != rs2_data { 
                     cpu.set_pc(pc.wrapping_add(offset)); 
-                    // println!("bne pc: {:x}", pc);
+                    // trace!(cpu, "bne pc: {:x}", pc);
                 } else {
                     cpu.set_pc(pc.wrapping_add(4));
                 }
-                println!("bne {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
+                trace!(cpu, "bne {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
             },
             //blt 3'b100
             0x04 => {
@@ -266,7 +764,7 @@ impl Rv32Actor {
                 } else {
                     cpu.set_pc(pc.wrapping_add(4));
                 }
-                println!("blt {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
+                trace!(cpu, "blt {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
             },
             //bge 3'b101
             0x05 => {
@@ -275,7 +773,7 @@ impl Rv32Actor {
                 } else {
                     cpu.set_pc(pc.wrapping_add(4));
                 }
-                println!("bge {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
+                trace!(cpu, "bge {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
             },
             //bltu 3'b110
             0x06 => {
@@ -284,7 +782,7 @@ impl Rv32Actor {
                 } else {
                     cpu.set_pc(pc.wrapping_add(4)); 
                 }
-                println!("bltu {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
+                trace!(cpu, "bltu {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
             },
             //bgeu 3'b111
             0x07 => {
@@ -293,10 +791,10 @@ impl Rv32Actor {
                 } else {
                     cpu.set_pc(pc.wrapping_add(4));
                 }
-                println!("bgeu {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
+                trace!(cpu, "bgeu {}, {}, {}", REG_NAME[rs1], REG_NAME[rs2], offset as i32);
             },
             //others
-            _ => panic!("jb illegal instruction. {:08x}.", instr),
+            _ => Rv32Actor::illegal(cpu, instr),
         }
     }
 
@@ -310,39 +808,39 @@ impl Rv32Actor {
             //addi 3'b000
             0x00 => {
                 let rd = cpu.set_rd(instr, rs1_data.wrapping_add(s_imm));
-                println!("addi {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm as i32);
+                trace!(cpu, "addi {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm as i32);
             },
             //slti 3'b010
             0x02 => {
                 let rd_data = if (rs1_data as i32) < (s_imm as i32) {1} else {0};
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("slti {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm as i32);
+                trace!(cpu, "slti {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm as i32);
             },
             //sltiu 3'b011
             0x03 => {
                 let rd_data = if rs1_data < s_imm {1} else {0};
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("sltiu {}, {}, {:08x}", REG_NAME[rd], REG_NAME[rs1], s_imm);
+                trace!(cpu, "sltiu {}, {}, {:08x}", REG_NAME[rd], REG_NAME[rs1], s_imm);
             },
             //xori 3'b100
             0x04 => {
                 let rd = cpu.set_rd(instr, rs1_data ^ s_imm);
-                println!("xori {}, {}, {:08x}", REG_NAME[rd], REG_NAME[rs1], s_imm);
+                trace!(cpu, "xori {}, {}, {:08x}", REG_NAME[rd], REG_NAME[rs1], s_imm);
             },
             //ori 3'b110
             0x06 => {
                 let rd = cpu.set_rd(instr, rs1_data | s_imm);
-                println!("ori {}, {}, {:08x}", REG_NAME[rd], REG_NAME[rs1], s_imm);
+                trace!(cpu, "ori {}, {}, {:08x}", REG_NAME[rd], REG_NAME[rs1], s_imm);
             },
             //andi 3'b111
             0x07 => {
                 let rd = cpu.set_rd(instr, rs1_data & s_imm);
-                println!("andi {}, {}, {:08x}", REG_NAME[rd], REG_NAME[rs1], s_imm);
+                trace!(cpu, "andi {}, {}, {:08x}", REG_NAME[rd], REG_NAME[rs1], s_imm);
             },
             //slli 3'b001
             0x01 => {
                 let rd = cpu.set_rd(instr, rs1_data << (s_imm & 0x1f));
-                println!("slli {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm & 0x1f);
+                trace!(cpu, "slli {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm & 0x1f);
             },
             //srli srai 3'b101
             0x05 => {
@@ -350,21 +848,19 @@ impl Rv32Actor {
                     //srli 7'b000_0000
                     0x00 => {
                         let rd = cpu.set_rd(instr, rs1_data >> (s_imm & 0x1f));
-                        println!("srli {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm & 0x1f);
+                        trace!(cpu, "srli {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm & 0x1f);
                     },
                     //srai 7'b010_0000
                     0x20 => {
                         let rd_data = ((rs1_data as i32) >> (s_imm & 0x1f)) as u32;
                         let rd = cpu.set_rd(instr, rd_data);
-                        println!("srai {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm & 0x1f);
+                        trace!(cpu, "srai {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], s_imm & 0x1f);
                     }
-                    _ => {
-                        println!("math i sr illegal instruction. {:08x}.", instr)
-                    },
+                    _ => Rv32Actor::illegal(cpu, instr),
                 }
             },
             //others
-            _ => panic!("math i illegal instruction. {:08x}.", instr),
+            _ => Rv32Actor::illegal(cpu, instr),
         }
     }
 
@@ -376,100 +872,149 @@ impl Rv32Actor {
             //add sub mul 3'b000
             (0x00, 0x00) => {
                 let rd = cpu.set_rd(instr, rs1_data.wrapping_add(rs2_data));
-                println!("add {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "add {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x00, 0x20) => {
                 let rd = cpu.set_rd(instr, rs1_data.wrapping_sub(rs2_data));
-                println!("sub {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "sub {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x00, 0x01) => {
                 let rd_data = rs1_data.wrapping_mul(rs2_data);
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("mul {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "mul {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             //sll mulh 3'b001
             (0x01, 0x00) => {
                 let rd = cpu.set_rd(instr, rs1_data << (rs2_data & 0x1f));
-                println!("sll {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "sll {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x01, 0x01) => {
                 let rd_data: u32 = (((rs1_data as i32 as i64) * (rs2_data as i32 as i64)) >> 32) as u32;
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("mulh {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "mulh {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             //slt mulhsu 3'b010
             (0x02, 0x00) => {
                 let rd_data = if (rs1_data as i32) < (rs2_data as i32) {1} else {0};
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("slt {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "slt {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x02, 0x01) => {
                 let rd_data = (((rs1_data as i32 as i64) * (rs2_data as i64)) >> 32) as u32;
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("mulhsu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "mulhsu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             //sltu mulhu 3'b011
             (0x03, 0x00) => {
                 let rd_data = if rs1_data < rs2_data {1} else {0};
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("sltu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "sltu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x03, 0x01) => {
                 let rd_data = (((rs1_data as u64) * (rs2_data as u64)) >> 32) as u32;
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("mulhu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "mulhu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             //xor div 3'b100
             (0x04, 0x00) => {
                 let rd = cpu.set_rd(instr, rs1_data ^ rs2_data);
-                println!("xor {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "xor {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x04, 0x01) => {
-                let rd_data: u32 = if rs2_data != 0 {((rs1_data as i32) / (rs2_data as i32)) as u32} else {0xffffffff};
+                //i32::MIN / -1 overflows to i32::MIN.
+                let rd_data: u32 = if rs2_data != 0 {(rs1_data as i32).wrapping_div(rs2_data as i32) as u32} else {0xffffffff};
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("div {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "div {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             //or rem 3'b110
             (0x06, 0x00) => {
                 let rd = cpu.set_rd(instr, rs1_data | rs2_data);
-                println!("or {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "or {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x06, 0x01) => {
-                let rd_data: u32 = if rs2_data != 0 {((rs1_data as i32) % (rs2_data as i32)) as u32} else {rs1_data};
+                let rd_data: u32 = if rs2_data != 0 {(rs1_data as i32).wrapping_rem(rs2_data as i32) as u32} else {rs1_data};
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("rem {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "rem {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             }
             //and remu 3'b111
             (0x07, 0x00) => {
                 let rd = cpu.set_rd(instr, rs1_data & rs2_data);
-                println!("and {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "and {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x07, 0x01) => {
                 let rd_data: u32 = if rs2_data!= 0 {rs1_data % rs2_data} else {rs1_data};
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("remu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "remu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             //srl sra divu 3'b101
             (0x05, 0x00) => {
                 let rd = cpu.set_rd(instr, rs1_data >> (rs2_data & 0x1f));
-                println!("srl {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "srl {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x05, 0x20) => {
                 let rd_data = ((rs1_data as i32) >> (rs2_data & 0x1f)) as u32;
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("sra {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "sra {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             (0x05, 0x01) => {
-                let rd_data: u32 = if rs2_data!= 0 {rs1_data / rs2_data} else {0xffffffff};
+                let rd_data: u32 = rs1_data.checked_div(rs2_data).unwrap_or(0xffffffff);
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("divu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
+                trace!(cpu, "divu {}, {}, {}", REG_NAME[rd], REG_NAME[rs1], REG_NAME[rs2]);
             },
             //others
-            _ => panic!("math i illegal instruction. {:08x}.", instr),
+            _ => Rv32Actor::illegal(cpu, instr),
+        }
+    }
+
+    //bus read of 1, 2 or 4 bytes, the perips take precedence over the mems.
+    fn load(mems: &mut [Mem], perips: &mut [Box<dyn Peripheral>], intr: &mut IntrLines, events: &mut Vec<SocEvent>, addr: u32, size: u32) -> u32 {
+        for p in perips.iter_mut() {
+            if p.in_range(addr) {
+                let mut ctx = PeripCtx::new(mems, intr, events);
+                return match size {
+                    1 => p.read_u8(addr, &mut ctx) as u32,
+                    2 => p.read_u16(addr, &mut ctx) as u32,
+                    _ => p.read_u32(addr, &mut ctx),
+                };
+            }
+        }
+        for m in mems.iter() {
+            if m.in_range(addr) {
+                return match size {
+                    1 => m.read_u8(addr) as u32,
+                    2 => m.read_u16(addr) as u32,
+                    _ => m.read_u32(addr),
+                };
+            }
+        }
+        0
+    }
+
+    fn store(mems: &mut [Mem], perips: &mut [Box<dyn Peripheral>], intr: &mut IntrLines, events: &mut Vec<SocEvent>, addr: u32, size: u32, data: u32) {
+        for p in perips.iter_mut() {
+            if p.in_range(addr) {
+                let mut ctx = PeripCtx::new(mems, intr, events);
+                match size {
+                    1 => p.write_u8(data as u8, addr, &mut ctx),
+                    2 => p.write_u16(data as u16, addr, &mut ctx),
+                    _ => p.write_u32(data, addr, &mut ctx),
+                }
+                return;
+            }
+        }
+        for m in mems.iter_mut() {
+            if m.in_range(addr) {
+                match size {
+                    1 => m.write_u8(data as u8, addr),
+                    2 => m.write_u16(data as u16, addr),
+                    _ => m.write_u32(data, addr),
+                }
+                return;
+            }
         }
     }
 
-    fn execute_load(cpu: &mut Rv32Cpu, instr: u32, mems: &Vec<Mem>, perips: &Vec<Perips>) {
+    fn execute_load(cpu: &mut Rv32Cpu, instr: u32, mems: &mut [Mem], perips: &mut [Box<dyn Peripheral>], intr: &mut IntrLines, events: &mut Vec<SocEvent>) {
         let (rs1, rs1_data) = cpu.get_rs_1(instr);
         let imm = (instr>>20) & 0x00000fff;
         let s_imm = if instr & 0x80000000 == 0x80000000 { 0xfffff000 | imm } else { imm };
@@ -478,80 +1023,40 @@ impl Rv32Actor {
         match instr>>12 & 0x07 {
             //lb 3'b000
             0x00 => {
-                let mut rd_data = 0;
-                // self.mems.iter().for_each(|m| {
-                //     if m.in_range(r_addr) {
-                //         rd_data = m.read_u8(r_addr) as i8 as i32;
-                // }});
-                for m in mems.iter() {
-                    if m.in_range(r_addr) {
-                        rd_data = m.read_u8(r_addr) as i8 as i32;
-                        break;
-                    }
-                }
+                let rd_data = Rv32Actor::load(mems, perips, intr, events, r_addr, 1) as u8 as i8 as i32;
                 let rd = cpu.set_rd(instr, rd_data as u32);
-                println!("lb {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
+                trace!(cpu, "lb {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
             },
             //lbu 3'b100
             0x04 => {
-                let mut rd_data = 0;
-                for m in mems.iter() {
-                    if m.in_range(r_addr) {
-                        rd_data = m.read_u8(r_addr);
-                        break;
-                    }
-                }
-                let rd = cpu.set_rd(instr, rd_data as u32);
-                println!("lbu {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
+                let rd_data = Rv32Actor::load(mems, perips, intr, events, r_addr, 1);
+                let rd = cpu.set_rd(instr, rd_data);
+                trace!(cpu, "lbu {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
             },
             //lh 3'b001
             0x01 => {
-                let mut rd_data = 0;
-                for m in mems.iter() {
-                    if m.in_range(r_addr) {
-                        rd_data = m.read_u16(r_addr) as i16 as i32;
-                        break;
-                    }
-                }
+                let rd_data = Rv32Actor::load(mems, perips, intr, events, r_addr, 2) as u16 as i16 as i32;
                 let rd = cpu.set_rd(instr, rd_data as u32);
-                println!("lh {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
+                trace!(cpu, "lh {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
             },
             //lhu 3'b101
             0x05 => {
-                let mut rd_data = 0;
-                for m in mems.iter() {
-                    if m.in_range(r_addr) {
-                        rd_data = m.read_u16(r_addr);
-                        break;
-                    }
-                }
-                let rd = cpu.set_rd(instr, rd_data as u32);
-                println!("lhu {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
+                let rd_data = Rv32Actor::load(mems, perips, intr, events, r_addr, 2);
+                let rd = cpu.set_rd(instr, rd_data);
+                trace!(cpu, "lhu {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
             },
             //lw 3'b010
             0x02 => {
-                let mut rd_data = 0 ;
-                for m in mems.iter() {
-                    if m.in_range(r_addr) {
-                        rd_data = m.read_u32(r_addr);
-                        break;
-                    }
-                }
-                for p in perips.iter() {
-                    if p.in_range(r_addr) {
-                        rd_data = p.read_u32(r_addr);
-                        break;
-                    }
-                }
+                let rd_data = Rv32Actor::load(mems, perips, intr, events, r_addr, 4);
                 let rd = cpu.set_rd(instr, rd_data);
-                println!("lw {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
+                trace!(cpu, "lw {}, {}({})", REG_NAME[rd], s_imm as i32, REG_NAME[rs1]);
             },
             //others
-            _ => panic!("load illegal instruction. {:08x}.", instr),
+            _ => Rv32Actor::illegal(cpu, instr),
         }
     }
 
-    fn execute_store(cpu: &mut Rv32Cpu, instr: u32, mems: &mut Vec<Mem>, perips: &mut Vec<Perips>) {
+    fn execute_store(cpu: &mut Rv32Cpu, instr: u32, mems: &mut [Mem], perips: &mut [Box<dyn Peripheral>], intr: &mut IntrLines, events: &mut Vec<SocEvent>) {
         let (rs1, rs1_data) = cpu.get_rs_1(instr);
         let (rs2, rs2_data) = cpu.get_rs_2(instr);
         let imm = ((instr>>20) & 0x000007e0) | ((instr>>7) & 0x0000001f);
@@ -561,56 +1066,35 @@ impl Rv32Actor {
         match instr>>12 & 0x07 {
             //sb 3'b000
             0x00 => {
-                for m in mems.iter_mut() {
-                    if m.in_range(wr_addr) {
-                        m.write_u8((rs2_data & 0xff) as u8, wr_addr);
-                        break;
-                    }
-                }
-                println!("sb {}, {}({})", REG_NAME[rs2], s_imm as i32, REG_NAME[rs1]);
+                Rv32Actor::store(mems, perips, intr, events, wr_addr, 1, rs2_data);
+                trace!(cpu, "sb {}, {}({})", REG_NAME[rs2], s_imm as i32, REG_NAME[rs1]);
             },
             //sh 3'b001
             0x01 => {
-                for m in mems.iter_mut() {
-                    if m.in_range(wr_addr) {
-                        m.write_u16((rs2_data & 0xffff) as u16, wr_addr);
-                        break;
-                    }
-                }
-                println!("sh {}, {}({})", REG_NAME[rs2], s_imm as i32, REG_NAME[rs1]);
+                Rv32Actor::store(mems, perips, intr, events, wr_addr, 2, rs2_data);
+                trace!(cpu, "sh {}, {}({})", REG_NAME[rs2], s_imm as i32, REG_NAME[rs1]);
             },
             //sw 3'b010
             0x02 => {
-                for m in mems.iter_mut() {
-                    if m.in_range(wr_addr) {
-                        m.write_u32(rs2_data, wr_addr);
-                        break;
-                    }
-                }
-                for p in perips.iter_mut() {
-                    if p.in_range(wr_addr) {
-                        p.write_u32(rs2_data, wr_addr);
-                        break;
-                    }
-                }
-                println!("sb {}, {}({})", REG_NAME[rs2], s_imm as i32, REG_NAME[rs1]);
+                Rv32Actor::store(mems, perips, intr, events, wr_addr, 4, rs2_data);
+                trace!(cpu, "sw {}, {}({})", REG_NAME[rs2], s_imm as i32, REG_NAME[rs1]);
             },
             //others
-            _ => panic!("store illegal instruction. {:08x}.", instr),
+            _ => Rv32Actor::illegal(cpu, instr),
         }
     }
 
-    fn execute_fence(_cpu: &mut Rv32Cpu, instr: u32) {
+    fn execute_fence(cpu: &mut Rv32Cpu, instr: u32) {
         match instr>>12 & 0x07 {
             //fence 3'b000
             0x00 => {
-                println!("fence {}, {}", (instr>>24)&0x0f, (instr>>20)&0x0f);
+                trace!(cpu, "fence {}, {}", (instr>>24)&0x0f, (instr>>20)&0x0f);
             },
             //fence.i 3'b001
             0x01 => {
-                println!("fence.i");
+                trace!(cpu, "fence.i");
             },
-            _ => panic!("fence illegal instruction. {:08x}.", instr),
+            _ => Rv32Actor::illegal(cpu, instr),
         }
     }
 
@@ -622,29 +1106,43 @@ impl Rv32Actor {
         match (instr>>12 & 0x07, instr>>20 & 0xfff) {
             //ecall 3'b000, 12'h0
             (0x00, 0x000) => {
-                println!("ecall");
+                trace!(cpu, "ecall");
+                cpu.set_exception(IntrType::Ecall);
             },
             //ebreak 3'b000, 12'h1
             (0x00, 0x001) => {
-                println!("ebreak");
+                trace!(cpu, "ebreak");
+                cpu.set_exception(IntrType::Breakpoint(pc));
             },
-            //mret 3'b000, 12'h1
+            //mret 3'b000, 12'h302
             (0x00, 0x302) => {
-                let mut status = cpu.read_csr(0x300);//mstatus
-                status |= 0x08;
-                cpu.write_csr(0x300, status);//mstatus
-
-                println!("mret");
-
-                let mepc = cpu.read_csr(0x341);//mepc
-                cpu.set_pc(mepc);
+                trace!(cpu, "mret");
+                cpu.mret();
+            },
+            //wfi 3'b000, 12'h105
+            (0x00, 0x105) => {
+                trace!(cpu, "wfi");
+                cpu.set_waiting(true);
+                cpu.set_pc(pc.wrapping_add(4));
+            },
+            //mnxti csrrs(i)/csrrc(i) 12'h345, the write goes to mstatus
+            (0x02 | 0x03 | 0x06 | 0x07, 0x345) => {
+                let src = if instr>>12 & 0x04 != 0 { rs1 as u32 } else { rs1_data };
+                let status = cpu.read_csr(0x300);//mstatus
+                cpu.write_csr(0x300, if instr>>12 & 0x01 == 0 { status | src } else { status & !src });
+                let t = cpu.mnxti();
+                let rd = cpu.set_rd(instr, t);
+                trace!(cpu, "mnxti {}, {}", REG_NAME[rd], REG_NAME[rs1]);
+                cpu.set_pc(pc.wrapping_add(4));
             },
+            //the csrs that don't exist are illegal.
+            (0x01..=0x03 | 0x05..=0x07, _) if !Rv32Cpu::has_csr(csr) => Rv32Actor::illegal(cpu, instr),
             //csrrw 3'b001, *
             (0x01, _) => {
                 let t = cpu.read_csr(csr);
                 cpu.write_csr(csr, rs1_data);
                 let rd = cpu.set_rd(instr, t);
-                println!("csrrw {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
+                trace!(cpu, "csrrw {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
                 cpu.set_pc(pc.wrapping_add(4));
             },
             //csrrs 3'b010, *
@@ -652,7 +1150,7 @@ impl Rv32Actor {
                 let t = cpu.read_csr(csr);
                 cpu.write_csr(csr, rs1_data | t);
                 let rd: usize = cpu.set_rd(instr, t);
-                println!("csrrs {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
+                trace!(cpu, "csrrs {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
                 cpu.set_pc(pc.wrapping_add(4));
             },
             //csrrc 3'b011, *
@@ -660,7 +1158,7 @@ impl Rv32Actor {
                 let t = cpu.read_csr(csr);
                 cpu.write_csr(csr, (!rs1_data) & t);
                 let rd = cpu.set_rd(instr, t);
-                println!("csrrc {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
+                trace!(cpu, "csrrc {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
                 cpu.set_pc(pc.wrapping_add(4));
             },
             //csrrwi 3'b101, *
@@ -668,7 +1166,7 @@ impl Rv32Actor {
                 let t = cpu.read_csr(csr);
                 cpu.write_csr(csr, rs1 as u32);
                 let rd = cpu.set_rd(instr, t);
-                println!("csrrwi {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
+                trace!(cpu, "csrrwi {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
                 cpu.set_pc(pc.wrapping_add(4));
             },
             //csrrsi 3'b110, *
@@ -676,7 +1174,7 @@ impl Rv32Actor {
                 let t = cpu.read_csr(csr);
                 cpu.write_csr(csr, (rs1 as u32) | t);
                 let rd = cpu.set_rd(instr, t);
-                println!("csrrsi {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
+                trace!(cpu, "csrrsi {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
                 cpu.set_pc(pc.wrapping_add(4));
             },
             //csrrci 3'b111, *
@@ -684,28 +1182,30 @@ impl Rv32Actor {
                 let t = cpu.read_csr(csr);
                 cpu.write_csr(csr, (!(rs1 as u32)) & t);
                 let rd = cpu.set_rd(instr, t);
-                println!("csrrci {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
+                trace!(cpu, "csrrci {}, {csr}, {}", REG_NAME[rd], REG_NAME[rs1]);
                 cpu.set_pc(pc.wrapping_add(4));
             },
-            _ => panic!("sys illegal instruction. {:08x}.", instr),
+            _ => Rv32Actor::illegal(cpu, instr),
         }
     }
 
     pub fn print_d(&self, name: &String, arg: &String) {
         for cpu in self.cpus.iter() {
-            if cpu.match_name(&name) {
+            if cpu.match_name(name) {
                 if arg == "reg" {
                     cpu.print_reg();
                 } else if arg == "csr" {
                     cpu.print_csr();
+                } else if arg == "pc" {
+                    println!("{} (mhartid {}) pc:{:08x}", name, cpu.hartid(), cpu.get_pc());
                 }
                 return;
             }
         }
 
         for mem in self.mems.iter() {
-            if mem.match_name(&name) {
-                let addr = crate::utils::parse_hex_u32_err_to_0(&arg);
+            if mem.match_name(name) {
+                let addr = crate::utils::parse_hex_u32_err_to_0(arg);
                 if mem.in_range(addr) {
                     println!("{}", mem.dump(addr));
                 }
@@ -714,18 +1214,18 @@ impl Rv32Actor {
         }
 
         for p in self.perips.iter() {
-            if p.match_name(&name) {
-                println!("{}", p.dump(0));
+            if p.name() == name {
+                println!("{}", p.dump());
                 return;
             }
         }
     }
 
-    pub fn set_v_d(&mut self, name: &String, arg1: &String, arg2: &String) {
-        let addr = crate::utils::parse_hex_u32_err_to_0(&arg1);
-        let val = crate::utils::parse_hex_u32_err_to_0(&arg2);
+    pub fn set_v_d(&mut self, name: &String, arg1: &str, arg2: &str) {
+        let addr = crate::utils::parse_hex_u32_err_to_0(arg1);
+        let val = crate::utils::parse_hex_u32_err_to_0(arg2);
         for cpu in self.cpus.iter_mut() {
-            if cpu.match_name(&name) {
+            if cpu.match_name(name) {
                 if addr < 32 {
                     cpu.set_rs(addr, val);
                 } else {
@@ -736,7 +1236,7 @@ impl Rv32Actor {
         }
 
         for mem in self.mems.iter_mut() {
-            if mem.match_name(&name) {
+            if mem.match_name(name) {
                 if mem.in_range(addr) {
                     mem.write_u32(val, addr);
                 }
@@ -745,8 +1245,9 @@ impl Rv32Actor {
         }
 
         for p in self.perips.iter_mut() {
-            if p.match_name(&name) {
-                p.write_u32(val, addr);
+            if p.name() == name {
+                p.write_u32(val, addr, &mut PeripCtx::new(&mut self.mems, &mut self.intr, &mut self.events));
+                self.flush_events();
                 return;
             }
         }
@@ -0,0 +1,75 @@
+/// The clock of a cpu or a perip. The soc ticks at the fastest clock and a
+/// slower one has an edge on the soc ticks its phase wraps in, so the
+/// edges fall on the same soc ticks every run.
+#[derive(Debug, Clone, Copy)]
+pub struct Clock {
+    //0 has an edge on every soc tick.
+    hz: u64,
+    phase: u64,
+    cycles: u64,
+    due: bool,
+    //interrupts changed since the last edge.
+    stale: bool,
+}
+
+impl Clock {
+    /// `freq` in MHz, 0 follows the soc clock.
+    pub fn new(freq: f32) -> Self {
+        let hz = if freq > 0.0 { (freq as f64 * 1_000_000.0).round() as u64 } else { 0 };
+        Clock { hz, phase: 0, cycles: 0, due: false, stale: true }
+    }
+
+    pub fn hz(&self) -> u64 {
+        self.hz
+    }
+
+    fn step(&self, soc_hz: u64) -> u64 {
+        if self.hz == 0 { soc_hz } else { self.hz.min(soc_hz) }
+    }
+
+    /// One soc tick, true on an edge.
+    pub fn tick(&mut self, soc_hz: u64) -> bool {
+        self.phase += self.step(soc_hz);
+        self.due = self.phase >= soc_hz;
+        if self.due {
+            self.phase -= soc_hz;
+            self.cycles += 1;
+            self.stale = false;
+        }
+        self.due
+    }
+
+    /// The last soc tick had an edge.
+    pub fn is_due(&self) -> bool {
+        self.due
+    }
+
+    /// The edges so far.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    pub fn set_stale(&mut self) {
+        self.stale = true;
+    }
+
+    /// The soc ticks that have at most `edges` edges.
+    pub fn ticks_for(&self, edges: u64, soc_hz: u64) -> u64 {
+        let end = (edges as u128 + 1) * soc_hz as u128 - self.phase as u128 - 1;
+        (end / self.step(soc_hz) as u128).min(u64::MAX as u128) as u64
+    }
+
+    /// Jump over `ticks` soc ticks, returns the edges in them.
+    pub fn skip(&mut self, ticks: u64, soc_hz: u64) -> u64 {
+        let phase = self.phase as u128 + ticks as u128 * self.step(soc_hz) as u128;
+        let edges = (phase / soc_hz as u128) as u64;
+        self.phase = (phase % soc_hz as u128) as u64;
+        self.cycles += edges;
+        self.due = false;
+        edges
+    }
+}
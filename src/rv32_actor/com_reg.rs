@@ -1,5 +1,3 @@
-use std::usize;
-
 pub struct ComReg {
     reg: Vec<u32>,
 }
@@ -28,7 +26,7 @@ impl std::fmt::Display for ComReg {
         for (i, elem) in self.reg.iter().enumerate() {
             out_str.push_str(&format!("{}={:x}({}), ", reg_name[i], elem, elem));
             if i % 8 == 7 {
-                out_str.push_str("\n");
+                out_str.push('\n');
             }
         }
         write!(f, "{}", out_str)
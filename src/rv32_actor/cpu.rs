@@ -1,14 +1,23 @@
-use crate::intrrupt::IntrType;
-use crate::rv32_actor::csr_reg::CsrReg;
+use crate::intrrupt::{ClicIntr, IntrType};
+use crate::rv32_actor::csr_reg::{CsrReg, MSTATUS_MIE, MSTATUS_MPIE};
 use crate::rv32_actor::com_reg::ComReg;
 
+//meip, msip, mtip, seip, ssip, stip
+const INTR_PRIORITY: [u32; 6] = [11, 3, 7, 9, 1, 5];
+
 pub struct Rv32Cpu {
     name: String,
 
     freq: f32,
+    trace: bool,
 
+    rst_pc: u32,
     pc: u32,
     exception: IntrType,
+    waiting: bool,
+    //the interrupt the clic presents, and the one mnxti took over.
+    clic: Option<ClicIntr>,
+    clic_ack: Option<u32>,
 
     reg: ComReg,
     csr: CsrReg,
@@ -19,8 +28,13 @@ impl Rv32Cpu {
         Rv32Cpu{
                     name,
                     freq,
+                    trace: true,
+                    rst_pc,
                     pc: rst_pc, 
                     exception: IntrType::None,
+                    waiting: false,
+                    clic: None,
+                    clic_ack: None,
                     reg: ComReg::new(32), 
                     csr: CsrReg::new(), 
                 }
@@ -34,6 +48,30 @@ impl Rv32Cpu {
         self.name.eq(name)
     }
 
+    pub fn freq(&self) -> f32 {
+        self.freq
+    }
+
+    pub fn trace(&self) -> bool {
+        self.trace
+    }
+
+    pub fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    pub fn reset(&mut self) {
+        self.pc = self.rst_pc;
+        self.exception = IntrType::None;
+        self.waiting = false;
+        self.clic = None;
+        self.clic_ack = None;
+        self.reg = ComReg::new(32);
+        let hartid = self.csr.read(0xf14);
+        self.csr = CsrReg::new();
+        self.csr.set_mhartid(hartid);
+    }
+
     pub fn exception(&self) -> IntrType {
         self.exception
     }
@@ -42,6 +80,101 @@ impl Rv32Cpu {
         self.exception = exce;
     }
 
+    /// The hart waits in a `wfi` for an interrupt.
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    pub fn set_waiting(&mut self, waiting: bool) {
+        self.waiting = waiting;
+    }
+
+    /// The enabled pending interrupt of the highest priority, whatever
+    /// `mstatus.MIE` is.
+    pub fn pending_interrupt(&self) -> Option<u32> {
+        let pending = self.csr.mip() & self.csr.read(0x304);//mip & mie
+        INTR_PRIORITY.into_iter().find(|&c| pending & (1 << c) != 0)
+    }
+
+    /// Enter the trap handler, interrupts go to base + 4 * cause in vectored mode.
+    pub fn trap(&mut self, cause: u32, tval: u32) {
+        let status = self.csr.read(0x300);//mstatus
+        let mpie = if status & MSTATUS_MIE != 0 { MSTATUS_MPIE } else { 0 };
+        self.csr.write(0x300, (status & !(MSTATUS_MIE | MSTATUS_MPIE)) | mpie);
+        self.csr.write(0x341, self.pc);//mepc
+        self.csr.set_trap_cause(cause);
+        self.csr.write(0x343, tval);//mtval
+
+        let tvec = self.csr.read(0x305);//mtvec
+        let base = tvec & !3;
+        self.pc = if self.csr.is_clic() {
+            tvec & !0x3f
+        } else if tvec & 3 == 1 && cause & 0x80000000 != 0 {
+            base.wrapping_add(4 * (cause & 0x7fffffff))
+        } else {
+            base
+        };
+        self.exception = IntrType::None;
+        self.waiting = false;
+    }
+
+    pub fn is_clic(&self) -> bool {
+        self.csr.is_clic()
+    }
+
+    pub fn set_clic(&mut self, intr: Option<ClicIntr>) {
+        self.clic = intr;
+    }
+
+    /// The clic interrupt above `mintstatus.mil` and `mintthresh`, whatever
+    /// `mstatus.MIE` is.
+    pub fn clic_interrupt(&self) -> Option<ClicIntr> {
+        let level = self.csr.mil().max(self.csr.read(0x347) as u8);//mintthresh
+        self.clic.filter(|c| c.level > level)
+    }
+
+    /// Take a clic interrupt, `entry` is its vector table entry when it is
+    /// hardware vectored.
+    pub fn trap_clic(&mut self, intr: ClicIntr, entry: Option<u32>) {
+        self.trap(0x80000000 | intr.id, 0);
+        self.csr.set_mil(intr.level);
+        if let Some(e) = entry {
+            self.pc = e & !1;
+        }
+    }
+
+    /// `mnxti`, a handler takes over the pending non vectored interrupt above
+    /// `mcause.mpil` and `mintthresh`, returns its vector table entry or 0.
+    pub fn mnxti(&mut self) -> u32 {
+        match self.clic {
+            Some(c) if self.csr.is_clic() && !c.shv && c.level > self.csr.mpil()
+                    && c.level as u32 > self.csr.read(0x347) => {
+                self.csr.set_mil(c.level);
+                let mcause = self.csr.read(0x342);
+                self.csr.write(0x342, (mcause & !0xfff) | 0x80000000 | c.id);
+                self.clic = None;
+                self.clic_ack = Some(c.id);
+                self.csr.read(0x307).wrapping_add(4 * c.id)//mtvt
+            },
+            _ => 0,
+        }
+    }
+
+    pub fn take_clic_ack(&mut self) -> Option<u32> {
+        self.clic_ack.take()
+    }
+
+    /// Return from the trap handler.
+    pub fn mret(&mut self) {
+        if self.csr.is_clic() {
+            self.csr.set_mil(self.csr.mpil());
+        }
+        let status = self.csr.read(0x300);//mstatus
+        let mie = if status & MSTATUS_MPIE != 0 { MSTATUS_MIE } else { 0 };
+        self.csr.write(0x300, (status & !MSTATUS_MIE) | mie | MSTATUS_MPIE);
+        self.pc = self.csr.read(0x341);//mepc
+    }
+
     pub fn get_pc(&self) -> u32 {
         self.pc
     }
@@ -82,6 +215,10 @@ impl Rv32Cpu {
         (r2 as usize, self.reg.read(r2))
     }
 
+    pub fn has_csr(addr: u32) -> bool {
+        CsrReg::exists(addr)
+    }
+
     pub fn read_csr(&self, addr: u32) -> u32 {
         self.csr.read(addr) 
     }
@@ -90,12 +227,33 @@ impl Rv32Cpu {
         self.csr.write(addr, dat);
     }
 
+    pub fn set_hartid(&mut self, id: u32) {
+        self.csr.set_mhartid(id);
+    }
+
+    pub fn hartid(&self) -> u32 {
+        self.csr.read(0xf14)
+    }
+
+    pub fn rst_pc(&self) -> u32 {
+        self.rst_pc
+    }
+
+    /// Where the hart starts after a reset, the pc does not change now.
+    pub fn set_rst_pc(&mut self, pc: u32) {
+        self.rst_pc = pc;
+    }
+
+    pub fn set_mip_hw(&mut self, bits: u32) {
+        self.csr.set_mip_hw(bits);
+    }
+
     pub fn print_reg(&self) {
-        println!("{} Reg:\n{}", self.name, self.reg.to_string());
+        println!("{} (mhartid {}) pc:{:08x} Reg:\n{}", self.name, self.hartid(), self.pc, self.reg);
     }
 
     pub fn print_csr(&self) {
-        println!("{} Csr:\n{}", self.name, self.csr.to_string());
+        println!("{} Csr:\n{}", self.name, self.csr);
     }
 }
 
@@ -2,34 +2,140 @@
 pub struct CsrReg {
     mepc: u32,
     mcause: u32,
+    mtval: u32,
     mstatus: u32,
     mtvec: u32,
+    mscratch: u32,
+    mie: u32,
+    mip: u32,
+    mip_hw: u32,
+    mhartid: u32,
+    //clic mode
+    mtvt: u32,
+    mil: u8,
+    mintthresh: u32,
+}
+
+//mip bits driven by the clint/plic, read only for the software.
+const MIP_HW_MASK: u32 = 0xa88;
+
+pub const MSTATUS_MIE: u32 = 0x08;
+pub const MSTATUS_MPIE: u32 = 0x80;
+//machine mode only, MPP always reads 3.
+const MSTATUS_MPP: u32 = 0x1800;
+
+//mcause of the clic mode, interrupt, minhv, mpil and exccode. mpp and mpie
+//are aliases of the mstatus ones.
+const MCAUSE_CLIC_MASK: u32 = 0xc0ff0fff;
+const MCAUSE_MPIE: u32 = 1 << 27;
+const MCAUSE_MPP: u32 = 3 << 28;
+
+impl Default for CsrReg {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl CsrReg {
     pub fn new() ->Self {
-        CsrReg{mepc: 0, mcause: 0, mstatus: 0, mtvec: 0}
+        CsrReg{mepc: 0, mcause: 0, mtval: 0, mstatus: MSTATUS_MPP, mtvec: 0, mscratch: 0, mie: 0, mip: 0, mip_hw: 0, mhartid: 0,
+               mtvt: 0, mil: 0, mintthresh: 0}
+    }
+
+    /// The csrs that are implemented, the others read as 0 and ignore the
+    /// writes here while a csr instruction on them is illegal.
+    pub fn exists(addr: u32) -> bool {
+        matches!(addr, 0x300 | 0x304 | 0x305 | 0x307 | 0x340..=0x345 | 0x347 | 0xf14 | 0xfb1)
     }
 
     pub fn read(&self, addr: u32) -> u32 {
         match addr {
             0x341 => self.mepc,
+            0x342 if self.is_clic() => {
+                let mpie = if self.mstatus & MSTATUS_MPIE != 0 { MCAUSE_MPIE } else { 0 };
+                self.mcause | MCAUSE_MPP | mpie
+            },
             0x342 => self.mcause,
+            0x343 => self.mtval,
+            0x307 => self.mtvt,
+            0x345 => 0,//mnxti, see Rv32Cpu::mnxti
+            0x347 => self.mintthresh,
+            0xfb1 => (self.mil as u32) << 24,//mintstatus
             0x300 => self.mstatus,
             0x305 => self.mtvec,
-            _ => panic!("csr read address {:x} not exist.", addr),
+            0x340 => self.mscratch,
+            0x304 => self.mie,
+            0x344 => self.mip(),
+            0xf14 => self.mhartid,
+            _ => 0,
         }
     }
 
     pub fn write(&mut self, addr: u32, dat: u32) {
         match addr {
             0x341 => self.mepc = dat,
+            0x342 if self.is_clic() => {
+                self.mcause = dat & MCAUSE_CLIC_MASK;
+                self.mstatus = if dat & MCAUSE_MPIE != 0 { self.mstatus | MSTATUS_MPIE } else { self.mstatus & !MSTATUS_MPIE };
+            },
             0x342 => self.mcause = dat,
-            0x300 => self.mstatus = dat,
-            0x305 => self.mtvec = dat,
-            _ => panic!("csr write address {:x} not exist.", addr),
+            0x343 => self.mtval = dat,
+            0x300 => self.mstatus = (dat & (MSTATUS_MIE | MSTATUS_MPIE)) | MSTATUS_MPP,
+            //direct, vectored or clic with a 64 bytes aligned base, the
+            //reserved mode 2 falls back to direct.
+            0x305 => self.mtvec = match dat & 3 {
+                2 => dat & !3,
+                3 => (dat & !0x3f) | 3,
+                _ => dat,
+            },
+            0x307 => self.mtvt = dat & !0x3f,
+            0x347 => self.mintthresh = dat & 0xff,
+            0xfb1 => {},
+            0x340 => self.mscratch = dat,
+            0x304 => self.mie = dat & 0xaaa,
+            0x344 => self.mip = dat & 0x222,
+            0xf14 => {},
+            _ => {},
         }
     }
+
+    pub fn mip(&self) -> u32 {
+        self.mip | self.mip_hw
+    }
+
+    pub fn set_mip_hw(&mut self, bits: u32) {
+        self.mip_hw = bits & MIP_HW_MASK;
+    }
+
+    /// `mtvec.MODE` is 3, the interrupts come from a clic.
+    pub fn is_clic(&self) -> bool {
+        self.mtvec & 3 == 3
+    }
+
+    pub fn mil(&self) -> u8 {
+        self.mil
+    }
+
+    pub fn set_mil(&mut self, level: u8) {
+        self.mil = level;
+    }
+
+    /// mcause of a trap, the clic mode also saves mil in mpil.
+    pub fn set_trap_cause(&mut self, cause: u32) {
+        self.mcause = if self.is_clic() {
+            (cause & MCAUSE_CLIC_MASK & !0x00ff0000) | ((self.mil as u32) << 16)
+        } else {
+            cause
+        };
+    }
+
+    pub fn mpil(&self) -> u8 {
+        (self.mcause >> 16) as u8
+    }
+
+    pub fn set_mhartid(&mut self, id: u32) {
+        self.mhartid = id;
+    }
 }
 
 impl std::fmt::Display for CsrReg {
@@ -37,8 +143,15 @@ impl std::fmt::Display for CsrReg {
         let mut out_str = String::new();
         out_str.push_str(&format!("mepc={:x}.\n", self.mepc));
         out_str.push_str(&format!("mcause={:x}({:b}).\n", self.mcause, self.mcause));
+        out_str.push_str(&format!("mtval={:x}.\n", self.mtval));
         out_str.push_str(&format!("mstatus={:x}({:b}).\n", self.mstatus, self.mstatus));
         out_str.push_str(&format!("mtvec={:x}.\n", self.mtvec));
+        out_str.push_str(&format!("mscratch={:x}.\n", self.mscratch));
+        out_str.push_str(&format!("mtvt={:x}.\n", self.mtvt));
+        out_str.push_str(&format!("mintstatus.mil={:x}, mintthresh={:x}.\n", self.mil, self.mintthresh));
+        out_str.push_str(&format!("mie={:x}({:b}).\n", self.mie, self.mie));
+        out_str.push_str(&format!("mip={:x}({:b}).\n", self.mip(), self.mip()));
+        out_str.push_str(&format!("mhartid={:x}.\n", self.mhartid));
         write!(f, "{}", out_str)
     }
 }
\ No newline at end of file
@@ -0,0 +1,245 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::mem::{self, Mem};
+use crate::rv32_actor::cpu::Rv32Cpu;
+use crate::rv32_actor::SocEvent;
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITEC: u32 = 0x03;
+const SYS_WRITE0: u32 = 0x04;
+const SYS_WRITE: u32 = 0x05;
+const SYS_READ: u32 = 0x06;
+const SYS_ISTTY: u32 = 0x09;
+const SYS_SEEK: u32 = 0x0a;
+const SYS_FLEN: u32 = 0x0c;
+const SYS_CLOCK: u32 = 0x10;
+const SYS_TIME: u32 = 0x11;
+const SYS_ERRNO: u32 = 0x13;
+const SYS_GET_CMDLINE: u32 = 0x15;
+const SYS_EXIT: u32 = 0x18;
+const SYS_EXIT_EXTENDED: u32 = 0x20;
+
+const ADP_STOPPED_APPLICATION_EXIT: u32 = 0x20026;
+
+//slli x0, x0, 0x1f; ebreak; srai x0, x0, 7
+pub const ENTRY: u32 = 0x01f01013;
+pub const EBREAK: u32 = 0x00100073;
+pub const EXIT: u32 = 0x40705013;
+
+enum Handle {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+/// Host side of the semihosting calls, a0 is the operation and a1 the
+/// parameter block.
+#[derive(Default)]
+pub struct Semihost {
+    handles: Vec<Option<Handle>>,
+    cmdline: String,
+    errno: u32,
+}
+
+impl Semihost {
+    pub fn set_cmdline(&mut self, cmdline: &str) {
+        self.cmdline = cmdline.to_owned();
+    }
+
+    fn word(mems: &[Mem], addr: u32) -> u32 {
+        let mut buf = [0u8; 4];
+        mem::read_bytes(mems, addr, &mut buf);
+        u32::from_le_bytes(buf)
+    }
+
+    fn bytes(mems: &[Mem], addr: u32, len: Option<u32>) -> Vec<u8> {
+        let mut res = Vec::new();
+        let mut b = [0u8; 1];
+        let mut a = addr;
+        while len.is_none_or(|l| a - addr < l) && mem::read_bytes(mems, a, &mut b) && (len.is_some() || b[0] != 0) {
+            res.push(b[0]);
+            a += 1;
+        }
+        res
+    }
+
+    fn string(mems: &[Mem], addr: u32, len: Option<u32>) -> String {
+        String::from_utf8_lossy(&Semihost::bytes(mems, addr, len)).into_owned()
+    }
+
+    fn handle(&mut self, h: u32) -> Option<&mut Handle> {
+        self.handles.get_mut((h as usize).wrapping_sub(1))?.as_mut()
+    }
+
+    fn fail(&mut self, e: std::io::Error) -> u32 {
+        self.errno = e.raw_os_error().unwrap_or(5) as u32;
+        u32::MAX
+    }
+
+    fn open(&mut self, name: &str, mode: u32) -> u32 {
+        let handle = if name == ":tt" {
+            match mode {
+                0..=3 => Handle::Stdin,
+                4..=7 => Handle::Stdout,
+                _ => Handle::Stderr,
+            }
+        } else {
+            let mut opt = OpenOptions::new();
+            //r, r+, w, w+, a, a+ each with a binary variant.
+            match mode / 2 {
+                0 => opt.read(true),
+                1 => opt.read(true).write(true),
+                2 => opt.write(true).create(true).truncate(true),
+                3 => opt.read(true).write(true).create(true).truncate(true),
+                4 => opt.append(true).create(true),
+                _ => opt.read(true).append(true).create(true),
+            };
+            match opt.open(name) {
+                Ok(f) => Handle::File(f),
+                Err(e) => return self.fail(e),
+            }
+        };
+        match self.handles.iter().position(|h| h.is_none()) {
+            Some(i) => {
+                self.handles[i] = Some(handle);
+                i as u32 + 1
+            },
+            None => {
+                self.handles.push(Some(handle));
+                self.handles.len() as u32
+            },
+        }
+    }
+
+    //returns the bytes not written.
+    fn write(&mut self, h: u32, data: &[u8]) -> u32 {
+        let res = match self.handle(h) {
+            Some(Handle::Stdout) => std::io::stdout().write_all(data).and_then(|_| std::io::stdout().flush()),
+            Some(Handle::Stderr) => std::io::stderr().write_all(data),
+            Some(Handle::File(f)) => f.write_all(data),
+            _ => Err(std::io::Error::from_raw_os_error(9)),//EBADF
+        };
+        match res {
+            Ok(_) => 0,
+            Err(e) => {
+                self.fail(e);
+                data.len() as u32
+            },
+        }
+    }
+
+    //returns the bytes read.
+    fn read(&mut self, h: u32, buf: &mut [u8]) -> Result<usize, std::io::Error> {
+        match self.handle(h) {
+            Some(Handle::Stdin) => crate::chardev::read_stdin(buf),
+            Some(Handle::File(f)) => f.read(buf),
+            _ => Err(std::io::Error::from_raw_os_error(9)),
+        }
+    }
+
+    /// Run the call of `cpu` at its `ebreak`, the result goes to a0. The
+    /// clock counts the `ticks` of the cpu.
+    pub fn call(&mut self, cpu: &mut Rv32Cpu, mems: &mut [Mem], ticks: u64, events: &mut Vec<SocEvent>) {
+        let op = cpu.get_rs(10);
+        let arg = cpu.get_rs(11);
+        let param: Vec<u32> = (0..3).map(|i| Semihost::word(mems, arg.wrapping_add(4 * i))).collect();
+        let ret = match op {
+            SYS_OPEN => {
+                let name = Semihost::string(mems, param[0], Some(param[2]));
+                self.open(&name, param[1])
+            },
+            SYS_CLOSE => match self.handles.get_mut((param[0] as usize).wrapping_sub(1)).and_then(|h| h.take()) {
+                Some(_) => 0,
+                None => u32::MAX,
+            },
+            SYS_WRITEC => {
+                let mut out = std::io::stdout();
+                let _ = out.write_all(&Semihost::bytes(mems, arg, Some(1))).and_then(|_| out.flush());
+                0
+            },
+            SYS_WRITE0 => {
+                let mut out = std::io::stdout();
+                let _ = out.write_all(&Semihost::bytes(mems, arg, None)).and_then(|_| out.flush());
+                0
+            },
+            SYS_WRITE => {
+                let mut buf = vec![0u8; mem::mapped_len(mems, param[1], param[2]) as usize];
+                if buf.len() as u32 == param[2] && mem::read_bytes(mems, param[1], &mut buf) {
+                    self.write(param[0], &buf)
+                } else {
+                    param[2]
+                }
+            },
+            SYS_READ => {
+                let (h, addr, len) = (param[0], param[1], param[2]);
+                //no more than fits in the mems.
+                let mut buf = vec![0u8; mem::mapped_len(mems, addr, len) as usize];
+                match self.read(h, &mut buf) {
+                    Ok(n) if mem::write_bytes(mems, addr, &buf[..n]) => len - n as u32,
+                    Ok(_) => len,
+                    Err(e) => {
+                        self.fail(e);
+                        len
+                    },
+                }
+            },
+            SYS_ISTTY => match self.handle(param[0]) {
+                Some(Handle::File(_)) => 0,
+                Some(_) => 1,
+                None => u32::MAX,
+            },
+            SYS_SEEK => {
+                let pos = param[1] as u64;
+                match self.handle(param[0]) {
+                    Some(Handle::File(f)) => match f.seek(SeekFrom::Start(pos)) {
+                        Ok(_) => 0,
+                        Err(e) => self.fail(e),
+                    },
+                    _ => u32::MAX,
+                }
+            },
+            SYS_FLEN => match self.handle(param[0]) {
+                Some(Handle::File(f)) => match f.metadata() {
+                    Ok(m) => m.len() as u32,
+                    Err(e) => self.fail(e),
+                },
+                _ => u32::MAX,
+            },
+            //centiseconds of simulated time
+            SYS_CLOCK => (ticks as f64 / (cpu.freq() as f64 * 10000.0)) as u32,
+            SYS_TIME => SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs() as u32),
+            SYS_ERRNO => self.errno,
+            SYS_GET_CMDLINE => {
+                let (addr, len) = (param[0], param[1]);
+                let mut line = self.cmdline.clone().into_bytes();
+                line.push(0);
+                if line.len() as u32 <= len && mem::write_bytes(mems, addr, &line) {
+                    mem::write_bytes(mems, arg + 4, &(line.len() as u32 - 1).to_le_bytes());
+                    0
+                } else {
+                    u32::MAX
+                }
+            },
+            SYS_EXIT | SYS_EXIT_EXTENDED => {
+                //rv32 passes the reason itself, the extended call a block with the code.
+                let code = match (op, arg) {
+                    (SYS_EXIT_EXTENDED, _) if param[0] == ADP_STOPPED_APPLICATION_EXIT => param[1],
+                    (SYS_EXIT_EXTENDED, _) => 1,
+                    (_, ADP_STOPPED_APPLICATION_EXIT) => 0,
+                    _ => 1,
+                };
+                events.push(SocEvent::Halt { perip: "semihosting".to_owned(), code });
+                0
+            },
+            _ => {
+                println!("semihosting call {op:x} not supported.");
+                u32::MAX
+            },
+        };
+        cpu.set_rs(10, ret);
+    }
+}
@@ -0,0 +1,254 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::mem::{self, Mem};
+use crate::rv32_actor::cpu::Rv32Cpu;
+use crate::rv32_actor::SocEvent;
+
+const SYS_OPENAT: u32 = 56;
+const SYS_CLOSE: u32 = 57;
+const SYS_READ: u32 = 63;
+const SYS_WRITE: u32 = 64;
+const SYS_FSTAT: u32 = 80;
+const SYS_EXIT: u32 = 93;
+const SYS_EXIT_GROUP: u32 = 94;
+const SYS_GETTIMEOFDAY: u32 = 169;
+const SYS_BRK: u32 = 214;
+const SYS_CLOCK_GETTIME64: u32 = 403;
+
+const EBADF: i32 = 9;
+const EFAULT: i32 = 14;
+const EINVAL: i32 = 22;
+const ENOSYS: i32 = 38;
+const ENOTSUP: i32 = 95;
+
+const AT_FDCWD: u32 = -100i32 as u32;
+
+const O_ACCMODE: u32 = 0o3;
+const O_CREAT: u32 = 0o100;
+const O_TRUNC: u32 = 0o1000;
+const O_APPEND: u32 = 0o2000;
+
+const AT_NULL: u32 = 0;
+const AT_PAGESZ: u32 = 6;
+const AT_ENTRY: u32 = 9;
+const AT_RANDOM: u32 = 25;
+
+pub const ECALL: u32 = 0x00000073;
+
+enum Fd {
+    Stdin,
+    Stdout,
+    Stderr,
+    File(File),
+}
+
+/// Host side of the linux syscalls of a user mode program, a7 is the
+/// syscall, a0..a5 the args and a0 the result (or -errno).
+pub struct UserMode {
+    fds: Vec<Option<Fd>>,
+    brk_start: u32,
+    brk: u32,
+    brk_limit: u32,
+}
+
+impl UserMode {
+    /// The heap grows from `brk` up to `brk_limit`.
+    pub fn new(brk: u32, brk_limit: u32) -> Self {
+        UserMode { fds: vec![Some(Fd::Stdin), Some(Fd::Stdout), Some(Fd::Stderr)], brk_start: brk, brk, brk_limit }
+    }
+
+    /// Lay out argc, argv, envp and the auxv below `top` as linux does, returns sp.
+    pub fn setup_stack(mems: &mut [Mem], top: u32, entry: u32, args: &[String], envs: &[String]) -> Option<u32> {
+        let mut sp = top;
+        let mut push_str = |mems: &mut [Mem], s: &str| {
+            sp -= s.len() as u32 + 1;
+            mem::write_bytes(mems, sp, s.as_bytes());
+            mem::write_bytes(mems, sp + s.len() as u32, &[0]);
+            sp
+        };
+        let argv: Vec<u32> = args.iter().map(|a| push_str(mems, a)).collect();
+        let envp: Vec<u32> = envs.iter().map(|e| push_str(mems, e)).collect();
+        //AT_RANDOM bytes, fixed so the runs are reproducible.
+        sp = (sp - 16) & !15;
+        let random = sp;
+        if !mem::write_bytes(mems, random, &[0x5a; 16]) {
+            return None;
+        }
+
+        let mut words = vec![args.len() as u32];
+        words.extend(argv);
+        words.push(0);
+        words.extend(envp);
+        words.push(0);
+        words.extend([AT_PAGESZ, 4096, AT_ENTRY, entry, AT_RANDOM, random, AT_NULL, 0]);
+
+        sp = (sp - 4 * words.len() as u32) & !15;
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        if mem::write_bytes(mems, sp, &bytes) { Some(sp) } else { None }
+    }
+
+    fn add_fd(&mut self, fd: Fd) -> u32 {
+        match self.fds.iter().position(|f| f.is_none()) {
+            Some(i) => {
+                self.fds[i] = Some(fd);
+                i as u32
+            },
+            None => {
+                self.fds.push(Some(fd));
+                self.fds.len() as u32 - 1
+            },
+        }
+    }
+
+    fn fd(&mut self, fd: u32) -> Option<&mut Fd> {
+        self.fds.get_mut(fd as usize)?.as_mut()
+    }
+
+    fn err(e: std::io::Error) -> i32 {
+        -e.raw_os_error().unwrap_or(EINVAL)
+    }
+
+    fn path(mems: &[Mem], addr: u32) -> String {
+        let mut res = Vec::new();
+        let mut b = [0u8; 1];
+        let mut a = addr;
+        while mem::read_bytes(mems, a, &mut b) && b[0] != 0 {
+            res.push(b[0]);
+            a += 1;
+        }
+        String::from_utf8_lossy(&res).into_owned()
+    }
+
+    //a relative path is opened from the cwd only, there are no directory fds.
+    fn openat(&mut self, mems: &[Mem], dirfd: u32, path: u32, flags: u32) -> i32 {
+        let path = UserMode::path(mems, path);
+        if dirfd != AT_FDCWD && !path.starts_with('/') {
+            return if self.fd(dirfd).is_some() { -ENOTSUP } else { -EBADF };
+        }
+        let mut opt = OpenOptions::new();
+        match flags & O_ACCMODE {
+            0 => opt.read(true),
+            1 => opt.write(true),
+            _ => opt.read(true).write(true),
+        };
+        opt.create(flags & O_CREAT != 0).truncate(flags & O_TRUNC != 0).append(flags & O_APPEND != 0);
+        match opt.open(&path) {
+            Ok(f) => self.add_fd(Fd::File(f)) as i32,
+            Err(e) => UserMode::err(e),
+        }
+    }
+
+    fn read(&mut self, mems: &mut [Mem], fd: u32, addr: u32, len: u32) -> i32 {
+        //no more than fits in the mems.
+        let mut buf = vec![0u8; mem::mapped_len(mems, addr, len) as usize];
+        if buf.is_empty() && len > 0 {
+            return -EFAULT;
+        }
+        let res = match self.fd(fd) {
+            Some(Fd::Stdin) => crate::chardev::read_stdin(&mut buf),
+            Some(Fd::File(f)) => f.read(&mut buf),
+            _ => return -EBADF,
+        };
+        match res {
+            Ok(n) if mem::write_bytes(mems, addr, &buf[..n]) => n as i32,
+            Ok(_) => -EFAULT,
+            Err(e) => UserMode::err(e),
+        }
+    }
+
+    fn write(&mut self, mems: &[Mem], fd: u32, addr: u32, len: u32) -> i32 {
+        if mem::mapped_len(mems, addr, len) < len {
+            return -EFAULT;
+        }
+        let mut buf = vec![0u8; len as usize];
+        if !mem::read_bytes(mems, addr, &mut buf) {
+            return -EFAULT;
+        }
+        let res = match self.fd(fd) {
+            Some(Fd::Stdout) => std::io::stdout().write_all(&buf).and_then(|_| std::io::stdout().flush()),
+            Some(Fd::Stderr) => std::io::stderr().write_all(&buf),
+            Some(Fd::File(f)) => f.write_all(&buf),
+            _ => return -EBADF,
+        };
+        match res {
+            Ok(_) => len as i32,
+            Err(e) => UserMode::err(e),
+        }
+    }
+
+    //the struct kernel_stat of newlib and picolibc.
+    fn fstat(&mut self, mems: &mut [Mem], fd: u32, addr: u32) -> i32 {
+        let (mode, size) = match self.fd(fd) {
+            Some(Fd::File(f)) => match f.metadata() {
+                Ok(m) => (0o100644u32, m.len()),
+                Err(e) => return UserMode::err(e),
+            },
+            Some(_) => (0o020620, 0),
+            None => return -EBADF,
+        };
+        let mut st = [0u8; 128];
+        st[16..20].copy_from_slice(&mode.to_le_bytes());
+        st[20..24].copy_from_slice(&1u32.to_le_bytes());
+        st[48..56].copy_from_slice(&size.to_le_bytes());
+        st[56..60].copy_from_slice(&4096u32.to_le_bytes());
+        st[64..72].copy_from_slice(&size.div_ceil(512).to_le_bytes());
+        if mem::write_bytes(mems, addr, &st) { 0 } else { -EFAULT }
+    }
+
+    //realtime is the host time, the other clocks count the simulated time.
+    fn clock_gettime(mems: &mut [Mem], clock: u32, addr: u32, ticks: u64, freq: f32) -> i32 {
+        let (sec, nsec) = if clock == 0 {
+            let d = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            (d.as_secs(), d.subsec_nanos())
+        } else {
+            let hz = (freq as f64 * 1_000_000.0).round().max(1.0) as u128;
+            let ns = (ticks as u128 * 1_000_000_000 / hz) as u64;
+            (ns / 1_000_000_000, (ns % 1_000_000_000) as u32)
+        };
+        let ts: Vec<u8> = [sec.to_le_bytes(), (nsec as u64).to_le_bytes()].concat();
+        if mem::write_bytes(mems, addr, &ts) { 0 } else { -EFAULT }
+    }
+
+    //the host time in a struct timeval of 64 bit fields, no timezone.
+    fn gettimeofday(mems: &mut [Mem], tv: u32, tz: u32) -> i32 {
+        let d = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let timeval = [d.as_secs().to_le_bytes(), (d.subsec_micros() as u64).to_le_bytes()].concat();
+        let tv_ok = tv == 0 || mem::write_bytes(mems, tv, &timeval);
+        let tz_ok = tz == 0 || mem::write_bytes(mems, tz, &[0; 8]);
+        if tv_ok && tz_ok { 0 } else { -EFAULT }
+    }
+
+    /// Run the syscall of `cpu` at its `ecall`, the clocks count the `ticks` of the cpu.
+    pub fn call(&mut self, cpu: &mut Rv32Cpu, mems: &mut [Mem], ticks: u64, events: &mut Vec<SocEvent>) {
+        let a: Vec<u32> = (10..16).map(|r| cpu.get_rs(r)).collect();
+        let ret = match cpu.get_rs(17) {
+            SYS_OPENAT => self.openat(mems, a[0], a[1], a[2]),
+            SYS_CLOSE => match self.fds.get_mut(a[0] as usize).and_then(|f| f.take()) {
+                Some(_) => 0,
+                None => -EBADF,
+            },
+            SYS_READ => self.read(mems, a[0], a[1], a[2]),
+            SYS_WRITE => self.write(mems, a[0], a[1], a[2]),
+            SYS_FSTAT => self.fstat(mems, a[0], a[1]),
+            SYS_EXIT | SYS_EXIT_GROUP => {
+                events.push(SocEvent::Halt { perip: "usermode".to_owned(), code: a[0] });
+                0
+            },
+            SYS_GETTIMEOFDAY => UserMode::gettimeofday(mems, a[0], a[1]),
+            SYS_CLOCK_GETTIME64 => UserMode::clock_gettime(mems, a[0], a[1], ticks, cpu.freq()),
+            SYS_BRK => {
+                if a[0] >= self.brk_start && a[0] < self.brk_limit {
+                    self.brk = a[0];
+                }
+                self.brk as i32
+            },
+            n => {
+                eprintln!("syscall {n} not supported.");
+                -ENOSYS
+            },
+        };
+        cpu.set_rs(10, ret as u32);
+    }
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use zemulator::rv32_actor::Rv32Actor;
+
+//interrupt 40 at level 0x40 and 41 at level 0x80, both edge triggered so
+//the software pends them.
+const INT_40: u32 = CLIC + 0x1000 + 4 * 40;
+
+fn clic_soc(prog: Asm) -> Rv32Actor {
+    let clic = json!({"name": "clic0", "class": "clic", "start": CLIC, "size": 0x5000,
+                      "hart": 0, "interrupts": 64, "ctl_bits": 8});
+    soc(board(&[(100.0, 0)], json!([clic])), prog)
+}
+
+fn setup(a: &mut Asm) {
+    a.li(T0, 0x303);
+    a.csrw(MTVEC, T0);
+    a.li(T1, CLIC);
+    a.li(T0, 0x10);
+    a.sw(T0, T1, 0);
+    a.li(T5, INT_40);
+    a.li(T0, 0x4002_0100);
+    a.sw(T0, T5, 0);
+    a.li(T0, 0x8002_0100);
+    a.sw(T0, T5, 4);
+}
+
+//the id of the interrupt to t3, a non vectored handler clears the edge.
+fn entry(a: &mut Asm) {
+    a.org(0x300);
+    a.csrr(T3, MCAUSE);
+    a.andi(T3, T3, 0xff);
+    a.addi(T4, T3, -40);
+    a.slli(T4, T4, 2);
+    a.add(T4, T4, T5);
+    a.sb(ZERO, T4, 0);
+}
+
+#[test]
+fn higher_level_preempts_the_handler() {
+    let mut a = Asm::new();
+    setup(&mut a);
+    a.csrsi(MSTATUS, 8);
+    a.li(T0, 1);
+    a.sb(T0, T5, 0);
+    a.nop();
+    a.nop();
+    a.csrr(S8, MINTSTATUS);
+    a.label("end");
+    a.j("end");
+    //log the ids in s4, the handler of 40 pends 41 with the interrupts on.
+    entry(&mut a);
+    a.slli(S4, S4, 8);
+    a.add(S4, S4, T3);
+    a.li(T4, 40);
+    a.bne(T3, T4, "done");
+    a.csrr(S9, MEPC);
+    a.csrr(S10, MCAUSE);
+    a.csrr(S6, MINTSTATUS);
+    a.csrsi(MSTATUS, 8);
+    a.li(T0, 1);
+    a.sb(T0, T5, 4);
+    a.nop();
+    a.nop();
+    a.csrci(MSTATUS, 8);
+    a.csrw(MEPC, S9);
+    a.csrw(MCAUSE, S10);
+    a.label("done");
+    a.addi(S2, S2, 1);
+    a.mret();
+
+    let mut soc = clic_soc(a);
+    soc.run(200);
+    assert_eq!(soc.get_rs(S2), 2);
+    assert_eq!(soc.get_rs(S4), (40 << 8) | 41);
+    assert_eq!(soc.get_rs(S6) >> 24, 0x40);
+    assert_eq!(soc.get_rs(S8) >> 24, 0);
+    //the byte stores to clicintip kept the other registers.
+    assert_eq!(soc.bus_read_u32(INT_40), 0x40c2_0100);
+    assert_eq!(soc.bus_read_u32(INT_40 + 4), 0x80c2_0100);
+}
+
+#[test]
+fn same_level_waits_for_the_handler() {
+    let mut a = Asm::new();
+    setup(&mut a);
+    a.csrsi(MSTATUS, 8);
+    a.li(T0, 1);
+    a.sb(T0, T5, 0);
+    a.nop();
+    a.nop();
+    a.label("end");
+    a.j("end");
+    //the first run of the handler pends 40 again with the interrupts on, it
+    //is taken after the mret.
+    entry(&mut a);
+    a.csrr(S9, MEPC);
+    a.csrr(S10, MCAUSE);
+    a.addi(S2, S2, 1);
+    a.li(T4, 1);
+    a.bne(S2, T4, "done");
+    a.csrsi(MSTATUS, 8);
+    a.li(T0, 1);
+    a.sb(T0, T5, 0);
+    a.nop();
+    a.nop();
+    a.addi(S3, S2, 0);
+    a.csrci(MSTATUS, 8);
+    a.csrw(MEPC, S9);
+    a.csrw(MCAUSE, S10);
+    a.label("done");
+    a.mret();
+
+    let mut soc = clic_soc(a);
+    soc.run(200);
+    assert_eq!(soc.get_rs(S3), 1);
+    assert_eq!(soc.get_rs(S2), 2);
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+#[test]
+fn timer_interrupt_at_mtimecmp() {
+    let mut a = Asm::new();
+    a.li(T0, 0x100);
+    a.csrw(MTVEC, T0);
+    a.li(T1, CLINT + 0x4000);
+    a.li(T2, 50);
+    a.sw(T2, T1, 0);
+    a.sw(ZERO, T1, 4);
+    a.li(T0, 0x80);
+    a.csrw(MIE, T0);
+    a.csrsi(MSTATUS, 8);
+    a.label("loop");
+    a.addi(S3, S3, 1);
+    a.j("loop");
+    //count the interrupts and move mtimecmp 50 mtime ticks on.
+    a.org(0x100);
+    a.csrr(S4, MCAUSE);
+    a.addi(S2, S2, 1);
+    a.li(T1, CLINT + 0x4000);
+    a.lw(T2, T1, 0);
+    a.addi(T2, T2, 50);
+    a.sw(T2, T1, 0);
+    a.mret();
+
+    //mtime runs at 10MHz, a tenth of the cpu clock.
+    let mut soc = soc(board(&[(100.0, 0)], json!([clint()])), a);
+    soc.run(480);
+    assert_eq!(soc.get_rs(S2), 0);
+    soc.run(2000 - 480);
+    assert_eq!(soc.get_rs(S2), 3);
+    assert_eq!(soc.get_rs(S4), 0x8000_0007);
+    assert_eq!(soc.bus_read_u32(CLINT + 0xbff8), 200);
+    assert_eq!(soc.bus_read_u32(CLINT + 0x4000), 200);
+}
+
+#[test]
+fn msip_interrupts_the_addressed_hart() {
+    let mut a = Asm::new();
+    a.csrr(T0, MHARTID);
+    a.li(T1, CLINT);
+    a.bne(T0, ZERO, "other");
+    //hart 0 raises msip of hart 5.
+    a.li(T2, 1);
+    a.sw(T2, T1, 4 * 5);
+    a.label("end");
+    a.j("end");
+    a.label("other");
+    a.li(T2, 0x100);
+    a.csrw(MTVEC, T2);
+    a.li(T2, 0x8);
+    a.csrw(MIE, T2);
+    a.csrsi(MSTATUS, 8);
+    a.label("wait");
+    a.wfi();
+    a.j("wait");
+    a.org(0x100);
+    a.csrr(S4, MCAUSE);
+    a.addi(S2, S2, 1);
+    a.csrr(T0, MHARTID);
+    a.slli(T0, T0, 2);
+    a.add(T0, T0, T1);
+    a.sw(ZERO, T0, 0);
+    a.mret();
+
+    let mut soc = soc(board(&[(100.0, 0), (100.0, 5), (100.0, 6)], json!([clint()])), a);
+    soc.run(200);
+    assert_eq!(soc.get_cpu_rs(1, S2), 1);
+    assert_eq!(soc.get_cpu_rs(1, S4), 0x8000_0003);
+    assert_eq!(soc.get_cpu_rs(2, S2), 0);
+    assert_eq!(soc.bus_read_u32(CLINT + 4 * 5), 0);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+const TIMER: u32 = 0x10000;
+
+fn count_loop() -> Asm {
+    let mut a = Asm::new();
+    a.label("loop");
+    a.addi(S2, S2, 1);
+    a.j("loop");
+    a
+}
+
+#[test]
+fn each_clock_runs_at_its_frequency() {
+    //the soc ticks at 200MHz, the clint counts mtime at 10MHz and the timer
+    //is clocked at 8MHz.
+    let timer = json!({"name": "timer0", "class": "timer", "start": TIMER, "size": 64, "irq": 1, "freq": 8.0});
+    let mut soc = soc(board(&[(200.0, 0), (32.0, 1)], json!([clint(), timer])), count_loop());
+    soc.bus_write_u32(TIMER + 0x0c, u32::MAX);
+    soc.bus_write_u32(TIMER, 1);
+    soc.run(10_000);
+
+    assert_eq!(soc.get_tick(), 10_000);
+    assert_eq!(soc.cpu_cycles(0), 10_000);
+    assert_eq!(soc.cpu_cycles(1), 1600);
+    assert_eq!(soc.get_cpu_rs(0, S2), 5000);
+    assert_eq!(soc.get_cpu_rs(1, S2), 800);
+    assert!((soc.sim_time() - 50e-6).abs() < 1e-12);
+    assert_eq!(soc.bus_read_u32(CLINT + 0xbff8), 500);
+    assert_eq!(soc.bus_read_u32(TIMER + 0x08), 400);
+}
+
+#[test]
+fn slow_cpu_edges_are_spread_evenly() {
+    //3 edges of the 30MHz cpu in every 10 ticks of the 100MHz one.
+    let mut soc = soc(board(&[(100.0, 0), (30.0, 1)], json!([])), count_loop());
+    let mut edges = Vec::new();
+    for _ in 0..10 {
+        let before = soc.cpu_cycles(1);
+        soc.run(10);
+        edges.push(soc.cpu_cycles(1) - before);
+    }
+    assert!(edges.iter().all(|e| *e == 3), "{edges:?}");
+}
+
+#[test]
+fn ticks_go_past_32_bits() {
+    //the cpu sleeps, the run is skipped over in one go.
+    let mut a = Asm::new();
+    a.label("wait");
+    a.wfi();
+    a.j("wait");
+    let mut soc = soc(board(&[(100.0, 0)], json!([])), a);
+    soc.run(u32::MAX);
+    soc.run(u32::MAX);
+    assert_eq!(soc.get_tick(), 2 * u32::MAX as u64);
+    assert!((soc.sim_time() - 2.0 * u32::MAX as f64 / 100e6).abs() < 1e-6);
+}
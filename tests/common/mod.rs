@@ -0,0 +1,297 @@
+//! Shared by the integration tests: a soc built from a json config and a
+//! small rv32im encoder, the tests don't need a riscv toolchain.
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use zemulator::config::{CSoc, SocBuilder};
+use zemulator::rv32_actor::Rv32Actor;
+
+pub const ZERO: u32 = 0;
+pub const RA: u32 = 1;
+pub const SP: u32 = 2;
+pub const T0: u32 = 5;
+pub const T1: u32 = 6;
+pub const T2: u32 = 7;
+pub const S0: u32 = 8;
+pub const S1: u32 = 9;
+pub const A0: u32 = 10;
+pub const A1: u32 = 11;
+pub const A2: u32 = 12;
+pub const A3: u32 = 13;
+pub const A7: u32 = 17;
+pub const S2: u32 = 18;
+pub const S3: u32 = 19;
+pub const S4: u32 = 20;
+pub const S5: u32 = 21;
+pub const S6: u32 = 22;
+pub const S7: u32 = 23;
+pub const S8: u32 = 24;
+pub const S9: u32 = 25;
+pub const S10: u32 = 26;
+pub const T3: u32 = 28;
+pub const T4: u32 = 29;
+pub const T5: u32 = 30;
+
+pub const MSTATUS: u32 = 0x300;
+pub const MIE: u32 = 0x304;
+pub const MTVEC: u32 = 0x305;
+pub const MTVT: u32 = 0x307;
+pub const MEPC: u32 = 0x341;
+pub const MCAUSE: u32 = 0x342;
+pub const MIP: u32 = 0x344;
+pub const MINTSTATUS: u32 = 0xfb1;
+pub const MTVAL: u32 = 0x343;
+pub const MHARTID: u32 = 0xf14;
+
+pub const CLINT: u32 = 0x200_0000;
+pub const PLIC: u32 = 0xc00_0000;
+pub const CLIC: u32 = 0x280_0000;
+pub const GPIO_A: u32 = 0xd100_0000;
+pub const GPIO_B: u32 = 0xd100_1000;
+
+/// A board with 16KiB of ram at 0, the `cpus` as (MHz, mhartid) and the
+/// `perips` config entries.
+pub fn board(cpus: &[(f32, u32)], perips: Value) -> Value {
+    let cpus: Vec<Value> = cpus.iter().enumerate().map(|(i, (freq, hartid))| json!({
+        "name": format!("cpu{i}"), "class": "rv32", "isa": "im", "freq": freq, "rst_pc": 0, "hartid": hartid,
+    })).collect();
+    json!({
+        "name": "test_board",
+        "cpus": cpus,
+        "mems": [{"name": "ram", "start": 0, "size": 0x4000}],
+        "perips": perips,
+    })
+}
+
+pub fn clint() -> Value {
+    json!({"name": "clint", "class": "clint", "start": CLINT, "size": 0x10000, "timebase": 10000000})
+}
+
+pub fn plic() -> Value {
+    json!({"name": "plic", "class": "plic", "start": PLIC, "size": 0x4000000, "sources": 32})
+}
+
+pub fn gpio(name: &str, start: u32, irq: u32) -> Value {
+    json!({"name": name, "class": "gpio", "start": start, "size": 0x2c, "irq": irq})
+}
+
+/// Build the soc of `cfg` and load `prog` at 0.
+pub fn soc(cfg: Value, prog: Asm) -> Rv32Actor {
+    let cfg: CSoc = serde_json::from_value(cfg).unwrap();
+    let mut soc = SocBuilder::new().build_cfg(cfg);
+    assert!(soc.load_image(0, &prog.finish()));
+    soc
+}
+
+/// An elf32 file with `code` as its one segment at `entry`.
+pub fn elf(entry: u32, code: &[u8]) -> Vec<u8> {
+    let mut b = vec![0x7f, b'E', b'L', b'F', 1, 1, 1];
+    b.resize(16, 0);
+    b.extend(2u16.to_le_bytes());
+    b.extend(243u16.to_le_bytes());
+    b.extend(1u32.to_le_bytes());
+    for w in [entry, 52, 0, 0] {
+        b.extend(w.to_le_bytes());
+    }
+    for h in [52u16, 32, 1, 40, 0, 0] {
+        b.extend(h.to_le_bytes());
+    }
+    for w in [1, 84, entry, entry, code.len() as u32, code.len() as u32, 5, 4] {
+        b.extend(w.to_le_bytes());
+    }
+    b.extend(code);
+    b
+}
+
+enum Fixup {
+    Branch(u32, u32, u32),
+    Jump(u32),
+}
+
+/// Encodes rv32im instructions from address 0, `finish` patches the
+/// branches to the labels.
+#[derive(Default)]
+pub struct Asm {
+    words: Vec<u32>,
+    labels: HashMap<&'static str, u32>,
+    fixups: Vec<(usize, &'static str, Fixup)>,
+}
+
+fn i_type(op: u32, f3: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32 & 0xfff) << 20) | (rs1 << 15) | (f3 << 12) | (rd << 7) | op
+}
+
+fn s_type(f3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    (((imm >> 5) & 0x7f) << 25) | (rs2 << 20) | (rs1 << 15) | (f3 << 12) | ((imm & 0x1f) << 7) | 0x23
+}
+
+fn r_type(f7: u32, f3: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    (f7 << 25) | (rs2 << 20) | (rs1 << 15) | (f3 << 12) | (rd << 7) | 0x33
+}
+
+fn b_type(f3: u32, rs1: u32, rs2: u32, off: i32) -> u32 {
+    let o = off as u32;
+    (((o >> 12) & 1) << 31) | (((o >> 5) & 0x3f) << 25) | (rs2 << 20) | (rs1 << 15) | (f3 << 12)
+        | (((o >> 1) & 0xf) << 8) | (((o >> 11) & 1) << 7) | 0x63
+}
+
+fn j_type(rd: u32, off: i32) -> u32 {
+    let o = off as u32;
+    (((o >> 20) & 1) << 31) | (((o >> 1) & 0x3ff) << 21) | (((o >> 11) & 1) << 20) | (((o >> 12) & 0xff) << 12)
+        | (rd << 7) | 0x6f
+}
+
+impl Asm {
+    pub fn new() -> Self {
+        Asm::default()
+    }
+
+    pub fn pc(&self) -> u32 {
+        self.words.len() as u32 * 4
+    }
+
+    pub fn label(&mut self, name: &'static str) {
+        self.labels.insert(name, self.pc());
+    }
+
+    pub fn addr(&self, label: &'static str) -> u32 {
+        self.labels[label]
+    }
+
+    /// Pad with nops up to `addr`.
+    pub fn org(&mut self, addr: u32) {
+        assert!(addr >= self.pc());
+        while self.pc() < addr {
+            self.nop();
+        }
+    }
+
+    pub fn word(&mut self, w: u32) {
+        self.words.push(w);
+    }
+
+    pub fn nop(&mut self) {
+        self.addi(ZERO, ZERO, 0);
+    }
+
+    pub fn li(&mut self, rd: u32, v: u32) {
+        let lo = ((v & 0xfff) as i32) << 20 >> 20;
+        let hi = v.wrapping_sub(lo as u32);
+        if hi == 0 {
+            self.addi(rd, ZERO, lo);
+        } else {
+            self.lui(rd, hi);
+            if lo != 0 {
+                self.addi(rd, rd, lo);
+            }
+        }
+    }
+
+    pub fn lui(&mut self, rd: u32, imm: u32) {
+        self.word((imm & 0xffff_f000) | (rd << 7) | 0x37);
+    }
+
+    pub fn addi(&mut self, rd: u32, rs1: u32, imm: i32) {
+        self.word(i_type(0x13, 0, rd, rs1, imm));
+    }
+
+    pub fn andi(&mut self, rd: u32, rs1: u32, imm: i32) {
+        self.word(i_type(0x13, 7, rd, rs1, imm));
+    }
+
+    pub fn slli(&mut self, rd: u32, rs1: u32, shamt: u32) {
+        self.word(i_type(0x13, 1, rd, rs1, shamt as i32));
+    }
+
+    pub fn add(&mut self, rd: u32, rs1: u32, rs2: u32) {
+        self.word(r_type(0, 0, rd, rs1, rs2));
+    }
+
+    pub fn lw(&mut self, rd: u32, rs1: u32, imm: i32) {
+        self.word(i_type(0x03, 2, rd, rs1, imm));
+    }
+
+    pub fn sb(&mut self, rs2: u32, rs1: u32, imm: i32) {
+        self.word(s_type(0, rs1, rs2, imm));
+    }
+
+    pub fn sh(&mut self, rs2: u32, rs1: u32, imm: i32) {
+        self.word(s_type(1, rs1, rs2, imm));
+    }
+
+    pub fn sw(&mut self, rs2: u32, rs1: u32, imm: i32) {
+        self.word(s_type(2, rs1, rs2, imm));
+    }
+
+    fn branch(&mut self, f3: u32, rs1: u32, rs2: u32, label: &'static str) {
+        self.fixups.push((self.words.len(), label, Fixup::Branch(f3, rs1, rs2)));
+        self.word(0);
+    }
+
+    pub fn beq(&mut self, rs1: u32, rs2: u32, label: &'static str) {
+        self.branch(0, rs1, rs2, label);
+    }
+
+    pub fn bne(&mut self, rs1: u32, rs2: u32, label: &'static str) {
+        self.branch(1, rs1, rs2, label);
+    }
+
+    pub fn j(&mut self, label: &'static str) {
+        self.fixups.push((self.words.len(), label, Fixup::Jump(ZERO)));
+        self.word(0);
+    }
+
+    pub fn csrr(&mut self, rd: u32, csr: u32) {
+        self.word(i_type(0x73, 2, rd, 0, csr as i32));
+    }
+
+    pub fn csrw(&mut self, csr: u32, rs1: u32) {
+        self.word(i_type(0x73, 1, 0, rs1, csr as i32));
+    }
+
+    pub fn csrs(&mut self, csr: u32, rs1: u32) {
+        self.word(i_type(0x73, 2, 0, rs1, csr as i32));
+    }
+
+    pub fn csrsi(&mut self, csr: u32, uimm: u32) {
+        self.word(i_type(0x73, 6, 0, uimm, csr as i32));
+    }
+
+    pub fn csrci(&mut self, csr: u32, uimm: u32) {
+        self.word(i_type(0x73, 7, 0, uimm, csr as i32));
+    }
+
+    pub fn mret(&mut self) {
+        self.word(0x3020_0073);
+    }
+
+    pub fn wfi(&mut self) {
+        self.word(0x1050_0073);
+    }
+
+    /// The semihosting call sequence, op in a0 and the parameter block in a1.
+    pub fn ecall(&mut self) {
+        self.word(0x0000_0073);
+    }
+
+    pub fn semihost(&mut self) {
+        self.word(0x01f0_1013);
+        self.word(0x0010_0073);
+        self.word(0x4070_5013);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        for (index, label, fixup) in self.fixups.drain(..) {
+            let target = *self.labels.get(label).unwrap_or_else(|| panic!("no label {label}"));
+            let off = target as i32 - (index as i32 * 4);
+            self.words[index] = match fixup {
+                Fixup::Branch(f3, rs1, rs2) => b_type(f3, rs1, rs2, off),
+                Fixup::Jump(rd) => j_type(rd, off),
+            };
+        }
+        self.words.iter().flat_map(|w| w.to_le_bytes()).collect()
+    }
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::*;
+use serde_json::{json, Value};
+use zemulator::rv32_actor::StopReason;
+
+const TOHOST: u32 = 0x1000;
+const FROMHOST: u32 = 0x1008;
+
+fn htif(chardev: &str) -> Value {
+    json!([{"name": "htif", "class": "htif", "start": 0, "size": 0,
+            "tohost": TOHOST, "fromhost": FROMHOST, "chardev": chardev}])
+}
+
+//the command runs on the write of the upper half.
+fn tohost(a: &mut Asm, cmd: u32) {
+    a.li(T1, TOHOST);
+    a.li(T0, cmd);
+    a.sw(T0, T1, 0);
+    a.sw(ZERO, T1, 4);
+}
+
+#[test]
+fn exit_halts_with_the_code() {
+    let mut a = Asm::new();
+    tohost(&mut a, (7 << 1) | 1);
+    a.li(S2, 1);
+    a.label("end");
+    a.j("end");
+
+    let mut soc = soc(board(&[(100.0, 0)], htif("null")), a);
+    assert_eq!(soc.run(1000), StopReason::Halt { code: 7 });
+    assert_eq!(soc.get_rs(S2), 0);
+}
+
+#[test]
+fn syscall_write_and_exit() {
+    let path = std::env::temp_dir().join(format!("zemulator-htif-{}.txt", std::process::id()));
+    let data = b"hello htif\n";
+
+    let mut a = Asm::new();
+    tohost(&mut a, 0x1100);
+    a.li(T1, FROMHOST);
+    a.lw(S2, T1, 0);
+    a.li(T1, 0x1100);
+    a.lw(S3, T1, 0);
+    tohost(&mut a, 0x1140);
+    a.li(T1, 0x1140);
+    a.lw(S4, T1, 0);
+    //exit through the syscall.
+    tohost(&mut a, 0x1180);
+    a.label("end");
+    a.j("end");
+
+    let mut soc = soc(board(&[(100.0, 0)], htif(&format!("file:{}", path.to_str().unwrap()))), a);
+    let block = |args: [u64; 4]| -> Vec<u8> { args.iter().flat_map(|a| a.to_le_bytes()).collect() };
+    assert!(soc.write_mem(0x1100, &block([64, 1, 0x1200, data.len() as u64])));
+    //the buffer runs past the end of the ram.
+    assert!(soc.write_mem(0x1140, &block([64, 1, 0x3ff0, 0x20])));
+    assert!(soc.write_mem(0x1180, &block([93, 3, 0, 0])));
+    assert!(soc.write_mem(0x1200, data));
+    assert_eq!(soc.run(1000), StopReason::Halt { code: 3 });
+    assert_eq!(soc.get_rs(S2), 1);
+    assert_eq!(soc.get_rs(S3), data.len() as u32);
+    assert_eq!(soc.get_rs(S4), -14i32 as u32);
+    drop(soc);
+
+    let written = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(written.unwrap(), data);
+}
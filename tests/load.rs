@@ -0,0 +1,43 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use zemulator::config::{CSoc, SocBuilder};
+
+#[test]
+fn every_cpu_starts_at_the_elf_entry() {
+    //each hart counts in s2 from the entry at 0x100.
+    let mut a = Asm::new();
+    a.org(0x100);
+    a.label("loop");
+    a.addi(S2, S2, 1);
+    a.j("loop");
+    let code = a.finish();
+
+    let path = std::env::temp_dir().join(format!("zemulator-entry-{}.elf", std::process::id()));
+    std::fs::write(&path, elf(0x100, &code[0x100..])).unwrap();
+    let cfg: CSoc = serde_json::from_value(board(&[(100.0, 0), (100.0, 1)], json!([]))).unwrap();
+    let mut soc = SocBuilder::new().build_cfg(cfg);
+    let res = soc.load_file(path.to_str().unwrap(), 0);
+    let _ = std::fs::remove_file(&path);
+    res.unwrap();
+
+    for i in 0..2 {
+        assert_eq!(soc.cpu(i).unwrap().get_pc(), 0x100);
+    }
+    soc.run(100);
+    assert_eq!(soc.get_cpu_rs(0, S2), 50);
+    assert_eq!(soc.get_cpu_rs(1, S2), 50);
+    //a reset starts over at the entry too.
+    soc.reset(true);
+    assert_eq!(soc.cpu(1).unwrap().get_pc(), 0x100);
+}
+
+#[test]
+fn an_image_that_does_not_fit_is_not_reloaded() {
+    let mut soc = soc(board(&[(100.0, 0)], json!([])), Asm::new());
+    //half of it is past the end of the ram.
+    assert!(!soc.load_image(0x3ffe, &[0x11, 0x22, 0x33, 0x44]));
+    soc.reset(false);
+    assert_eq!(soc.read_mem_u32(0x3ffc), Some(0));
+}
@@ -0,0 +1,631 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use zemulator::rv32_actor::{Rv32Actor, SocEvent, StopReason};
+
+const GPIO_OUT: u32 = 0x08;
+const GPIO_RISE_IE: u32 = 0x18;
+const GPIO_IP: u32 = 0x28;
+
+fn idle() -> Asm {
+    let mut a = Asm::new();
+    a.label("end");
+    a.j("end");
+    a
+}
+
+#[test]
+fn sub_word_stores_keep_the_other_bytes() {
+    let mut a = Asm::new();
+    a.li(T1, CLINT + 0x4000);
+    a.li(T0, 0x1122_3344);
+    a.sw(T0, T1, 0);
+    a.li(T0, 0xaa);
+    a.sb(T0, T1, 1);
+    a.lw(S2, T1, 0);
+    a.li(T0, 0x5566);
+    a.sh(T0, T1, 2);
+    a.lw(S3, T1, 0);
+    a.li(T1, GPIO_A);
+    a.li(T0, 0x12ff);
+    a.sw(T0, T1, GPIO_OUT as i32);
+    a.li(T0, 0x5a);
+    a.sb(T0, T1, GPIO_OUT as i32 + 1);
+    a.lw(S4, T1, GPIO_OUT as i32);
+    a.label("end");
+    a.j("end");
+
+    let mut soc = soc(board(&[(100.0, 0)], json!([clint(), gpio("gpio_a", GPIO_A, 2)])), a);
+    soc.run(100);
+    assert_eq!(soc.get_rs(S2), 0x1122_aa44);
+    assert_eq!(soc.get_rs(S3), 0x5566_aa44);
+    assert_eq!(soc.get_rs(S4), 0x5aff);
+}
+
+#[test]
+fn sub_word_stores_skip_the_read_side_effects() {
+    //a byte store next to the write 1 to clear gpio IP must not clear it.
+    let mut a = Asm::new();
+    a.li(T1, GPIO_A);
+    a.sb(ZERO, T1, GPIO_IP as i32 + 1);
+    a.lw(S2, T1, GPIO_IP as i32);
+    a.li(T0, 1);
+    a.sb(T0, T1, GPIO_IP as i32);
+    a.lw(S3, T1, GPIO_IP as i32);
+    a.label("end");
+    a.j("end");
+
+    let mut soc = soc(board(&[(100.0, 0)], json!([gpio("gpio_a", GPIO_A, 2)])), a);
+    soc.bus_write_u32(GPIO_A + GPIO_RISE_IE, 1);
+    soc.set_pin("gpio_a", 0, true);
+    soc.run(100);
+    assert_eq!(soc.get_rs(S2), 1);
+    assert_eq!(soc.get_rs(S3), 0);
+}
+
+const SYSCON: u32 = 0x10_0000;
+
+fn syscon() -> serde_json::Value {
+    json!({"name": "syscon", "class": "syscon", "start": SYSCON, "size": 4})
+}
+
+#[test]
+fn syscon_pass_and_fail_halt_with_the_code() {
+    for (data, code) in [(0x5555, 0), (0x0100_3333, 0x100), (0x002a_3333, 0x2a)] {
+        let mut a = Asm::new();
+        a.li(T1, SYSCON);
+        a.li(T0, data);
+        a.sw(T0, T1, 0);
+        a.label("end");
+        a.j("end");
+
+        let mut soc = soc(board(&[(100.0, 0)], json!([syscon()])), a);
+        assert_eq!(soc.run(100), StopReason::Halt { code });
+    }
+}
+
+#[test]
+fn syscon_reset_keeps_the_ram() {
+    //count the boots at 0x3000, reset twice then fail with the count.
+    let mut a = Asm::new();
+    a.li(T2, 0x3000);
+    a.lw(T0, T2, 0);
+    a.addi(T0, T0, 1);
+    a.sw(T0, T2, 0);
+    a.li(T1, SYSCON);
+    a.li(T3, 3);
+    a.beq(T0, T3, "done");
+    a.li(T0, 0x7777);
+    a.sw(T0, T1, 0);
+    a.label("end");
+    a.j("end");
+    a.label("done");
+    a.slli(T0, T0, 16);
+    a.li(T3, 0x3333);
+    a.add(T0, T0, T3);
+    a.sw(T0, T1, 0);
+    a.j("end");
+
+    let mut soc = soc(board(&[(100.0, 0)], json!([syscon()])), a);
+    assert_eq!(soc.run(1000), StopReason::Halt { code: 3 });
+}
+
+const CRC: u32 = 0x3_2000;
+const RNG: u32 = 0x3_3000;
+
+#[test]
+fn crc32_check_value() {
+    //"123456789" as a word, a half word and bytes to any lane of DATA.
+    let mut a = Asm::new();
+    a.li(T1, CRC);
+    a.li(T0, u32::from_le_bytes(*b"1234"));
+    a.sw(T0, T1, 0x10);
+    a.li(T0, u16::from_le_bytes(*b"56") as u32);
+    a.sh(T0, T1, 0x12);
+    a.li(T0, b'7' as u32);
+    a.sb(T0, T1, 0x11);
+    a.li(T0, b'8' as u32);
+    a.sb(T0, T1, 0x13);
+    a.li(T0, b'9' as u32);
+    a.sb(T0, T1, 0x10);
+    a.lw(S2, T1, 0x10);
+    //start over with CRC-16/XMODEM.
+    a.li(T0, 0x1021);
+    a.sw(T0, T1, 0x04);
+    a.sw(ZERO, T1, 0x08);
+    a.sw(ZERO, T1, 0x0c);
+    a.li(T0, 0x09);
+    a.sw(T0, T1, 0x00);
+    a.li(T0, u32::from_le_bytes(*b"1234"));
+    a.sw(T0, T1, 0x10);
+    a.li(T0, u32::from_le_bytes(*b"5678"));
+    a.sw(T0, T1, 0x10);
+    a.li(T0, b'9' as u32);
+    a.sb(T0, T1, 0x12);
+    a.lw(S3, T1, 0x10);
+    a.label("end");
+    a.j("end");
+
+    let crc = json!({"name": "crc0", "class": "crc", "start": CRC, "size": 0x20});
+    let mut soc = soc(board(&[(100.0, 0)], json!([crc])), a);
+    soc.run(100);
+    assert_eq!(soc.get_rs(S2), 0xcbf4_3926);
+    assert_eq!(soc.get_rs(S3), 0x31c3);
+}
+
+#[test]
+fn rng_is_reproducible_and_waits_the_latency() {
+    let rng = json!({"name": "rng0", "class": "rng", "start": RNG, "size": 0x10, "seed": 1, "latency": 5});
+    let numbers = |soc: &mut Rv32Actor| {
+        soc.bus_write_u32(RNG, 1);
+        let first = soc.bus_read_u32(RNG + 8);
+        assert_eq!(soc.bus_read_u32(RNG + 4), 0);
+        assert_eq!(soc.bus_read_u32(RNG + 8), 0);
+        soc.run(10);
+        assert_eq!(soc.bus_read_u32(RNG + 4), 1);
+        (first, soc.bus_read_u32(RNG + 8))
+    };
+
+    let mut a = soc(board(&[(100.0, 0)], json!([rng.clone()])), idle());
+    assert_eq!(a.bus_read_u32(RNG + 4), 0);
+    let (first, second) = numbers(&mut a);
+    assert_ne!(first, second);
+    let mut b = soc(board(&[(100.0, 0)], json!([rng])), idle());
+    assert_eq!(numbers(&mut b), (first, second));
+    //a write to SEED starts the sequence over.
+    b.bus_write_u32(RNG + 0xc, 1);
+    b.run(10);
+    assert_eq!(b.bus_read_u32(RNG + 8), first);
+}
+
+const SPI: u32 = 0x3_0000;
+
+fn spi(slave: serde_json::Value) -> serde_json::Value {
+    json!({"name": "spi0", "class": "spi", "start": SPI, "size": 0x1c, "irq": 6, "slaves": [slave]})
+}
+
+//one command to the slave on cs 0, returns the bytes shifted in.
+fn xfer(soc: &mut Rv32Actor, tx: &[u8]) -> Vec<u8> {
+    soc.bus_write_u32(SPI, 0x100);
+    for b in tx {
+        soc.bus_write_u32(SPI + 0x4, *b as u32);
+    }
+    for _ in 0..1000 {
+        if soc.bus_read_u32(SPI + 0xc) & 1 == 0 {
+            break;
+        }
+        soc.run(1);
+    }
+    let rx = tx.iter().map(|_| soc.bus_read_u32(SPI + 0x8) as u8).collect();
+    assert_eq!(soc.bus_read_u32(SPI + 0x8), 0x8000_0000);
+    soc.bus_write_u32(SPI, 0);
+    rx
+}
+
+#[test]
+fn spi_nor_program_read_and_erase() {
+    let path = std::env::temp_dir().join(format!("zemulator-flash-{}.bin", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let flash = json!({"class": "spi_nor", "cs": 0, "size": 0x10000, "file": path.to_str().unwrap()});
+    let mut soc = soc(board(&[(100.0, 0)], json!([spi(flash)])), idle());
+
+    assert_eq!(xfer(&mut soc, &[0x9f, 0, 0, 0]), [0xff, 0xef, 0x40, 0x16]);
+    //no program without a write enable.
+    xfer(&mut soc, &[0x02, 0x00, 0x01, 0x00, 0x12]);
+    assert_eq!(xfer(&mut soc, &[0x03, 0x00, 0x01, 0x00, 0, 0]), [0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    xfer(&mut soc, &[0x06]);
+    assert_eq!(xfer(&mut soc, &[0x05, 0]), [0xff, 0x02]);
+    xfer(&mut soc, &[0x02, 0x00, 0x01, 0x00, 0x12, 0x34, 0x56]);
+    assert_eq!(xfer(&mut soc, &[0x05, 0]), [0xff, 0x00]);
+    assert_eq!(xfer(&mut soc, &[0x03, 0x00, 0x01, 0x00, 0, 0, 0, 0]), [0xff, 0xff, 0xff, 0xff, 0x12, 0x34, 0x56, 0xff]);
+    let file = std::fs::read(&path).unwrap();
+    assert_eq!(file.len(), 0x10000);
+    assert_eq!(&file[0x100..0x104], [0x12, 0x34, 0x56, 0xff]);
+
+    xfer(&mut soc, &[0x06]);
+    xfer(&mut soc, &[0x20, 0x00, 0x0f, 0xff]);
+    assert_eq!(xfer(&mut soc, &[0x0b, 0x00, 0x01, 0x00, 0, 0]), [0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    let file = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(&file.unwrap()[0x100..0x103], [0xff; 3]);
+}
+
+#[test]
+fn spi_nor_of_size_0_does_not_panic() {
+    let mut soc = soc(board(&[(100.0, 0)], json!([spi(json!({"class": "spi_nor", "cs": 0, "size": 0}))])), idle());
+    xfer(&mut soc, &[0x06]);
+    xfer(&mut soc, &[0x02, 0x12, 0x34, 0x56, 0x5a]);
+    assert_eq!(xfer(&mut soc, &[0x03, 0x00, 0x00, 0x00, 0, 0]), [0xff, 0xff, 0xff, 0xff, 0x5a, 0x5a]);
+}
+
+const WDT: u32 = 0x3_4000;
+
+fn watchdog(args: serde_json::Value) -> serde_json::Value {
+    let mut v = json!({"name": "wdt0", "class": "watchdog", "start": WDT, "size": 0x14, "irq": 9});
+    v.as_object_mut().unwrap().extend(args.as_object().unwrap().clone());
+    v
+}
+
+#[test]
+fn watchdog_kicks_keep_it_from_expiring() {
+    //kick it 20 times then stop.
+    let mut a = Asm::new();
+    a.li(T1, WDT);
+    a.li(T0, 0x5a5a_a5a5);
+    a.li(T2, 20);
+    a.label("kick");
+    a.sw(T0, T1, 0xc);
+    a.addi(S2, S2, 1);
+    a.bne(S2, T2, "kick");
+    a.label("end");
+    a.j("end");
+
+    let cfg = board(&[(100.0, 0)], json!([watchdog(json!({"action": "stop", "timeout": 100, "enable": true}))]));
+    let mut soc = soc(cfg, a);
+    assert_eq!(soc.run(10_000), StopReason::Halt { code: 1 });
+    assert_eq!(soc.get_rs(S2), 20);
+    assert!(soc.get_tick() > 100 + 20 * 3, "expired at {}", soc.get_tick());
+}
+
+#[test]
+fn watchdog_load_is_locked_and_sets_status() {
+    let mut soc = soc(board(&[(100.0, 0)], json!([watchdog(json!({}))])), idle());
+    soc.bus_write_u32(WDT + 0x4, 10);
+    assert_eq!(soc.bus_read_u32(WDT + 0x4), u32::MAX);
+    soc.bus_write_u32(WDT + 0xc, 0x1acc_e551);
+    soc.bus_write_u32(WDT + 0x4, 10);
+    assert_eq!(soc.bus_read_u32(WDT + 0x8), 10);
+    soc.bus_write_u32(WDT + 0xc, 0x1acc_e551);
+    soc.bus_write_u32(WDT, 1);
+    soc.run(5);
+    assert_eq!(soc.bus_read_u32(WDT + 0x10), 0);
+    soc.run(10);
+    assert_eq!(soc.bus_read_u32(WDT + 0x10), 1);
+    soc.bus_write_u32(WDT + 0x10, 1);
+    assert_eq!(soc.bus_read_u32(WDT + 0x10), 0);
+}
+
+#[test]
+fn watchdog_reset_keeps_the_ram() {
+    //count the boots at 0x3000.
+    let mut a = Asm::new();
+    a.li(T2, 0x3000);
+    a.lw(T0, T2, 0);
+    a.addi(T0, T0, 1);
+    a.sw(T0, T2, 0);
+    a.label("end");
+    a.j("end");
+
+    let cfg = board(&[(100.0, 0)], json!([watchdog(json!({"action": "reset", "timeout": 100, "enable": true}))]));
+    let mut soc = soc(cfg, a);
+    let resets = std::rc::Rc::new(std::cell::Cell::new(0));
+    let r = resets.clone();
+    soc.add_hook(move |e| {
+        if let SocEvent::Reset { keep_ram, .. } = e {
+            assert!(*keep_ram);
+            r.set(r.get() + 1);
+        }
+    });
+    soc.run(1000);
+    assert!(resets.get() >= 5);
+    assert_eq!(soc.read_mem_u32(0x3000), Some(resets.get() + 1));
+}
+
+const UART: u32 = 0x3_5000;
+
+#[cfg(unix)]
+#[test]
+fn uart_over_a_unix_socket() {
+    //say hi, then echo each byte plus one.
+    let mut a = Asm::new();
+    a.li(T1, UART);
+    a.li(T0, b'h' as u32);
+    a.sb(T0, T1, 0);
+    a.li(T0, b'i' as u32);
+    a.sb(T0, T1, 0);
+    a.label("poll");
+    a.lw(T0, T1, 5);
+    a.andi(T0, T0, 1);
+    a.beq(T0, ZERO, "poll");
+    a.lw(T0, T1, 0);
+    a.addi(T0, T0, 1);
+    a.sb(T0, T1, 0);
+    a.j("poll");
+
+    let path = std::env::temp_dir().join(format!("zemulator-uart-{}.sock", std::process::id()));
+    let uart = json!({"name": "uart0", "class": "uart16550", "start": UART, "size": 8,
+                      "chardev": format!("unix:{}", path.to_str().unwrap())});
+    let mut soc = soc(board(&[(100.0, 0)], json!([uart])), a);
+    let mut client = std::os::unix::net::UnixStream::connect(&path).unwrap();
+    client.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+    soc.run(100);
+    std::io::Write::write_all(&mut client, b"abc").unwrap();
+    soc.run(1000);
+    drop(soc);
+    let _ = std::fs::remove_file(&path);
+
+    let mut out = Vec::new();
+    std::io::Read::read_to_end(&mut client, &mut out).unwrap();
+    assert_eq!(out, b"hibcd");
+}
+
+const FB: u32 = 0x3_6000;
+
+#[test]
+fn framebuffer_dumps_a_ppm() {
+    let dir = std::env::temp_dir().join(format!("zemulator-fb-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = format!("{}/frame", dir.to_str().unwrap());
+    let fb = json!({"name": "fb0", "class": "framebuffer", "start": FB, "size": 0x1c,
+                    "width": 2, "height": 2, "format": "rgb565", "base": 0x2000, "out": out});
+    let mut soc = soc(board(&[(100.0, 0)], json!([fb])), idle());
+    //red, green on the first line, blue, white on the second 16 bytes on.
+    assert!(soc.write_mem(0x2000, &[0x00, 0xf8, 0xe0, 0x07]));
+    assert!(soc.write_mem(0x2010, &[0x1f, 0x00, 0xff, 0xff]));
+    //no frame while disabled.
+    soc.bus_write_u32(FB + 0x18, 1);
+    soc.bus_write_u32(FB + 0x14, 16);
+    soc.bus_write_u32(FB, 1);
+    soc.bus_write_u32(FB + 0x18, 1);
+    let frames = soc.bus_read_u32(FB + 0x18);
+
+    let ppm = std::fs::read(dir.join("frame0000.ppm"));
+    let second = dir.join("frame0001.ppm").exists();
+    let _ = std::fs::remove_dir_all(&dir);
+    assert_eq!(frames, 1);
+    assert!(!second);
+    let mut expect = b"P6\n2 2\n255\n".to_vec();
+    expect.extend([255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 255, 255]);
+    assert_eq!(ppm.unwrap(), expect);
+}
+
+const DMA: u32 = 0x3_7000;
+
+#[test]
+fn dma_copies_a_chain_and_reports_errors() {
+    let dma = json!({"name": "dma0", "class": "dma", "start": DMA, "size": 0xa0, "irq": 10, "bytes_per_tick": 4});
+    let mut soc = soc(board(&[(100.0, 0)], json!([dma])), idle());
+    let src: Vec<u8> = (1..=24).collect();
+    assert!(soc.write_mem(0x2000, &src));
+    //the second block from the descriptor at 0x2200, words with both increments.
+    let desc: Vec<u8> = [0x27, 0x2010, 0x2180, 8, 0].iter().flat_map(|w: &u32| w.to_le_bytes()).collect();
+    assert!(soc.write_mem(0x2200, &desc));
+    soc.bus_write_u32(DMA + 0x24, 0x2000);
+    soc.bus_write_u32(DMA + 0x28, 0x2100);
+    soc.bus_write_u32(DMA + 0x2c, 16);
+    soc.bus_write_u32(DMA + 0x30, 0x2200);
+    soc.bus_write_u32(DMA + 0x20, 0x27);
+    soc.run(2);
+    assert_eq!(soc.bus_read_u32(DMA + 0x34), 1);
+    soc.run(10);
+    assert_eq!(soc.bus_read_u32(DMA + 0x34), 2);
+    assert_eq!(soc.bus_read_u32(DMA), 1);
+
+    let mut dst = [0u8; 16];
+    assert!(soc.read_mem(0x2100, &mut dst));
+    assert_eq!(dst[..], src[..16]);
+    let mut dst = [0u8; 8];
+    assert!(soc.read_mem(0x2180, &mut dst));
+    assert_eq!(dst[..], src[16..]);
+
+    //a count that is not a multiple of the width, a source outside the mems.
+    soc.bus_write_u32(DMA + 0x4c, 6);
+    soc.bus_write_u32(DMA + 0x40, 0x27);
+    soc.bus_write_u32(DMA + 0x64, 0x8000_0000);
+    soc.bus_write_u32(DMA + 0x68, 0x2100);
+    soc.bus_write_u32(DMA + 0x6c, 4);
+    soc.bus_write_u32(DMA + 0x60, 0x27);
+    soc.run(2);
+    assert_eq!(soc.bus_read_u32(DMA + 0x54), 4);
+    assert_eq!(soc.bus_read_u32(DMA + 0x74), 4);
+    assert_eq!(soc.bus_read_u32(DMA), 0x6_0001);
+    soc.bus_write_u32(DMA, 0x6_0001);
+    assert_eq!(soc.bus_read_u32(DMA), 0);
+}
+
+const I2C: u32 = 0x3_8000;
+
+//an eeprom at 0x50 and a sensor at 0x48 going from 25 to 30 at tick 5000.
+fn i2c(reg_shift: u32) -> serde_json::Value {
+    json!({"name": "i2c0", "class": "i2c", "start": I2C, "size": 5 << reg_shift, "irq": 7, "reg_shift": reg_shift,
+           "slaves": [{"class": "24cxx", "addr": 0x50, "size": 256, "page": 8},
+                      {"class": "sensor", "addr": 0x48, "regs": {"0": 25, "1": 7},
+                       "script": [{"tick": 5000, "reg": 0, "value": 30}]}]})
+}
+
+//one byte on the bus, returns RXR and SR.
+fn i2c_byte(soc: &mut Rv32Actor, txr: u8, cr: u8) -> (u8, u8) {
+    soc.bus_write_u32(I2C + 12, txr as u32);
+    soc.bus_write_u32(I2C + 16, cr as u32);
+    for _ in 0..1000 {
+        if soc.bus_read_u32(I2C + 16) & 0x02 == 0 {
+            break;
+        }
+        soc.run(1);
+    }
+    (soc.bus_read_u32(I2C + 12) as u8, soc.bus_read_u32(I2C + 16) as u8)
+}
+
+const STA_WR: u8 = 0x90;
+const WR: u8 = 0x10;
+const WR_STO: u8 = 0x50;
+const RD: u8 = 0x20;
+const RD_NACK_STO: u8 = 0x68;
+
+//read `n` bytes of `dev` from `reg`.
+fn i2c_read(soc: &mut Rv32Actor, dev: u8, reg: u8, n: usize) -> Vec<u8> {
+    assert_eq!(i2c_byte(soc, dev << 1, STA_WR).1 & 0x80, 0);
+    i2c_byte(soc, reg, WR);
+    i2c_byte(soc, (dev << 1) | 1, STA_WR);
+    (0..n).map(|i| i2c_byte(soc, 0, if i + 1 == n { RD_NACK_STO } else { RD }).0).collect()
+}
+
+fn i2c_soc(reg_shift: u32) -> Rv32Actor {
+    let mut soc = soc(board(&[(100.0, 0)], json!([i2c(reg_shift)])), idle());
+    soc.bus_write_u32(I2C, 0);
+    soc.bus_write_u32(I2C + (1 << reg_shift), 0);
+    soc.bus_write_u32(I2C + (2 << reg_shift), 0x80);
+    soc
+}
+
+#[test]
+fn i2c_eeprom_page_write_and_read() {
+    let mut soc = i2c_soc(2);
+    //no device at 0x51.
+    assert_eq!(i2c_byte(&mut soc, 0xa2, STA_WR).1 & 0x80, 0x80);
+    //3 bytes from 0x0e wrap inside the 8 bytes page.
+    assert_eq!(i2c_byte(&mut soc, 0xa0, STA_WR).1 & 0xc0, 0x40);
+    for (b, cr) in [(0x0e, WR), (0x11, WR), (0x22, WR), (0x33, WR_STO)] {
+        assert_eq!(i2c_byte(&mut soc, b, cr).1 & 0x80, 0);
+    }
+    assert_eq!(soc.bus_read_u32(I2C + 16) & 0x40, 0);
+    assert_eq!(i2c_read(&mut soc, 0x50, 0x0e, 3), [0x11, 0x22, 0xff]);
+    assert_eq!(i2c_read(&mut soc, 0x50, 0x08, 1), [0x33]);
+}
+
+#[test]
+fn i2c_sensor_follows_its_script() {
+    let mut soc = i2c_soc(2);
+    assert_eq!(i2c_read(&mut soc, 0x48, 0, 2), [25, 7]);
+    soc.run(5000);
+    assert_eq!(i2c_read(&mut soc, 0x48, 0, 2), [30, 7]);
+}
+
+#[test]
+fn i2c_wide_stores_with_packed_registers() {
+    //with reg_shift 0 a word store writes PRERlo, PRERhi, CTR and TXR.
+    let mut a = Asm::new();
+    a.li(T1, I2C);
+    a.li(T0, 0x0102);
+    a.sh(T0, T1, 0);
+    a.lw(S2, T1, 0);
+    a.lw(S3, T1, 1);
+    a.li(T0, 0xa080_0000);
+    a.sw(T0, T1, 0);
+    a.lw(S4, T1, 0);
+    a.lw(S5, T1, 2);
+    a.li(T0, STA_WR as u32);
+    a.sb(T0, T1, 4);
+    a.label("end");
+    a.j("end");
+
+    let mut soc = soc(board(&[(100.0, 0)], json!([i2c(0)])), a);
+    soc.run(200);
+    assert_eq!((soc.get_rs(S2), soc.get_rs(S3)), (0x02, 0x01));
+    assert_eq!((soc.get_rs(S4), soc.get_rs(S5)), (0x00, 0x80));
+    //the eeprom acked the address from TXR.
+    assert_eq!(soc.bus_read_u32(I2C + 4) & 0xc2, 0x40);
+}
+
+const RTC: u32 = 0x3_9000;
+
+#[test]
+fn rtc_alarm_interrupts_at_its_second() {
+    //wait for the alarm through the plic, the handler keeps SECONDS, DATE
+    //and TIME then clears the alarm.
+    let mut a = Asm::new();
+    a.li(T0, 0x100);
+    a.csrw(MTVEC, T0);
+    a.li(T0, 0x800);
+    a.csrw(MIE, T0);
+    a.csrsi(MSTATUS, 8);
+    a.label("wait");
+    a.wfi();
+    a.j("wait");
+    a.org(0x100);
+    a.li(T1, PLIC + 0x200004);
+    a.lw(S5, T1, 0);
+    a.li(T2, RTC);
+    a.lw(S2, T2, 0x4);
+    a.lw(S3, T2, 0xc);
+    a.lw(S6, T2, 0x10);
+    a.li(T0, 1);
+    a.sw(T0, T2, 0x18);
+    a.sw(S5, T1, 0);
+    a.addi(S4, S4, 1);
+    a.mret();
+
+    //2023-11-14 22:13:20, a tuesday.
+    let epoch = 1_700_000_000;
+    let rtc = json!({"name": "rtc0", "class": "rtc", "start": RTC, "size": 0x1c, "irq": 5, "freq": 0.001, "epoch": epoch});
+    let mut soc = soc(board(&[(1.0, 0)], json!([plic(), rtc])), a);
+    soc.bus_write_u32(PLIC + 4 * 5, 1);
+    soc.bus_write_u32(PLIC + 0x2000, 1 << 5);
+    soc.bus_write_u32(RTC + 0x14, epoch + 3);
+    soc.bus_write_u32(RTC, 3);
+    soc.run(2_900_000);
+    assert_eq!(soc.get_rs(S4), 0);
+    soc.run(200_000);
+    assert_eq!(soc.get_rs(S4), 1);
+    assert_eq!(soc.get_rs(S5), 5);
+    assert_eq!(soc.get_rs(S2), epoch + 3);
+    assert_eq!(soc.get_rs(S3), (2023 << 16) | (11 << 8) | 14);
+    assert_eq!(soc.get_rs(S6), (2 << 24) | (22 << 16) | (13 << 8) | 23);
+    assert_eq!(soc.bus_read_u32(RTC + 0x18), 0);
+    //the alarm fires once.
+    soc.run(5_000_000);
+    assert_eq!(soc.get_rs(S4), 1);
+}
+
+const TIMER: u32 = 0x3_a000;
+
+//counts 0..=9 every 2 ticks, pwm 0 high below 3, compare 1 at 5.
+fn pwm_soc(prog: Asm) -> Rv32Actor {
+    let timer = json!({"name": "timer0", "class": "timer", "start": TIMER, "size": 0x40, "irq": 1});
+    let mut soc = soc(board(&[(100.0, 0)], json!([timer])), prog);
+    soc.bus_write_u32(TIMER + 0x04, 1);
+    soc.bus_write_u32(TIMER + 0x0c, 9);
+    soc.bus_write_u32(TIMER + 0x20, 3);
+    soc.bus_write_u32(TIMER + 0x24, 2);
+    soc.bus_write_u32(TIMER + 0x28, 5);
+    soc.bus_write_u32(TIMER + 0x2c, 1);
+    soc.bus_write_u32(TIMER, 1);
+    soc
+}
+
+#[test]
+fn timer_pwm_duty_and_compare() {
+    let mut soc = pwm_soc(idle());
+    let mut high = 0;
+    for _ in 0..60 {
+        soc.run(1);
+        high += soc.get_pin("timer0", 0).unwrap() as u32;
+    }
+    assert_eq!(high, 18);
+    assert_eq!(soc.bus_read_u32(TIMER + 0x10), 0b101);
+    soc.bus_write_u32(TIMER + 0x10, 0b101);
+    assert_eq!(soc.bus_read_u32(TIMER + 0x10), 0);
+
+    //one-shot stops at the overflow.
+    soc.bus_write_u32(TIMER + 0x08, 0);
+    soc.bus_write_u32(TIMER, 3);
+    soc.run(100);
+    assert_eq!(soc.bus_read_u32(TIMER), 2);
+    assert_eq!(soc.bus_read_u32(TIMER + 0x08), 0);
+}
+
+#[test]
+fn timer_pwm_edges_survive_the_fast_forward() {
+    //the cpu sleeps, the ticks between the edges are skipped.
+    let mut a = Asm::new();
+    a.label("wait");
+    a.wfi();
+    a.j("wait");
+    let mut soc = pwm_soc(a);
+    let edges = std::rc::Rc::new(std::cell::Cell::new(0));
+    let e = edges.clone();
+    soc.add_hook(move |ev| {
+        if let SocEvent::Pin { perip, pin: 0, level: true } = ev {
+            assert_eq!(perip, "timer0");
+            e.set(e.get() + 1);
+        }
+    });
+    let mut stops = 0;
+    soc.run_until(1000, |_| {
+        stops += 1;
+        false
+    });
+    assert_eq!(edges.get(), 50);
+    assert!(stops < 500, "{stops} ticks were not skipped");
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use zemulator::rv32_actor::Rv32Actor;
+
+const CLAIM: u32 = PLIC + 0x200004;
+const THRESHOLD: u32 = PLIC + 0x200000;
+const GPIO_RISE_IE: u32 = 0x18;
+const GPIO_IP: u32 = 0x28;
+
+//gpio_a on source 2 at priority 1, gpio_b on source 3 at priority 5, both
+//enabled for the m mode of hart 0 and interrupting on a rising pin 0.
+fn plic_soc(prog: Asm) -> Rv32Actor {
+    let mut soc = soc(board(&[(100.0, 0)], json!([plic(), gpio("gpio_a", GPIO_A, 2), gpio("gpio_b", GPIO_B, 3)])), prog);
+    soc.bus_write_u32(PLIC + 4 * 2, 1);
+    soc.bus_write_u32(PLIC + 4 * 3, 5);
+    soc.bus_write_u32(PLIC + 0x2000, (1 << 2) | (1 << 3));
+    soc.bus_write_u32(GPIO_A + GPIO_RISE_IE, 1);
+    soc.bus_write_u32(GPIO_B + GPIO_RISE_IE, 1);
+    soc
+}
+
+fn idle() -> Asm {
+    let mut a = Asm::new();
+    a.label("end");
+    a.j("end");
+    a
+}
+
+#[test]
+fn claim_takes_the_highest_priority_and_complete_rearms() {
+    let mut soc = plic_soc(idle());
+    soc.set_pin("gpio_a", 0, true);
+    soc.set_pin("gpio_b", 0, true);
+    soc.run(1);
+    assert_eq!(soc.cpu(0).unwrap().read_csr(MIP) & 0x800, 0x800);
+
+    assert_eq!(soc.bus_read_u32(CLAIM), 3);
+    assert_eq!(soc.bus_read_u32(CLAIM), 2);
+    assert_eq!(soc.bus_read_u32(CLAIM), 0);
+    soc.run(1);
+    assert_eq!(soc.cpu(0).unwrap().read_csr(MIP) & 0x800, 0);
+
+    //the line of source 3 is still up, it is pending again once completed.
+    soc.bus_write_u32(CLAIM, 3);
+    assert_eq!(soc.bus_read_u32(CLAIM), 3);
+    soc.bus_write_u32(GPIO_B + GPIO_IP, 1);
+    soc.bus_write_u32(CLAIM, 3);
+    assert_eq!(soc.bus_read_u32(CLAIM), 0);
+}
+
+#[test]
+fn threshold_masks_the_lower_priorities() {
+    let mut soc = plic_soc(idle());
+    soc.bus_write_u32(THRESHOLD, 1);
+    soc.set_pin("gpio_a", 0, true);
+    soc.run(1);
+    assert_eq!(soc.cpu(0).unwrap().read_csr(MIP) & 0x800, 0);
+    assert_eq!(soc.bus_read_u32(CLAIM), 0);
+
+    soc.set_pin("gpio_b", 0, true);
+    soc.run(1);
+    assert_eq!(soc.bus_read_u32(CLAIM), 3);
+    soc.bus_write_u32(THRESHOLD, 0);
+    assert_eq!(soc.bus_read_u32(CLAIM), 2);
+}
+
+#[test]
+fn handler_claims_in_priority_order() {
+    let mut a = Asm::new();
+    a.li(T0, 0x100);
+    a.csrw(MTVEC, T0);
+    a.li(T0, 0x800);
+    a.csrw(MIE, T0);
+    a.csrsi(MSTATUS, 8);
+    a.label("end");
+    a.j("end");
+    //log the claimed ids in s2, clear the gpio of the source and complete it.
+    a.org(0x100);
+    a.csrr(S4, MCAUSE);
+    a.li(T1, CLAIM);
+    a.lw(S5, T1, 0);
+    a.slli(S2, S2, 8);
+    a.add(S2, S2, S5);
+    a.li(T0, GPIO_A + GPIO_IP);
+    a.li(T2, 2);
+    a.beq(S5, T2, "clear");
+    a.li(T0, GPIO_B + GPIO_IP);
+    a.label("clear");
+    a.li(T2, 1);
+    a.sw(T2, T0, 0);
+    a.sw(S5, T1, 0);
+    a.addi(S3, S3, 1);
+    a.mret();
+
+    let mut soc = plic_soc(a);
+    soc.run(20);
+    soc.set_pin("gpio_a", 0, true);
+    soc.set_pin("gpio_b", 0, true);
+    soc.run(200);
+    assert_eq!(soc.get_rs(S4), 0x8000_000b);
+    assert_eq!(soc.get_rs(S3), 2);
+    assert_eq!(soc.get_rs(S2), 0x0302);
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+const SYS_OPEN: u32 = 0x01;
+const SYS_CLOSE: u32 = 0x02;
+const SYS_WRITE: u32 = 0x05;
+
+const PARAM: u32 = 0x1000;
+const NAME: u32 = 0x1100;
+const DATA: u32 = 0x1200;
+
+fn call(a: &mut Asm, op: u32, params: &[Option<u32>]) {
+    a.li(A1, PARAM);
+    for (i, p) in params.iter().enumerate() {
+        //None is the handle from the open.
+        match p {
+            Some(v) => {
+                a.li(T0, *v);
+                a.sw(T0, A1, 4 * i as i32);
+            },
+            None => a.sw(S2, A1, 4 * i as i32),
+        }
+    }
+    a.li(A0, op);
+    a.semihost();
+}
+
+#[test]
+fn open_write_close() {
+    let path = std::env::temp_dir().join(format!("zemulator-semihost-{}.txt", std::process::id()));
+    let name = path.to_str().unwrap();
+    let data = b"hello semihosting\n";
+
+    let mut a = Asm::new();
+    //mode 4 is "w".
+    call(&mut a, SYS_OPEN, &[Some(NAME), Some(4), Some(name.len() as u32)]);
+    a.addi(S2, A0, 0);
+    call(&mut a, SYS_WRITE, &[None, Some(DATA), Some(data.len() as u32)]);
+    a.addi(S3, A0, 0);
+    call(&mut a, SYS_CLOSE, &[None]);
+    a.addi(S4, A0, 0);
+    call(&mut a, SYS_CLOSE, &[None]);
+    a.addi(S5, A0, 0);
+    a.label("end");
+    a.j("end");
+
+    let mut cfg = board(&[(100.0, 0)], json!([]));
+    cfg["semihosting"] = json!(true);
+    let mut soc = soc(cfg, a);
+    assert!(soc.write_mem(NAME, name.as_bytes()));
+    assert!(soc.write_mem(DATA, data));
+    soc.run(200);
+
+    let written = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(soc.get_rs(S2), 1);
+    //the bytes not written.
+    assert_eq!(soc.get_rs(S3), 0);
+    assert_eq!(soc.get_rs(S4), 0);
+    assert_eq!(soc.get_rs(S5), u32::MAX);
+    assert_eq!(written.unwrap(), data);
+}
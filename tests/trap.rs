@@ -0,0 +1,59 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+
+const LOG: u32 = 0x1000;
+const MCYCLE: u32 = 0xb00;
+
+#[test]
+fn illegal_instruction_traps_before_the_pending_interrupt() {
+    let mut a = Asm::new();
+    a.li(T5, LOG);
+    a.li(T0, 0x100);
+    a.csrw(MTVEC, T0);
+    //mtip is pending at once, mstatus.MIE stays off until the handler.
+    a.li(T1, CLINT + 0x4000);
+    a.sw(ZERO, T1, 0);
+    a.sw(ZERO, T1, 4);
+    a.li(T0, 0x80);
+    a.csrw(MIE, T0);
+    a.nop();
+    a.label("bad");
+    a.word(0xffff_ffff);
+    //no such csr.
+    a.csrr(S5, MCYCLE);
+    a.li(S6, 1);
+    a.label("end");
+    a.j("end");
+    //log mcause, mtval and mepc, skip an illegal instruction with the
+    //interrupts on after the mret, mask the timer once it is taken.
+    a.org(0x100);
+    a.csrr(T3, MCAUSE);
+    a.sw(T3, T5, 0);
+    a.csrr(T4, MTVAL);
+    a.sw(T4, T5, 4);
+    a.csrr(T4, MEPC);
+    a.sw(T4, T5, 8);
+    a.addi(T5, T5, 12);
+    a.li(T4, 2);
+    a.bne(T3, T4, "intr");
+    a.csrr(T4, MEPC);
+    a.addi(T4, T4, 4);
+    a.csrw(MEPC, T4);
+    a.li(T4, 0x80);
+    a.csrs(MSTATUS, T4);
+    a.mret();
+    a.label("intr");
+    a.csrw(MIE, ZERO);
+    a.mret();
+
+    let bad = a.addr("bad");
+    let mut soc = soc(board(&[(100.0, 0)], json!([clint()])), a);
+    soc.run(300);
+    let log: Vec<u32> = (0..9).map(|i| soc.read_mem_u32(LOG + 4 * i).unwrap()).collect();
+    let csrr_mcycle = (MCYCLE << 20) | (2 << 12) | (S5 << 7) | 0x73;
+    assert_eq!(log, [2, 0xffff_ffff, bad, 0x8000_0007, 0, bad + 4, 2, csrr_mcycle, bad + 4]);
+    assert_eq!(soc.get_rs(S5), 0);
+    assert_eq!(soc.get_rs(S6), 1);
+}
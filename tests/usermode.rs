@@ -0,0 +1,93 @@
+mod common;
+
+use common::*;
+use zemulator::config::{CSoc, SocBuilder};
+use zemulator::rv32_actor::{Rv32Actor, StopReason};
+
+const SYS_OPENAT: u32 = 56;
+const SYS_WRITE: u32 = 64;
+const SYS_EXIT: u32 = 93;
+const SYS_GETTIMEOFDAY: u32 = 169;
+
+const AT_FDCWD: u32 = -100i32 as u32;
+const O_WRONLY_CREAT_TRUNC: u32 = 0o1101;
+
+const ENTRY: u32 = 0x1_0000;
+const PATH: u32 = 0x2_0000;
+const REL: u32 = 0x2_0100;
+const DATA: u32 = 0x2_0200;
+const TV: u32 = 0x2_0300;
+
+fn syscall(a: &mut Asm, n: u32, args: &[u32]) {
+    for (r, v) in [A0, A1, A2, A3].into_iter().zip(args) {
+        a.li(r, *v);
+    }
+    a.li(A7, n);
+    a.ecall();
+}
+
+fn user_soc(prog: Asm, name: &str) -> Rv32Actor {
+    let path = std::env::temp_dir().join(format!("zemulator-user-{name}-{}.elf", std::process::id()));
+    std::fs::write(&path, elf(ENTRY, &prog.finish())).unwrap();
+    let mut soc = SocBuilder::new().build_cfg(CSoc::user_mode());
+    let res = soc.load_user_program(path.to_str().unwrap(), &[name.to_owned()], &[]);
+    let _ = std::fs::remove_file(&path);
+    res.unwrap();
+    soc
+}
+
+#[test]
+fn open_write_and_exit() {
+    let out = std::env::temp_dir().join(format!("zemulator-user-out-{}.txt", std::process::id()));
+    let name = out.to_str().unwrap();
+    let data = b"hello user mode\n";
+
+    let mut a = Asm::new();
+    syscall(&mut a, SYS_OPENAT, &[AT_FDCWD, PATH, O_WRONLY_CREAT_TRUNC, 0o644]);
+    a.addi(S2, A0, 0);
+    a.addi(A0, S2, 0);
+    a.li(A1, DATA);
+    a.li(A2, data.len() as u32);
+    a.li(A7, SYS_WRITE);
+    a.ecall();
+    a.addi(S3, A0, 0);
+    //no directory fds, a relative path from an open file or a bad fd fails.
+    a.addi(A0, S2, 0);
+    a.li(A1, REL);
+    a.li(A2, 0);
+    a.li(A7, SYS_OPENAT);
+    a.ecall();
+    a.addi(S4, A0, 0);
+    syscall(&mut a, SYS_OPENAT, &[77, REL, 0]);
+    a.addi(S5, A0, 0);
+    //the 32 bit clock_gettime is not a rv32 syscall.
+    syscall(&mut a, 113, &[0, TV]);
+    a.addi(S6, A0, 0);
+    syscall(&mut a, SYS_GETTIMEOFDAY, &[TV, 0]);
+    a.addi(S7, A0, 0);
+    syscall(&mut a, SYS_EXIT, &[7]);
+
+    let mut soc = user_soc(a, "write");
+    let mut path = name.as_bytes().to_vec();
+    path.push(0);
+    assert!(soc.write_mem(PATH, &path));
+    assert!(soc.write_mem(REL, b"rel.txt\0"));
+    assert!(soc.write_mem(DATA, data));
+    let stop = soc.run(10_000);
+
+    let written = std::fs::read(&out);
+    let _ = std::fs::remove_file(&out);
+    assert_eq!(stop, StopReason::Halt { code: 7 });
+    assert_eq!(soc.get_rs(S2), 3);
+    assert_eq!(soc.get_rs(S3), data.len() as u32);
+    assert_eq!(written.unwrap(), data);
+    assert_eq!(soc.get_rs(S4) as i32, -95);
+    assert_eq!(soc.get_rs(S5) as i32, -9);
+    assert_eq!(soc.get_rs(S6) as i32, -38);
+    assert_eq!(soc.get_rs(S7), 0);
+
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    let sec = soc.read_mem_u32(TV).unwrap() as u64 | (soc.read_mem_u32(TV + 4).unwrap() as u64) << 32;
+    assert!(now - sec < 10, "{sec} is not now");
+    assert!(soc.read_mem_u32(TV + 8).unwrap() < 1_000_000);
+}
@@ -0,0 +1,243 @@
+mod common;
+
+use common::*;
+use serde_json::{json, Value};
+use zemulator::config::{CSoc, SocBuilder};
+use zemulator::perips::virtio::{VirtioDevice, VirtioMmio, Virtqueue};
+use zemulator::perips::PeripCtx;
+use zemulator::rv32_actor::Rv32Actor;
+
+const VIRTIO: u32 = 0x1000_1000;
+const QUEUE_SEL: u32 = 0x030;
+const QUEUE_NUM: u32 = 0x038;
+const QUEUE_READY: u32 = 0x044;
+const QUEUE_NOTIFY: u32 = 0x050;
+const INTERRUPT_STATUS: u32 = 0x060;
+const STATUS: u32 = 0x070;
+const QUEUE_DESC_LOW: u32 = 0x080;
+const QUEUE_DRIVER_LOW: u32 = 0x090;
+const QUEUE_DEVICE_LOW: u32 = 0x0a0;
+const CONFIG: u32 = 0x100;
+
+//the rings of the queue in ram, the buffers from BUFS on.
+const DESC: u32 = 0x1000;
+const AVAIL: u32 = 0x1100;
+const USED: u32 = 0x1200;
+const BUFS: u32 = 0x2000;
+const NUM: u32 = 8;
+
+const F_NEXT: u16 = 1;
+const F_WRITE: u16 = 2;
+
+fn virtio(args: Value) -> Value {
+    let mut v = json!({"name": "virtio0", "class": "virtio_mmio", "start": VIRTIO, "size": 0x200, "irq": 8});
+    v.as_object_mut().unwrap().extend(args.as_object().unwrap().clone());
+    v
+}
+
+fn idle() -> Asm {
+    let mut a = Asm::new();
+    a.label("end");
+    a.j("end");
+    a
+}
+
+//the driver side, on the host.
+struct Driver {
+    avail_idx: u16,
+}
+
+impl Driver {
+    fn new(soc: &mut Rv32Actor, queue: u32) -> Self {
+        soc.bus_write_u32(VIRTIO + STATUS, 0x0b);
+        soc.bus_write_u32(VIRTIO + QUEUE_SEL, queue);
+        soc.bus_write_u32(VIRTIO + QUEUE_NUM, NUM);
+        soc.bus_write_u32(VIRTIO + QUEUE_DESC_LOW, DESC);
+        soc.bus_write_u32(VIRTIO + QUEUE_DRIVER_LOW, AVAIL);
+        soc.bus_write_u32(VIRTIO + QUEUE_DEVICE_LOW, USED);
+        soc.bus_write_u32(VIRTIO + QUEUE_READY, 1);
+        soc.bus_write_u32(VIRTIO + STATUS, 0x0f);
+        Driver { avail_idx: 0 }
+    }
+
+    fn desc(soc: &mut Rv32Actor, i: u32, addr: u32, len: u32, flags: u16, next: u16) {
+        let mut d = Vec::new();
+        d.extend((addr as u64).to_le_bytes());
+        d.extend(len.to_le_bytes());
+        d.extend(flags.to_le_bytes());
+        d.extend(next.to_le_bytes());
+        assert!(soc.write_mem(DESC + 16 * i, &d));
+    }
+
+    //make the chain of `head` available and notify `queue`.
+    fn submit(&mut self, soc: &mut Rv32Actor, queue: u32, head: u16) {
+        let slot = AVAIL + 4 + 2 * (self.avail_idx as u32 % NUM);
+        assert!(soc.write_mem(slot, &head.to_le_bytes()));
+        self.avail_idx += 1;
+        assert!(soc.write_mem(AVAIL + 2, &self.avail_idx.to_le_bytes()));
+        soc.bus_write_u32(VIRTIO + QUEUE_NOTIFY, queue);
+    }
+
+    //the id and len of the used element `n`.
+    fn used(soc: &Rv32Actor, n: u32) -> (u32, u32) {
+        let elem = USED + 4 + 8 * (n % NUM);
+        (soc.read_mem_u32(elem).unwrap(), soc.read_mem_u32(elem + 4).unwrap())
+    }
+
+    fn used_idx(soc: &Rv32Actor) -> u32 {
+        soc.read_mem_u32(USED).unwrap() >> 16
+    }
+}
+
+fn blk_request(soc: &mut Rv32Actor, kind: u32, sector: u64) {
+    let mut hdr = Vec::new();
+    hdr.extend(kind.to_le_bytes());
+    hdr.extend(0u32.to_le_bytes());
+    hdr.extend(sector.to_le_bytes());
+    assert!(soc.write_mem(BUFS, &hdr));
+    Driver::desc(soc, 0, BUFS, 16, F_NEXT, 1);
+}
+
+fn read_bytes(soc: &Rv32Actor, addr: u32, len: usize) -> Vec<u8> {
+    let mut buf = vec![0u8; len];
+    assert!(soc.read_mem(addr, &mut buf));
+    buf
+}
+
+#[test]
+fn blk_write_then_read() {
+    let path = std::env::temp_dir().join(format!("zemulator-blk-{}.img", std::process::id()));
+    std::fs::write(&path, vec![0u8; 4 * 512]).unwrap();
+    let cfg = board(&[(100.0, 0)], json!([virtio(json!({"device": "blk", "image": path.to_str().unwrap()}))]));
+    let mut soc = soc(cfg, idle());
+    let mut drv = Driver::new(&mut soc, 0);
+    let sector: Vec<u8> = (0..512).map(|i| (i * 7) as u8).collect();
+
+    //header, data, status.
+    blk_request(&mut soc, 1, 1);
+    assert!(soc.write_mem(BUFS + 0x100, &sector));
+    Driver::desc(&mut soc, 1, BUFS + 0x100, 512, F_NEXT, 2);
+    Driver::desc(&mut soc, 2, BUFS + 0x400, 1, F_WRITE, 0);
+    soc.write_mem(BUFS + 0x400, &[0xff]);
+    drv.submit(&mut soc, 0, 0);
+    assert_eq!(Driver::used_idx(&soc), 1);
+    assert_eq!(Driver::used(&soc, 0), (0, 1));
+    assert_eq!(read_bytes(&soc, BUFS + 0x400, 1), [0]);
+    assert_eq!(soc.bus_read_u32(VIRTIO + INTERRUPT_STATUS), 1);
+
+    //read it back to another buffer.
+    blk_request(&mut soc, 0, 1);
+    Driver::desc(&mut soc, 1, BUFS + 0x800, 512, F_NEXT | F_WRITE, 2);
+    Driver::desc(&mut soc, 2, BUFS + 0x400, 1, F_WRITE, 0);
+    soc.write_mem(BUFS + 0x400, &[0xff]);
+    drv.submit(&mut soc, 0, 0);
+    assert_eq!(Driver::used(&soc, 1), (0, 513));
+    assert_eq!(read_bytes(&soc, BUFS + 0x800, 512), sector);
+    assert_eq!(read_bytes(&soc, BUFS + 0x400, 1), [0]);
+
+    //past the end of the disk.
+    blk_request(&mut soc, 0, 4);
+    drv.submit(&mut soc, 0, 0);
+    assert_eq!(read_bytes(&soc, BUFS + 0x400, 1), [1]);
+    drop(soc);
+
+    let disk = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(&disk.unwrap()[512..1024], &sector[..]);
+}
+
+#[test]
+fn console_transmit() {
+    let path = std::env::temp_dir().join(format!("zemulator-console-{}.txt", std::process::id()));
+    let chardev = format!("file:{}", path.to_str().unwrap());
+    let cfg = board(&[(100.0, 0)], json!([virtio(json!({"device": "console", "chardev": chardev}))]));
+    let mut soc = soc(cfg, idle());
+    let mut drv = Driver::new(&mut soc, 1);
+
+    assert!(soc.write_mem(BUFS, b"hello "));
+    assert!(soc.write_mem(BUFS + 0x10, b"virtio\n"));
+    Driver::desc(&mut soc, 3, BUFS, 6, F_NEXT, 4);
+    Driver::desc(&mut soc, 4, BUFS + 0x10, 7, 0, 0);
+    drv.submit(&mut soc, 1, 3);
+    assert_eq!(Driver::used_idx(&soc), 1);
+    assert_eq!(Driver::used(&soc, 0), (3, 0));
+    drop(soc);
+
+    let out = std::fs::read(&path);
+    let _ = std::fs::remove_file(&path);
+    assert_eq!(out.unwrap(), b"hello virtio\n");
+}
+
+#[test]
+fn ring_outside_the_mems_needs_a_reset() {
+    let cfg = board(&[(100.0, 0)], json!([virtio(json!({"device": "console", "chardev": "null"}))]));
+    let mut soc = soc(cfg, idle());
+    Driver::new(&mut soc, 1);
+    soc.bus_write_u32(VIRTIO + QUEUE_DRIVER_LOW, 0x8000_0000);
+    soc.bus_write_u32(VIRTIO + QUEUE_NOTIFY, 1);
+    assert_eq!(soc.bus_read_u32(VIRTIO + STATUS), 0x4f);
+    assert_eq!(soc.bus_read_u32(VIRTIO + INTERRUPT_STATUS), 2);
+
+    soc.bus_write_u32(VIRTIO + STATUS, 0);
+    assert_eq!(soc.bus_read_u32(VIRTIO + STATUS), 0);
+    assert_eq!(soc.bus_read_u32(VIRTIO + INTERRUPT_STATUS), 0);
+}
+
+//a device with 8 bytes of config space the driver writes.
+struct ConfigDev {
+    config: [u8; 8],
+}
+
+impl VirtioDevice for ConfigDev {
+    fn device_id(&self) -> u32 {
+        0x7f
+    }
+
+    fn queues(&self) -> usize {
+        1
+    }
+
+    fn read_config(&self, offset: u32) -> u8 {
+        self.config.get(offset as usize).copied().unwrap_or(0)
+    }
+
+    fn write_config(&mut self, offset: u32, data: u8) {
+        if let Some(c) = self.config.get_mut(offset as usize) {
+            *c = data;
+        }
+    }
+
+    fn notify(&mut self, _queue: usize, _vqs: &mut [Virtqueue], _ctx: &mut PeripCtx) -> bool {
+        false
+    }
+}
+
+#[test]
+fn sub_word_stores_to_config_and_registers() {
+    let mut a = Asm::new();
+    a.li(T1, VIRTIO);
+    a.li(T0, 0xbeef);
+    a.sh(T0, T1, (CONFIG + 2) as i32);
+    a.li(T0, 0x5a);
+    a.sb(T0, T1, (CONFIG + 5) as i32);
+    a.li(T0, 0x03);
+    a.sb(T0, T1, STATUS as i32);
+    a.label("end");
+    a.j("end");
+
+    let mut builder = SocBuilder::new();
+    builder.register_perip("config_dev", |cfg| {
+        let dev = ConfigDev { config: [0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88] };
+        Box::new(VirtioMmio::with_device(cfg, Some(Box::new(dev))))
+    });
+    let cfg: CSoc = serde_json::from_value(board(&[(100.0, 0)], json!([
+        {"name": "virtio0", "class": "config_dev", "start": VIRTIO, "size": 0x200, "irq": 8}
+    ]))).unwrap();
+    let mut soc = builder.build_cfg(cfg);
+    assert!(soc.load_image(0, &a.finish()));
+    soc.run(50);
+
+    assert_eq!(soc.bus_read_u32(VIRTIO + CONFIG), 0xbeef_2211);
+    assert_eq!(soc.bus_read_u32(VIRTIO + CONFIG + 4), 0x8877_5a55);
+    assert_eq!(soc.bus_read_u32(VIRTIO + STATUS), 0x03);
+}
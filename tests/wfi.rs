@@ -0,0 +1,83 @@
+mod common;
+
+use common::*;
+use serde_json::json;
+use zemulator::rv32_actor::Rv32Actor;
+
+//wait for the clint timer every 1000 mtime ticks, the handler counts the
+//interrupts in s2 and keeps the mtime it saw in s3.
+fn timer_loop() -> Asm {
+    let mut a = Asm::new();
+    a.li(T0, 0x100);
+    a.csrw(MTVEC, T0);
+    a.li(T1, CLINT + 0x4000);
+    a.li(T2, 1000);
+    a.sw(T2, T1, 0);
+    a.sw(ZERO, T1, 4);
+    a.li(T0, 0x80);
+    a.csrw(MIE, T0);
+    a.csrsi(MSTATUS, 8);
+    a.label("wait");
+    a.wfi();
+    a.addi(S4, S4, 1);
+    a.j("wait");
+    a.org(0x100);
+    a.addi(S2, S2, 1);
+    a.li(T0, CLINT + 0xbff8);
+    a.lw(S3, T0, 0);
+    a.lw(T2, T1, 0);
+    a.addi(T2, T2, 1000);
+    a.sw(T2, T1, 0);
+    a.mret();
+    a
+}
+
+fn run(fast_forward: bool) -> (Rv32Actor, u32) {
+    let mut soc = soc(board(&[(100.0, 0)], json!([clint()])), timer_loop());
+    soc.set_fast_forward(fast_forward);
+    let mut stops = 0;
+    soc.run_until(50_000, |_| {
+        stops += 1;
+        false
+    });
+    (soc, stops)
+}
+
+#[test]
+fn fast_forward_matches_the_ticked_run() {
+    let (ff, ff_stops) = run(true);
+    let (ticked, ticked_stops) = run(false);
+
+    assert_eq!(ticked_stops, 50_000);
+    assert!(ff_stops < 1000, "{ff_stops} ticks were not skipped");
+    assert_eq!(ff.get_tick(), ticked.get_tick());
+    assert_eq!(ff.cpu_cycles(0), ticked.cpu_cycles(0));
+    assert_eq!(ff.cpu(0).unwrap().get_pc(), ticked.cpu(0).unwrap().get_pc());
+    for i in 1..32 {
+        assert_eq!(ff.get_rs(i), ticked.get_rs(i), "x{i}");
+    }
+    assert_eq!(ticked.get_rs(S2), 4);
+    assert_eq!(ticked.get_rs(S4), 4);
+    assert_eq!(ticked.get_rs(S3), 4000);
+}
+
+#[test]
+fn wfi_wakes_on_a_pending_interrupt_with_mie_off() {
+    let mut a = Asm::new();
+    a.li(T1, CLINT + 0x4000);
+    a.li(T2, 100);
+    a.sw(T2, T1, 0);
+    a.sw(ZERO, T1, 4);
+    a.li(T0, 0x80);
+    a.csrw(MIE, T0);
+    a.wfi();
+    a.li(T0, CLINT + 0xbff8);
+    a.lw(S2, T0, 0);
+    a.label("end");
+    a.j("end");
+
+    let mut soc = soc(board(&[(100.0, 0)], json!([clint()])), a);
+    soc.run(5000);
+    assert_eq!(soc.get_rs(S2), 100);
+    assert_eq!(soc.cpu(0).unwrap().read_csr(MCAUSE), 0);
+}